@@ -34,6 +34,9 @@ fn create_mock_cell_dep(data: Bytes, lock: Option<Script>) -> (Byte32, MockCellD
             output: cell_output,
             data,
             header: None,
+            block_number: None,
+            epoch: None,
+            tx_index: None,
         },
     )
 }
@@ -73,11 +76,15 @@ pub fn test_bench() {
         output: input_dep.output,
         data: input_dep.data,
         header: None,
+        block_number: None,
+        epoch: None,
+        tx_index: None,
     };
     let mock_info = MockInfo {
         inputs: vec![mock_input],
         cell_deps: vec![data_dep, code_dep],
         header_deps: vec![],
+        files: Default::default(),
     };
     let mock_transaction = MockTransaction {
         mock_info,
@@ -5,33 +5,7 @@
 #include <stdio.h>
 #include <stdlib.h>
 
-#include "ckb_syscalls.h"
-
-void* fopen(const char* path, const char* mode) {
-    return (void*)syscall(9003, path, mode, 0, 0, 0, 0);
-}
-
-void* freopen(const char* path, const char* mode, void* stream) {
-    return (void*)syscall(9004, path, mode, stream, 0, 0, 0);
-}
-
-uint64_t fread(void* ptr, size_t size, size_t nitems, void* stream) {
-    return syscall(9005, ptr, size, nitems, stream, 0, 0);
-}
-
-int feof(void* stream) { return syscall(9006, stream, 0, 0, 0, 0, 0); }
-
-int ferror(void* stream) { return syscall(9007, stream, 0, 0, 0, 0, 0); }
-
-int fgetc(void* stream) { return syscall(9008, stream, 0, 0, 0, 0, 0); }
-
-int fclose(void* stream) { return syscall(9009, stream, 0, 0, 0, 0, 0); }
-
-long ftell(void* stream) { return syscall(9010, stream, 0, 0, 0, 0, 0); }
-
-int fseek(void* stream, long offset, int whence) {
-    return syscall(9011, stream, offset, whence, 0, 0, 0);
-}
+#include "file_operations.h"
 
 int main() {
     printf("Entering main");
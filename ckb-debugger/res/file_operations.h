@@ -0,0 +1,67 @@
+#ifndef CKB_DEBUGGER_FILE_OPERATIONS_H_
+#define CKB_DEBUGGER_FILE_OPERATIONS_H_
+
+// File syscalls served by ckb-debugger. Streams are opaque handles, 0 is the
+// null stream. Paths are confined to the directory given with --fs-root, if
+// any. Every call fails the way its C counterpart does.
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "ckb_syscalls.h"
+
+static inline void* fopen(const char* path, const char* mode) {
+    return (void*)syscall(9003, path, mode, 0, 0, 0, 0);
+}
+
+static inline void* freopen(const char* path, const char* mode, void* stream) {
+    return (void*)syscall(9004, path, mode, stream, 0, 0, 0);
+}
+
+static inline uint64_t fread(void* ptr, size_t size, size_t nitems,
+                             void* stream) {
+    return syscall(9005, ptr, size, nitems, stream, 0, 0);
+}
+
+static inline int feof(void* stream) {
+    return syscall(9006, stream, 0, 0, 0, 0, 0);
+}
+
+static inline int ferror(void* stream) {
+    return syscall(9007, stream, 0, 0, 0, 0, 0);
+}
+
+static inline int fgetc(void* stream) {
+    return syscall(9008, stream, 0, 0, 0, 0, 0);
+}
+
+static inline int fclose(void* stream) {
+    return syscall(9009, stream, 0, 0, 0, 0, 0);
+}
+
+static inline long ftell(void* stream) {
+    return syscall(9010, stream, 0, 0, 0, 0, 0);
+}
+
+static inline int fseek(void* stream, long offset, int whence) {
+    return syscall(9011, stream, offset, whence, 0, 0, 0);
+}
+
+static inline uint64_t fwrite(const void* ptr, size_t size, size_t nitems,
+                              void* stream) {
+    return syscall(9012, ptr, size, nitems, stream, 0, 0);
+}
+
+static inline int fputc(int ch, void* stream) {
+    return syscall(9013, ch, stream, 0, 0, 0, 0);
+}
+
+static inline int fputs(const char* s, void* stream) {
+    return syscall(9014, s, stream, 0, 0, 0, 0);
+}
+
+static inline int fflush(void* stream) {
+    return syscall(9015, stream, 0, 0, 0, 0, 0);
+}
+
+#endif  // CKB_DEBUGGER_FILE_OPERATIONS_H_
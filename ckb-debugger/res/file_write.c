@@ -0,0 +1,69 @@
+#define CKB_C_STDLIB_PRINTF
+#define CKB_C_STDLIB_PRINTF_BUFFER_SIZE 1024 * 16
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "file_operations.h"
+
+int main() {
+    printf("Entering main");
+    void* stream = fopen("build/file_write.txt", "w");
+    if (!stream) {
+        printf("Testing fopen failed");
+        return -1;
+    }
+
+    const char* line = "written by fwrite\n";
+    uint64_t count = fwrite(line, 1, strlen(line), stream);
+    if (count != strlen(line)) {
+        printf("Testing fwrite failed");
+        return -1;
+    }
+    int ch = fputc('!', stream);
+    if (ch != '!') {
+        printf("Testing fputc failed");
+        return -1;
+    }
+    int code = fputs("\nwritten by fputs\n", stream);
+    if (code < 0) {
+        printf("Testing fputs failed");
+        return -1;
+    }
+    code = fflush(stream);
+    if (code != 0) {
+        printf("Testing fflush failed");
+        return -1;
+    }
+    code = fclose(stream);
+    if (code != 0) {
+        printf("Testing fclose failed");
+        return -1;
+    }
+
+    stream = fopen("build/file_write.txt", "r");
+    if (!stream) {
+        printf("Testing fopen failed");
+        return -1;
+    }
+    const char* expected = "written by fwrite\n!\nwritten by fputs\n";
+    char content[1024] = {0};
+    count = fread(content, 1, sizeof(content), stream);
+    if (count != strlen(expected) || memcmp(content, expected, count) != 0) {
+        printf("Testing written content failed");
+        return -1;
+    }
+    ch = fputc('!', stream);
+    if (ch != -1 || !ferror(stream)) {
+        printf("Testing write to a read only stream failed");
+        return -1;
+    }
+    fclose(stream);
+
+    printf("--------content of file----------");
+    printf("%s", content);
+    printf("------------------");
+    return 0;
+}
@@ -0,0 +1,57 @@
+#define CKB_C_STDLIB_PRINTF
+#define CKB_C_STDLIB_PRINTF_BUFFER_SIZE 1024 * 16
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "read_streams.h"
+
+int main() {
+    int key = stream_index("key");
+    int message = stream_index("message");
+    if (key < 0 || message < 0) {
+        printf("Testing stream_index failed");
+        return -1;
+    }
+    if (stream_index("missing") != -1) {
+        printf("Testing stream_index of a missing stream failed");
+        return -1;
+    }
+
+    char buf[1024 * 16] = {0};
+    int length = stream_length(message);
+    if (length <= 0 || length >= sizeof(buf)) {
+        printf("Testing stream_length failed");
+        return -1;
+    }
+    int read_size = read_stream(buf, 16, message);
+    if (read_size != 16 && read_size != length) {
+        printf("Testing read_stream failed");
+        return -1;
+    }
+    // The streams keep their own offsets, reading the key doesn't move the
+    // message.
+    char key_buf[16] = {0};
+    if (read_stream(key_buf, sizeof(key_buf), key) <= 0) {
+        printf("Testing read_stream of the key failed");
+        return -1;
+    }
+    if (stream_seek(message, 0) != 0) {
+        printf("Testing stream_seek failed");
+        return -1;
+    }
+    read_size = read_stream(buf, sizeof(buf), message);
+    if (read_size != length) {
+        printf("Testing read_stream after rewinding failed");
+        return -1;
+    }
+    if (read_stream(buf, sizeof(buf), message) != -1) {
+        printf("Testing read_stream at the end failed");
+        return -1;
+    }
+
+    printf("------ message --------");
+    printf("%s", buf);
+    return 0;
+}
@@ -0,0 +1,26 @@
+#ifndef CKB_DEBUGGER_READ_STREAMS_H_
+#define CKB_DEBUGGER_READ_STREAMS_H_
+
+// Stream syscalls served by ckb-debugger for the files given with
+// --read-file. Streams are selected by their index, each keeps its own offset.
+// Every call returns -1 on failure.
+
+#include "ckb_syscalls.h"
+
+static inline int read_stream(char* buf, int size, int stream) {
+    return syscall(9016, buf, size, stream, 0, 0, 0);
+}
+
+static inline int stream_index(const char* name) {
+    return syscall(9017, name, 0, 0, 0, 0, 0);
+}
+
+static inline int stream_length(int stream) {
+    return syscall(9018, stream, 0, 0, 0, 0, 0);
+}
+
+static inline int stream_seek(int stream, int offset) {
+    return syscall(9019, stream, offset, 0, 0, 0, 0);
+}
+
+#endif  // CKB_DEBUGGER_READ_STREAMS_H_
@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Cycles measured for a transaction, which is also the format of baseline files.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    pub total_cycles: u64,
+    /// Total cycles of each script group, by script hash.
+    pub groups: BTreeMap<String, u64>,
+    /// Self cycles of each function, by script hash and then function. Only measured in full mode.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub functions: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Baseline {
+    pub fn add_group(&mut self, script_hash: &str, cycles: u64, functions: Option<HashMap<String, u64>>) {
+        self.total_cycles = self.total_cycles.saturating_add(cycles);
+        self.groups.insert(script_hash.to_string(), cycles);
+        if let Some(functions) = functions {
+            self.functions.insert(script_hash.to_string(), functions.into_iter().collect());
+        }
+    }
+
+    /// Replaces the groups of this baseline measured in `measured`, along with their functions, and keeps the others.
+    /// The total is that of all the groups.
+    pub fn update(&mut self, measured: &Baseline) {
+        for (script_hash, cycles) in &measured.groups {
+            self.groups.insert(script_hash.clone(), *cycles);
+            match measured.functions.get(script_hash) {
+                Some(functions) => self.functions.insert(script_hash.clone(), functions.clone()),
+                None => self.functions.remove(script_hash),
+            };
+        }
+        self.total_cycles = self.groups.values().fold(0, |total, cycles| total.saturating_add(*cycles));
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+/// How many cycles a measurement may grow over its baseline, given as `<cycles>` or `<percent>%`.
+#[derive(Clone, Copy, Debug)]
+pub enum Threshold {
+    Absolute(u64),
+    Percent(f64),
+}
+
+impl Threshold {
+    fn allowance(&self, baseline: u64) -> u64 {
+        match self {
+            Threshold::Absolute(cycles) => *cycles,
+            Threshold::Percent(percent) => (baseline as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.parse::<f64>() {
+                Ok(percent) if percent >= 0.0 => Ok(Threshold::Percent(percent)),
+                _ => Err(format!("Invalid threshold {}, expect a non-negative percentage", s)),
+            },
+            None => s.parse().map(Threshold::Absolute).map_err(|_| format!("Invalid threshold {}", s)),
+        }
+    }
+}
+
+/// Outcome of comparing a measurement with its baseline: one line for each value that changed or is new, and whether
+/// any grew over the threshold.
+pub struct Comparison {
+    pub lines: Vec<String>,
+    pub regressed: bool,
+}
+
+impl Comparison {
+    fn compare(&mut self, name: &str, baseline: Option<u64>, measured: u64, threshold: Threshold) {
+        let baseline = match baseline {
+            Some(baseline) => baseline,
+            None => {
+                self.lines.push(format!("{}: {} cycles, not in the baseline", name, measured));
+                return;
+            }
+        };
+        if baseline == measured {
+            return;
+        }
+        let exceeded = measured > baseline.saturating_add(threshold.allowance(baseline));
+        let change = measured as f64 / baseline.max(1) as f64 * 100.0 - 100.0;
+        self.lines.push(format!(
+            "{}: {} cycles, baseline {} ({:+.2}%){}",
+            name,
+            measured,
+            baseline,
+            change,
+            if exceeded { ", over the threshold" } else { "" }
+        ));
+        self.regressed |= exceeded;
+    }
+}
+
+/// Compares the groups and, when both have them, the functions of `measured` with `baseline`, and the total as well
+/// when `measured` holds every group of the transaction. Groups and functions missing from the baseline are listed but
+/// never count as a regression.
+pub fn compare(baseline: &Baseline, measured: &Baseline, threshold: Threshold, all_groups: bool) -> Comparison {
+    let mut comparison = Comparison {
+        lines: vec![],
+        regressed: false,
+    };
+    if all_groups {
+        comparison.compare("total", Some(baseline.total_cycles), measured.total_cycles, threshold);
+    }
+    for (script_hash, cycles) in &measured.groups {
+        comparison.compare(
+            script_hash,
+            baseline.groups.get(script_hash).copied(),
+            *cycles,
+            threshold,
+        );
+    }
+    for (script_hash, functions) in &measured.functions {
+        let baseline_functions = match baseline.functions.get(script_hash) {
+            Some(baseline_functions) => baseline_functions,
+            None => continue,
+        };
+        for (function, cycles) in functions {
+            let name = format!("{} {}", script_hash, function);
+            comparison.compare(&name, baseline_functions.get(function).copied(), *cycles, threshold);
+        }
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(groups: &[(&str, u64)]) -> Baseline {
+        let mut baseline = Baseline::default();
+        for (script_hash, cycles) in groups {
+            baseline.add_group(script_hash, *cycles, None);
+        }
+        baseline
+    }
+
+    #[test]
+    fn test_threshold_from_str() {
+        assert!(matches!("100".parse::<Threshold>(), Ok(Threshold::Absolute(100))));
+        assert!(matches!("2.5%".parse::<Threshold>(), Ok(Threshold::Percent(p)) if p == 2.5));
+        assert!(matches!("0%".parse::<Threshold>(), Ok(Threshold::Percent(p)) if p == 0.0));
+        assert!("-1%".parse::<Threshold>().is_err());
+        assert!("-1".parse::<Threshold>().is_err());
+        assert!("x%".parse::<Threshold>().is_err());
+        assert!("".parse::<Threshold>().is_err());
+        assert_eq!(Threshold::Percent(10.0).allowance(1000), 100);
+        assert_eq!(Threshold::Absolute(7).allowance(1000), 7);
+    }
+
+    #[test]
+    fn test_compare() {
+        let recorded = baseline(&[("0xa", 1000), ("0xb", 2000)]);
+
+        let comparison = compare(&recorded, &recorded, Threshold::Absolute(0), true);
+        assert!(comparison.lines.is_empty());
+        assert!(!comparison.regressed);
+
+        let measured = baseline(&[("0xa", 1050), ("0xb", 2000)]);
+        let comparison = compare(&recorded, &measured, Threshold::Percent(5.0), true);
+        assert_eq!(
+            comparison.lines,
+            vec![
+                "total: 3050 cycles, baseline 3000 (+1.67%)",
+                "0xa: 1050 cycles, baseline 1000 (+5.00%)"
+            ]
+        );
+        assert!(!comparison.regressed);
+        let comparison = compare(&recorded, &measured, Threshold::Absolute(49), true);
+        assert_eq!(
+            comparison.lines[1],
+            "0xa: 1050 cycles, baseline 1000 (+5.00%), over the threshold"
+        );
+        assert!(comparison.regressed);
+
+        // Getting faster is never a regression, and a new group is only listed.
+        let measured = baseline(&[("0xa", 900), ("0xc", 5000)]);
+        let comparison = compare(&recorded, &measured, Threshold::Absolute(0), false);
+        assert_eq!(
+            comparison.lines,
+            vec![
+                "0xa: 900 cycles, baseline 1000 (-10.00%)",
+                "0xc: 5000 cycles, not in the baseline"
+            ]
+        );
+        assert!(!comparison.regressed);
+    }
+
+    #[test]
+    fn test_compare_functions() {
+        let mut recorded = Baseline::default();
+        recorded.add_group(
+            "0xa",
+            1000,
+            Some(vec![("main".to_string(), 600), ("f".to_string(), 400)].into_iter().collect()),
+        );
+        let mut measured = Baseline::default();
+        measured.add_group(
+            "0xa",
+            1000,
+            Some(vec![("main".to_string(), 500), ("f".to_string(), 500)].into_iter().collect()),
+        );
+        let comparison = compare(&recorded, &measured, Threshold::Absolute(0), false);
+        assert_eq!(
+            comparison.lines,
+            vec![
+                "0xa f: 500 cycles, baseline 400 (+25.00%), over the threshold",
+                "0xa main: 500 cycles, baseline 600 (-16.67%)"
+            ]
+        );
+        assert!(comparison.regressed);
+        // Functions are only compared when the baseline has them.
+        assert!(compare(&baseline(&[("0xa", 1000)]), &measured, Threshold::Absolute(0), false).lines.is_empty());
+    }
+
+    #[test]
+    fn test_update() {
+        let mut recorded = Baseline::default();
+        recorded.add_group(
+            "0xa",
+            1000,
+            Some(vec![("main".to_string(), 1000)].into_iter().collect()),
+        );
+        recorded.add_group("0xb", 2000, None);
+        recorded.update(&baseline(&[("0xa", 1500)]));
+        assert_eq!(recorded.total_cycles, 3500);
+        assert_eq!(recorded.groups, baseline(&[("0xa", 1500), ("0xb", 2000)]).groups);
+        assert!(recorded.functions.is_empty());
+    }
+}
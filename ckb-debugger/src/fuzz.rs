@@ -0,0 +1,248 @@
+use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types::{JsonBytes, Script};
+use ckb_mock_tx_types::ReprMockTransaction;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::misc::HumanReadableCycles;
+
+/// A byte string of the mock transaction which the fuzzer is allowed to change.
+#[derive(Clone, Copy, Debug)]
+pub enum FuzzTarget {
+    Witness(usize),
+    InputData(usize),
+    OutputData(usize),
+    CellDepData(usize),
+    InputLockArgs(usize),
+    InputTypeArgs(usize),
+    OutputLockArgs(usize),
+    OutputTypeArgs(usize),
+}
+
+impl FromStr for FuzzTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, index) = s.split_once(':').ok_or_else(|| format!("Invalid fuzz target: {}", s))?;
+        let index: usize = index.parse().map_err(|_| format!("Invalid fuzz target index: {}", s))?;
+        match kind {
+            "witness" => Ok(FuzzTarget::Witness(index)),
+            "input-data" => Ok(FuzzTarget::InputData(index)),
+            "output-data" => Ok(FuzzTarget::OutputData(index)),
+            "cell-dep-data" => Ok(FuzzTarget::CellDepData(index)),
+            "input-lock-args" => Ok(FuzzTarget::InputLockArgs(index)),
+            "input-type-args" => Ok(FuzzTarget::InputTypeArgs(index)),
+            "output-lock-args" => Ok(FuzzTarget::OutputLockArgs(index)),
+            "output-type-args" => Ok(FuzzTarget::OutputTypeArgs(index)),
+            _ => Err(format!("Invalid fuzz target: {}", s)),
+        }
+    }
+}
+
+impl FuzzTarget {
+    fn bytes_mut<'a>(&self, tx: &'a mut ReprMockTransaction) -> Option<&'a mut JsonBytes> {
+        fn args(script: Option<&mut Script>) -> Option<&mut JsonBytes> {
+            script.map(|s| &mut s.args)
+        }
+        match *self {
+            FuzzTarget::Witness(i) => tx.tx.witnesses.get_mut(i),
+            FuzzTarget::InputData(i) => tx.mock_info.inputs.get_mut(i).map(|e| &mut e.data),
+            FuzzTarget::OutputData(i) => tx.tx.outputs_data.get_mut(i),
+            FuzzTarget::CellDepData(i) => tx.mock_info.cell_deps.get_mut(i).map(|e| &mut e.data),
+            FuzzTarget::InputLockArgs(i) => args(tx.mock_info.inputs.get_mut(i).map(|e| &mut e.output.lock)),
+            FuzzTarget::InputTypeArgs(i) => args(tx.mock_info.inputs.get_mut(i).and_then(|e| e.output.type_.as_mut())),
+            FuzzTarget::OutputLockArgs(i) => args(tx.tx.outputs.get_mut(i).map(|e| &mut e.lock)),
+            FuzzTarget::OutputTypeArgs(i) => args(tx.tx.outputs.get_mut(i).and_then(|e| e.type_.as_mut())),
+        }
+    }
+}
+
+/// What happened when the script group ran with a fuzzed transaction.
+pub struct Execution {
+    pub result: Result<i8, ckb_vm::Error>,
+    pub cycles: u64,
+    pub pcs: HashSet<u64>,
+}
+
+pub struct Fuzzer {
+    targets: Vec<FuzzTarget>,
+    exit_codes: Vec<i8>,
+    output_dir: PathBuf,
+    seed: u64,
+    rng: StdRng,
+    corpus: Vec<ReprMockTransaction>,
+    coverage: HashSet<u64>,
+}
+
+const INTERESTING_BYTES: [u8; 6] = [0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff];
+
+impl Fuzzer {
+    pub fn new(
+        tx: ReprMockTransaction,
+        targets: Vec<FuzzTarget>,
+        exit_codes: Vec<i8>,
+        output_dir: PathBuf,
+        seed: u64,
+    ) -> Result<Self, String> {
+        let mut tx = tx;
+        for target in &targets {
+            if target.bytes_mut(&mut tx).is_none() {
+                return Err(format!("Fuzz target {:?} does not exist in the transaction", target));
+            }
+        }
+        Ok(Self {
+            targets,
+            exit_codes,
+            output_dir,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            corpus: vec![tx],
+            coverage: HashSet::new(),
+        })
+    }
+
+    fn mutate_bytes(&mut self, data: &mut Vec<u8>) {
+        let rounds = self.rng.gen_range(1..=4);
+        for _ in 0..rounds {
+            match self.rng.gen_range(0..7) {
+                0 if !data.is_empty() => {
+                    let i = self.rng.gen_range(0..data.len());
+                    data[i] ^= 1 << self.rng.gen_range(0..8);
+                }
+                1 if !data.is_empty() => {
+                    let i = self.rng.gen_range(0..data.len());
+                    data[i] = self.rng.gen();
+                }
+                2 if !data.is_empty() => {
+                    let i = self.rng.gen_range(0..data.len());
+                    data[i] = INTERESTING_BYTES[self.rng.gen_range(0..INTERESTING_BYTES.len())];
+                }
+                3 if !data.is_empty() => {
+                    let i = self.rng.gen_range(0..data.len());
+                    let n = self.rng.gen_range(1..=(data.len() - i).min(16));
+                    data.drain(i..i + n);
+                }
+                4 if !data.is_empty() => {
+                    let i = self.rng.gen_range(0..data.len());
+                    let n = self.rng.gen_range(1..=(data.len() - i).min(16));
+                    let chunk: Vec<u8> = data[i..i + n].to_vec();
+                    let at = self.rng.gen_range(0..=data.len());
+                    data.splice(at..at, chunk);
+                }
+                5 if !data.is_empty() => {
+                    data.truncate(self.rng.gen_range(0..data.len()));
+                }
+                _ => {
+                    let at = self.rng.gen_range(0..=data.len());
+                    let n = self.rng.gen_range(1..=8);
+                    let chunk: Vec<u8> = (0..n).map(|_| self.rng.gen()).collect();
+                    data.splice(at..at, chunk);
+                }
+            }
+        }
+    }
+
+    fn mutate(&mut self) -> ReprMockTransaction {
+        let mut tx = self.corpus[self.rng.gen_range(0..self.corpus.len())].clone();
+        let target = self.targets[self.rng.gen_range(0..self.targets.len())];
+        let bytes = target.bytes_mut(&mut tx).expect("fuzz targets are checked on creation");
+        let mut data = bytes.as_bytes().to_vec();
+        self.mutate_bytes(&mut data);
+        *bytes = JsonBytes::from_vec(data);
+        tx
+    }
+
+    // Names the file after the content so the same finding is only saved once. Every finding is also logged to
+    // findings.jsonl with the seed and iteration it was found at, so that the same arguments find it again.
+    fn save(
+        &self,
+        kind: &str,
+        tx: &ReprMockTransaction,
+        iteration: u64,
+        execution: &Execution,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(tx)?;
+        let hash = blake2b_256(json.as_bytes());
+        let file_name = format!("{}-{}.json", kind, hex::encode(&hash[..8]));
+        let path = self.output_dir.join(&file_name);
+        std::fs::create_dir_all(&self.output_dir)?;
+        std::fs::write(&path, json)?;
+        let entry = json!({
+            "file": file_name,
+            "seed": self.seed,
+            "iteration": iteration,
+            "result": format!("{:?}", execution.result),
+            "cycles": execution.cycles,
+        });
+        let mut log =
+            std::fs::OpenOptions::new().create(true).append(true).open(self.output_dir.join("findings.jsonl"))?;
+        writeln!(log, "{}", entry)?;
+        Ok(path)
+    }
+
+    /// Runs `iterations` mutated transactions through `execute`, or forever if `iterations` is 0. `execute` returns
+    /// None when the mutated transaction could not be run at all, for example because the script group is gone.
+    /// Mutations executing pcs that no run has reached before are kept and mutated further.
+    pub fn run<F>(&mut self, iterations: u64, mut execute: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&ReprMockTransaction) -> Option<Execution>,
+    {
+        println!("Fuzzing with seed {}", self.seed);
+        let seed_tx = self.corpus[0].clone();
+        if let Some(execution) = execute(&seed_tx) {
+            self.coverage.extend(execution.pcs);
+        }
+        let mut findings = 0;
+        let mut i = 0;
+        while iterations == 0 || i < iterations {
+            i += 1;
+            let tx = self.mutate();
+            let execution = match execute(&tx) {
+                Some(execution) => execution,
+                None => continue,
+            };
+            let kind = match &execution.result {
+                Err(ckb_vm::Error::CyclesExceeded) => Some(String::from("cycles")),
+                Err(_) => Some(String::from("error")),
+                Ok(code) if self.exit_codes.contains(code) => Some(format!("exit-{}", code)),
+                Ok(_) => None,
+            };
+            if let Some(kind) = kind {
+                let path = self.save(&kind, &tx, i, &execution)?;
+                findings += 1;
+                println!(
+                    "Iteration {}: {:?} after {} cycles, saved to {}",
+                    i,
+                    execution.result,
+                    HumanReadableCycles(execution.cycles),
+                    path.display()
+                );
+            }
+            let new_pcs = execution.pcs.difference(&self.coverage).count();
+            if new_pcs > 0 {
+                self.coverage.extend(execution.pcs);
+                self.corpus.push(tx);
+                println!(
+                    "Iteration {}: {} new pcs, {} pcs covered, corpus size {}",
+                    i,
+                    new_pcs,
+                    self.coverage.len(),
+                    self.corpus.len()
+                );
+            }
+        }
+        println!(
+            "Fuzzing finished after {} iterations: {} pcs covered, {} findings, seed {}",
+            i,
+            self.coverage.len(),
+            findings,
+            self.seed
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use ckb_hash::blake2b_256;
+use ckb_mock_tx_types::TYPE_ID_CODE_HASH;
+use ckb_types::{
+    bytes::Bytes,
+    core::ScriptHashType,
+    packed::{Byte32, Script, WitnessArgs},
+    prelude::*,
+};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// 1000 and 999 CKB.
+const INPUT_CAPACITY: u64 = 100_000_000_000;
+const OUTPUT_CAPACITY: u64 = 99_900_000_000;
+
+/// A script given on the command line as `<binary>[,<hash type>[,<args>]]`.
+#[derive(Clone, Debug)]
+pub struct ScriptSpec {
+    pub binary: PathBuf,
+    pub hash_type: String,
+    pub args: String,
+}
+
+impl FromStr for ScriptSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ',');
+        let binary = PathBuf::from(parts.next().unwrap());
+        let hash_type = parts.next().unwrap_or("data1").to_string();
+        if !["data", "data1", "type"].contains(&hash_type.as_str()) {
+            return Err(format!("Invalid hash type {} in {}", hash_type, s));
+        }
+        let args = parts.next().unwrap_or("0x").to_string();
+        if !args.starts_with("0x") || hex::decode(&args[2..]).is_err() {
+            return Err(format!(
+                "Invalid args {} in {}, expect a 0x prefixed hex string",
+                args, s
+            ));
+        }
+        Ok(Self {
+            binary,
+            hash_type,
+            args,
+        })
+    }
+}
+
+fn out_point(tx: u64, index: usize) -> Value {
+    json!({
+        "tx_hash": format!("0x{:064x}", tx),
+        "index": format!("0x{:x}", index),
+    })
+}
+
+// Templates resolve paths against the directory of the tx file, so binaries are referenced from there when possible.
+fn template_path(binary: &Path, base: &Path) -> String {
+    let relative = match (binary.canonicalize(), base.canonicalize()) {
+        (Ok(binary), Ok(base)) => binary.strip_prefix(base).map(|p| p.to_path_buf()).unwrap_or(binary),
+        _ => binary.to_path_buf(),
+    };
+    relative.to_string_lossy().to_string()
+}
+
+// Each binary is deployed in its own cell dep, which also carries a type id like type script so that the binary can
+// be referenced with hash type `type`.
+struct Deployment {
+    binary: PathBuf,
+    type_script: Script,
+}
+
+impl Deployment {
+    fn new(binary: &Path) -> Self {
+        let type_script = Script::new_builder()
+            .code_hash(Byte32::new(TYPE_ID_CODE_HASH))
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(blake2b_256(binary.to_string_lossy().as_bytes()).to_vec()).pack())
+            .build();
+        Self {
+            binary: binary.to_path_buf(),
+            type_script,
+        }
+    }
+}
+
+// Deployed cells are never unlocked in the transaction, so their lock script doesn't matter.
+fn unused_lock() -> Value {
+    json!({
+        "code_hash": format!("0x{:064x}", 0),
+        "hash_type": "data1",
+        "args": "0x",
+    })
+}
+
+fn script_json(spec: &ScriptSpec, deployments: &[Deployment], base: &Path) -> Value {
+    let code_hash = if spec.hash_type == "type" {
+        let deployment = deployments.iter().find(|d| d.binary == spec.binary).unwrap();
+        format!("0x{:x}", deployment.type_script.calc_script_hash())
+    } else {
+        format!("{{{{ hash {} }}}}", template_path(&spec.binary, base))
+    };
+    json!({
+        "code_hash": code_hash,
+        "hash_type": spec.hash_type,
+        "args": spec.args,
+    })
+}
+
+/// Builds a mock transaction running the given scripts. There is one input for each lock script, and one output for
+/// each type script, locked by the first lock script. Script code is pulled in with `{{ data ... }}` templates, so
+/// the file picks up rebuilt binaries. `base` is the directory the file will be written to.
+pub fn scaffold(locks: &[ScriptSpec], types: &[ScriptSpec], base: &Path) -> Result<Value, String> {
+    if locks.is_empty() {
+        return Err(String::from("At least one lock script is required"));
+    }
+    let mut deployments: Vec<Deployment> = vec![];
+    for spec in locks.iter().chain(types) {
+        if !spec.binary.is_file() {
+            return Err(format!("Script binary {} does not exist", spec.binary.display()));
+        }
+        if !deployments.iter().any(|d| d.binary == spec.binary) {
+            deployments.push(Deployment::new(&spec.binary));
+        }
+    }
+
+    let cell_deps: Vec<Value> =
+        (0..deployments.len()).map(|i| json!({ "out_point": out_point(1, i), "dep_type": "code" })).collect();
+    let mock_cell_deps: Vec<Value> = deployments
+        .iter()
+        .zip(&cell_deps)
+        .map(|(d, cell_dep)| {
+            json!({
+                "cell_dep": cell_dep,
+                "output": {
+                    "capacity": format!("0x{:x}", INPUT_CAPACITY),
+                    "lock": unused_lock(),
+                    "type": {
+                        "code_hash": format!("0x{}", hex::encode(TYPE_ID_CODE_HASH)),
+                        "hash_type": "type",
+                        "args": format!("0x{}", hex::encode(d.type_script.args().raw_data())),
+                    },
+                },
+                "data": format!("{{{{ data {} }}}}", template_path(&d.binary, base)),
+                "header": null,
+            })
+        })
+        .collect();
+
+    let inputs: Vec<Value> =
+        (0..locks.len()).map(|i| json!({ "previous_output": out_point(2, i), "since": "0x0" })).collect();
+    let mock_inputs: Vec<Value> = locks
+        .iter()
+        .zip(&inputs)
+        .map(|(spec, input)| {
+            json!({
+                "input": input,
+                "output": {
+                    "capacity": format!("0x{:x}", INPUT_CAPACITY),
+                    "lock": script_json(spec, &deployments, base),
+                    "type": null,
+                },
+                "data": "0x",
+                "header": null,
+            })
+        })
+        .collect();
+
+    let mut outputs: Vec<Value> = types
+        .iter()
+        .map(|spec| {
+            json!({
+                "capacity": format!("0x{:x}", OUTPUT_CAPACITY),
+                "lock": script_json(&locks[0], &deployments, base),
+                "type": script_json(spec, &deployments, base),
+            })
+        })
+        .collect();
+    if outputs.is_empty() {
+        outputs.push(json!({
+            "capacity": format!("0x{:x}", OUTPUT_CAPACITY),
+            "lock": script_json(&locks[0], &deployments, base),
+            "type": null,
+        }));
+    }
+    let outputs_data: Vec<Value> = outputs.iter().map(|_| json!("0x")).collect();
+
+    // A zero filled lock field is what signing tools expect to find before the signature is put in.
+    let placeholder = WitnessArgs::new_builder().lock(Some(Bytes::from(vec![0u8; 65])).pack()).build();
+    let witnesses: Vec<Value> =
+        inputs.iter().map(|_| json!(format!("0x{}", hex::encode(placeholder.as_slice())))).collect();
+
+    Ok(json!({
+        "mock_info": {
+            "inputs": mock_inputs,
+            "cell_deps": mock_cell_deps,
+            "header_deps": [],
+        },
+        "tx": {
+            "version": "0x0",
+            "cell_deps": cell_deps,
+            "header_deps": [],
+            "inputs": inputs,
+            "outputs": outputs,
+            "outputs_data": outputs_data,
+            "witnesses": witnesses,
+        },
+    }))
+}
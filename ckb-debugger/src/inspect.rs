@@ -0,0 +1,315 @@
+use ckb_mock_tx_types::{short_out_point, MockTransaction, TYPE_ID_CODE_HASH};
+use ckb_types::{
+    bytes::Bytes,
+    core::DepType,
+    packed::{Byte32, BytesOpt, CellOutput, OutPoint, OutPointVec, Script, WitnessArgs},
+    prelude::*,
+};
+use std::fmt::Write;
+
+fn ckb(shannons: u64) -> String {
+    let fraction = format!("{:08}", shannons % 100_000_000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{} CKB", shannons / 100_000_000)
+    } else {
+        format!("{}.{} CKB", shannons / 100_000_000, fraction)
+    }
+}
+
+fn hash_type_name(script: &Script) -> String {
+    match script.hash_type().as_slice()[0] {
+        0 => String::from("data"),
+        1 => String::from("type"),
+        2 => String::from("data1"),
+        n => format!("unknown({})", n),
+    }
+}
+
+fn bytes_opt(bytes: BytesOpt) -> String {
+    match bytes.to_opt() {
+        Some(bytes) => format!("0x{}", hex::encode(bytes.raw_data())),
+        None => String::from("none"),
+    }
+}
+
+// A cell scripts can load code from: a cell dep, or a cell listed in a dep group.
+struct CodeCell {
+    label: String,
+    output: CellOutput,
+    data: Bytes,
+}
+
+struct Inspector<'a> {
+    mock_tx: &'a MockTransaction,
+    code_cells: Vec<CodeCell>,
+    out: String,
+}
+
+impl<'a> Inspector<'a> {
+    fn find_cell_dep(&self, out_point: &OutPoint) -> Option<usize> {
+        self.mock_tx.mock_info.cell_deps.iter().position(|dep| &dep.cell_dep.out_point() == out_point)
+    }
+
+    fn find_input(&self, out_point: &OutPoint) -> Option<usize> {
+        self.mock_tx.mock_info.inputs.iter().position(|input| &input.input.previous_output() == out_point)
+    }
+
+    // Names the cells whose code a script would run.
+    fn code_of(&self, script: &Script) -> String {
+        let code_hash = script.code_hash();
+        if hash_type_name(script) == "type" && code_hash == Byte32::new(TYPE_ID_CODE_HASH) {
+            return String::from("built-in type id");
+        }
+        let matches: Vec<&str> = self
+            .code_cells
+            .iter()
+            .filter(|cell| match hash_type_name(script).as_str() {
+                "type" => cell.output.type_().to_opt().map(|t| t.calc_script_hash()) == Some(code_hash.clone()),
+                _ => CellOutput::calc_data_hash(&cell.data) == code_hash,
+            })
+            .map(|cell| cell.label.as_str())
+            .collect();
+        if matches.is_empty() {
+            String::from("not found in cell deps")
+        } else {
+            matches.join(", ")
+        }
+    }
+
+    fn script(&mut self, name: &str, script: Option<Script>) {
+        let script = match script {
+            Some(script) => script,
+            None => {
+                writeln!(self.out, "      {}: none", name).unwrap();
+                return;
+            }
+        };
+        let code = self.code_of(&script);
+        writeln!(self.out, "      {}: 0x{:x}", name, script.calc_script_hash()).unwrap();
+        writeln!(self.out, "        code_hash: 0x{:x}", script.code_hash()).unwrap();
+        writeln!(self.out, "        hash_type: {}", hash_type_name(&script)).unwrap();
+        writeln!(self.out, "        args: 0x{}", hex::encode(script.args().raw_data())).unwrap();
+        writeln!(self.out, "        code: {}", code).unwrap();
+    }
+
+    fn cell(&mut self, output: &CellOutput, data: &Bytes) {
+        writeln!(self.out, "      capacity: {}", ckb(output.capacity().unpack())).unwrap();
+        writeln!(
+            self.out,
+            "      data: {} bytes, data hash 0x{:x}",
+            data.len(),
+            CellOutput::calc_data_hash(data)
+        )
+        .unwrap();
+        self.script("lock", Some(output.lock()));
+        self.script("type", output.type_().to_opt());
+    }
+
+    fn cell_deps(&mut self) {
+        let mock_tx = self.mock_tx;
+        writeln!(self.out, "Cell deps:").unwrap();
+        for (i, cell_dep) in mock_tx.tx.raw().cell_deps().into_iter().enumerate() {
+            let out_point = cell_dep.out_point();
+            let dep_type = if cell_dep.dep_type() == DepType::DepGroup.into() {
+                "dep_group"
+            } else {
+                "code"
+            };
+            writeln!(self.out, "  [{}] {} {}", i, short_out_point(&out_point), dep_type).unwrap();
+            let j = match self.find_cell_dep(&out_point) {
+                Some(j) => j,
+                None => {
+                    writeln!(self.out, "      missing from mock_info.cell_deps").unwrap();
+                    continue;
+                }
+            };
+            let dep = &mock_tx.mock_info.cell_deps[j];
+            if dep_type == "code" {
+                self.code_cells.push(CodeCell {
+                    label: format!("cell dep {}", i),
+                    output: dep.output.clone(),
+                    data: dep.data.clone(),
+                });
+                writeln!(
+                    self.out,
+                    "      data: {} bytes, data hash 0x{:x}",
+                    dep.data.len(),
+                    CellOutput::calc_data_hash(&dep.data)
+                )
+                .unwrap();
+                if let Some(type_script) = dep.output.type_().to_opt() {
+                    writeln!(self.out, "      type hash: 0x{:x}", type_script.calc_script_hash()).unwrap();
+                }
+                continue;
+            }
+            let group = match OutPointVec::from_slice(&dep.data) {
+                Ok(group) => group,
+                Err(err) => {
+                    writeln!(self.out, "      data is not a list of out points: {}", err).unwrap();
+                    continue;
+                }
+            };
+            for (k, member) in group.into_iter().enumerate() {
+                match self.find_cell_dep(&member) {
+                    Some(l) => {
+                        let cell = &mock_tx.mock_info.cell_deps[l];
+                        writeln!(
+                            self.out,
+                            "      [{}] {} data hash 0x{:x}",
+                            k,
+                            short_out_point(&member),
+                            CellOutput::calc_data_hash(&cell.data)
+                        )
+                        .unwrap();
+                        self.code_cells.push(CodeCell {
+                            label: format!("cell dep {} member {}", i, k),
+                            output: cell.output.clone(),
+                            data: cell.data.clone(),
+                        });
+                    }
+                    None => writeln!(
+                        self.out,
+                        "      [{}] {} missing from mock_info.cell_deps",
+                        k,
+                        short_out_point(&member)
+                    )
+                    .unwrap(),
+                }
+            }
+        }
+    }
+
+    fn header_deps(&mut self) {
+        writeln!(self.out, "Header deps:").unwrap();
+        for (i, hash) in self.mock_tx.tx.raw().header_deps().into_iter().enumerate() {
+            let found = self.mock_tx.mock_info.header_deps.iter().any(|header| header.hash() == hash);
+            let note = if found {
+                ""
+            } else {
+                ", missing from mock_info.header_deps"
+            };
+            writeln!(self.out, "  [{}] 0x{:x}{}", i, hash, note).unwrap();
+        }
+    }
+
+    fn inputs(&mut self) {
+        writeln!(self.out, "Inputs:").unwrap();
+        for (i, input) in self.mock_tx.tx.raw().inputs().into_iter().enumerate() {
+            let out_point = input.previous_output();
+            let since: u64 = input.since().unpack();
+            writeln!(
+                self.out,
+                "  [{}] {} since 0x{:x}",
+                i,
+                short_out_point(&out_point),
+                since
+            )
+            .unwrap();
+            match self.find_input(&out_point) {
+                Some(j) => {
+                    let mock_input = &self.mock_tx.mock_info.inputs[j];
+                    let (output, data) = (mock_input.output.clone(), mock_input.data.clone());
+                    self.cell(&output, &data);
+                }
+                None => writeln!(self.out, "      missing from mock_info.inputs").unwrap(),
+            }
+        }
+    }
+
+    fn outputs(&mut self) {
+        writeln!(self.out, "Outputs:").unwrap();
+        let outputs_data = self.mock_tx.tx.raw().outputs_data();
+        for (i, output) in self.mock_tx.tx.raw().outputs().into_iter().enumerate() {
+            writeln!(self.out, "  [{}]", i).unwrap();
+            let data = outputs_data.get(i).map(|data| data.raw_data()).unwrap_or_default();
+            self.cell(&output, &data);
+        }
+    }
+
+    fn witnesses(&mut self) {
+        writeln!(self.out, "Witnesses:").unwrap();
+        for (i, witness) in self.mock_tx.tx.witnesses().into_iter().enumerate() {
+            let witness = witness.raw_data();
+            match WitnessArgs::from_slice(&witness) {
+                Ok(witness_args) => {
+                    writeln!(self.out, "  [{}] WitnessArgs", i).unwrap();
+                    writeln!(self.out, "      lock: {}", bytes_opt(witness_args.lock())).unwrap();
+                    writeln!(self.out, "      input_type: {}", bytes_opt(witness_args.input_type())).unwrap();
+                    writeln!(self.out, "      output_type: {}", bytes_opt(witness_args.output_type())).unwrap();
+                }
+                Err(_) => writeln!(self.out, "  [{}] 0x{}", i, hex::encode(&witness)).unwrap(),
+            }
+        }
+    }
+
+    // Groups in the order ckb-script runs them: lock groups, then type groups, each by first appearance.
+    fn script_groups(&mut self) {
+        let mut groups: Vec<(&str, Byte32, Vec<usize>, Vec<usize>)> = vec![];
+        let mut add = |group_type: &'static str, hash: Byte32, input: Option<usize>, output: Option<usize>| {
+            let position = groups.iter().position(|(t, h, _, _)| *t == group_type && h == &hash);
+            let group = match position {
+                Some(position) => &mut groups[position],
+                None => {
+                    groups.push((group_type, hash, vec![], vec![]));
+                    groups.last_mut().unwrap()
+                }
+            };
+            group.2.extend(input);
+            group.3.extend(output);
+        };
+        let inputs: Vec<Option<CellOutput>> = self
+            .mock_tx
+            .tx
+            .raw()
+            .inputs()
+            .into_iter()
+            .map(|input| self.find_input(&input.previous_output()))
+            .map(|j| j.map(|j| self.mock_tx.mock_info.inputs[j].output.clone()))
+            .collect();
+        for (i, output) in inputs.iter().enumerate() {
+            if let Some(output) = output {
+                add("lock", output.calc_lock_hash(), Some(i), None);
+            }
+        }
+        for (i, output) in inputs.iter().enumerate() {
+            if let Some(type_script) = output.as_ref().and_then(|output| output.type_().to_opt()) {
+                add("type", type_script.calc_script_hash(), Some(i), None);
+            }
+        }
+        for (i, output) in self.mock_tx.tx.raw().outputs().into_iter().enumerate() {
+            if let Some(type_script) = output.type_().to_opt() {
+                add("type", type_script.calc_script_hash(), None, Some(i));
+            }
+        }
+        writeln!(self.out, "Script groups:").unwrap();
+        for (group_type, hash, inputs, outputs) in groups {
+            writeln!(
+                self.out,
+                "  {} 0x{:x} inputs {:?} outputs {:?}",
+                group_type, hash, inputs, outputs
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Describes `mock_tx` for humans: cells with their capacity in CKB, scripts with their hashes and the cell dep their
+/// code is loaded from, dep groups expanded, witnesses decoded as `WitnessArgs` and the script groups that run.
+pub fn inspect(mock_tx: &MockTransaction) -> String {
+    let mut inspector = Inspector {
+        mock_tx,
+        code_cells: vec![],
+        out: String::new(),
+    };
+    let tx = mock_tx.core_transaction();
+    let version: u32 = tx.version();
+    writeln!(inspector.out, "Transaction 0x{:x} (version {})", tx.hash(), version).unwrap();
+    inspector.cell_deps();
+    inspector.header_deps();
+    inspector.inputs();
+    inspector.outputs();
+    inspector.witnesses();
+    inspector.script_groups();
+    inspector.out
+}
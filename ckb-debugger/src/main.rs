@@ -1,34 +1,389 @@
 #[macro_use]
 extern crate log;
 
+use ckb_crypto::secp::Privkey;
 use ckb_debugger_api::DummyResourceLoader;
-use ckb_hash::blake2b_256;
-use ckb_mock_tx_types::{MockTransaction, ReprMockTransaction, Resource};
+use ckb_mock_tx_types::{
+    lint, verify_consensus, ChainTip, MockTransaction, ReprMockTransaction, Resource, RpcResourceLoader,
+};
 use ckb_script::{
     cost_model::{instruction_cycles, transferred_byte_cycles},
-    ScriptGroupType, ScriptVersion, TransactionScriptsVerifier,
+    ScriptGroup, ScriptGroupType, ScriptVersion, TransactionScriptsVerifier,
+};
+use ckb_types::{
+    core::{cell::resolve_transaction, EpochNumberWithFraction},
+    packed::{Byte32, Transaction},
+    H256,
 };
-use ckb_types::{core::cell::resolve_transaction, packed::Byte32};
 use ckb_vm::{
-    decoder::build_decoder, Bytes, CoreMachine, DefaultCoreMachine, DefaultMachineBuilder, SparseMemory,
-    SupportMachine, WXorXMemory,
+    decoder::build_decoder, Bytes, CoreMachine, DefaultCoreMachine, DefaultMachine, DefaultMachineBuilder,
+    SparseMemory, SupportMachine, Syscalls, WXorXMemory,
 };
 #[cfg(feature = "stdio")]
 use ckb_vm_debug_utils::Stdio;
-use ckb_vm_debug_utils::{ElfDumper, GdbHandler};
-use ckb_vm_pprof::{PProfMachine, Profile};
-use clap::{crate_version, App, Arg};
+use ckb_vm_debug_utils::{
+    disassemble, process_packets_with_reverse, ElfDumper, GdbHandler, TraceReader, TraceRecorder,
+};
+use ckb_vm_pprof::{Coverage, PProfMachine, Profile};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
 use faster_hex::hex_decode_fallback;
-use gdb_remote_protocol::process_packets_from;
-use regex::Regex;
 use serde_json::from_str as from_json_str;
 use serde_plain::from_str as from_plain_str;
+use std::cell::RefCell;
 use std::collections::HashSet;
-use std::fs::{read, read_to_string};
+use std::fs::{read, read_to_string, File};
+use std::io::{BufReader, BufWriter, Write};
 use std::net::TcpListener;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+mod baseline;
+mod fuzz;
+mod init;
+mod inspect;
 mod misc;
-use misc::{FileOperation, FileStream, HumanReadableCycles, Random, TimeNow};
+mod repl;
+mod replay;
+mod report;
+mod sign;
+mod strace;
+mod suite;
+mod template;
+use baseline::{Baseline, Threshold};
+use fuzz::{Execution, FuzzTarget, Fuzzer};
+use init::{scaffold, ScriptSpec};
+use misc::{Clock, FileOperation, FileStream, FileStreams, HumanReadableCycles, Random, SyscallSettings, TimeNow};
+use repl::Repl;
+use replay::RecordedSyscalls;
+use report::{DebugMessage, GroupReport, ReplayReport, TxReport};
+use strace::{SyscallTrace, SyscallTracer, TraceFormat};
+
+type CoreMachineType = DefaultCoreMachine<u64, WXorXMemory<SparseMemory<u64>>>;
+
+// The syscalls the debugger serves on top of those of ckb-script.
+fn debugger_syscalls<'a>(
+    fs_syscall: Option<FileStreams>,
+    syscall_settings: SyscallSettings,
+) -> Vec<Box<dyn Syscalls<CoreMachineType> + 'a>> {
+    let mut syscalls: Vec<Box<dyn Syscalls<CoreMachineType> + 'a>> = vec![];
+    if let Some(fs) = fs_syscall {
+        syscalls.push(Box::new(fs));
+    }
+    syscalls.push(Box::new(TimeNow::new(syscall_settings.clock)));
+    syscalls.push(Box::new(Random::new(syscall_settings.seed)));
+    syscalls.push(Box::new(FileOperation::new(syscall_settings)));
+    syscalls
+}
+
+fn machine_init<'a>(
+    verifier: &'a TransactionScriptsVerifier<'a, Resource>,
+    script_group: &'a ScriptGroup,
+    script_version: ScriptVersion,
+    max_cycles: u64,
+    dump_file: Option<&str>,
+    debugger_syscalls: Vec<Box<dyn Syscalls<CoreMachineType> + 'a>>,
+    trace: Option<SyscallTrace>,
+) -> DefaultMachine<'a, CoreMachineType> {
+    let machine_core = CoreMachineType::new(script_version.vm_isa(), script_version.vm_version(), max_cycles);
+    let mut syscalls: Vec<Box<dyn Syscalls<CoreMachineType> + 'a>> = vec![];
+    #[cfg(feature = "stdio")]
+    syscalls.push(Box::new(Stdio::new(false)));
+    if let Some(data) = dump_file {
+        syscalls.push(Box::new(ElfDumper::new(data.to_string(), 4097, 64)));
+    }
+    syscalls.extend(verifier.generate_syscalls(script_version, script_group));
+    syscalls.extend(debugger_syscalls);
+    let machine_builder = DefaultMachineBuilder::new(machine_core).instruction_cycle_func(&instruction_cycles);
+    // The tracer sees every ecall first and hands it to the other syscalls.
+    let machine_builder = match trace {
+        Some(trace) => {
+            let script_hash = script_group.script.calc_script_hash();
+            machine_builder.syscall(Box::new(SyscallTracer::new(syscalls, trace, script_hash)))
+        }
+        None => syscalls.into_iter().fold(machine_builder, |builder, syscall| builder.syscall(syscall)),
+    };
+    machine_builder.build()
+}
+
+// Cells and headers missing from the mock transaction are fetched from the node if one is given.
+fn load_resource(mock_tx: &MockTransaction, rpc_loader: Option<&RpcResourceLoader>) -> Result<Resource, String> {
+    match rpc_loader {
+        Some(loader) => Resource::from_both(mock_tx, loader.clone()),
+        None => Resource::from_both(mock_tx, DummyResourceLoader {}),
+    }
+}
+
+// Hash of the script selected with --cell-type and --cell-index, an error naming the selection if there is no such
+// script.
+fn cell_script_hash(
+    mock_tx: &MockTransaction,
+    script_group_type: ScriptGroupType,
+    cell_type: &str,
+    cell_index: usize,
+) -> Result<Byte32, String> {
+    let output = match cell_type {
+        "input" => mock_tx.mock_info.inputs.get(cell_index).map(|input| input.output.clone()),
+        "output" => mock_tx.tx.raw().outputs().get(cell_index),
+        _ => return Err(format!("Invalid cell type {}, expected input or output", cell_type)),
+    };
+    let output = output.ok_or_else(|| format!("The transaction has no {} {}", cell_type, cell_index))?;
+    match (script_group_type, cell_type) {
+        (ScriptGroupType::Lock, "input") => Ok(output.calc_lock_hash()),
+        (ScriptGroupType::Type, _) => output
+            .type_()
+            .to_opt()
+            .map(|script| script.calc_script_hash())
+            .ok_or_else(|| format!("{} {} has no type script", cell_type, cell_index)),
+        _ => Err(format!(
+            "Lock scripts of outputs are not run, {} {} has no lock script group",
+            cell_type, cell_index
+        )),
+    }
+}
+
+// An epoch is given either as number,index,length or as the packed value in hex, e.g. 0x3e8000f00012c.
+fn parse_epoch(s: &str) -> Result<EpochNumberWithFraction, String> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        let value = u64::from_str_radix(hex, 16).map_err(|e| format!("invalid epoch {}: {}", s, e))?;
+        return Ok(EpochNumberWithFraction::from_full_value(value));
+    }
+    let parts: Vec<u64> = s
+        .split(',')
+        .map(|p| p.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid epoch {}: {}", s, e))?;
+    match parts[..] {
+        [number, index, length] => Ok(EpochNumberWithFraction::new(number, index, length)),
+        _ => Err(format!("invalid epoch {}: expected number,index,length", s)),
+    }
+}
+
+// Coverage files are accumulated, so several runs can be merged into one report.
+fn read_coverage(path: &str) -> Result<Coverage, Box<dyn std::error::Error>> {
+    if Path::new(path).exists() {
+        Coverage::read_lcov(BufReader::new(File::open(path)?))
+    } else {
+        Ok(Coverage::new())
+    }
+}
+
+// Printed with the results so that a run using the time or random syscalls can be replayed.
+fn print_syscall_settings(settings: &SyscallSettings) {
+    println!("Random seed: {}", settings.seed);
+    if let Clock::Virtual { base, ns_per_cycle } = settings.clock {
+        println!("Virtual clock: base {} ns, {} ns per cycle", base, ns_per_cycle);
+    }
+}
+
+// Compares measured cycles with the baseline file, or writes them to it when updating. `all_groups` tells whether
+// every script group was measured, otherwise the groups measured are updated in the file and the others kept, and the
+// total is not compared. Returns false when cycles grew over the threshold. Differences go to stderr with JSON output,
+// which must stay parseable.
+fn check_baseline(
+    path: &str,
+    measured: &Baseline,
+    threshold: Threshold,
+    update: bool,
+    all_groups: bool,
+    output_json: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let path = Path::new(path);
+    let print = |line: &str| {
+        if output_json {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+    if update {
+        if all_groups || !path.exists() {
+            measured.write(path)?;
+        } else {
+            let mut baseline = Baseline::read(path)?;
+            baseline.update(measured);
+            baseline.write(path)?;
+        }
+        print(&format!("Baseline {} updated", path.display()));
+        return Ok(true);
+    }
+    if !path.exists() {
+        return Err(format!(
+            "Baseline {} doesn't exist, create it with --update-baseline",
+            path.display()
+        )
+        .into());
+    }
+    let comparison = baseline::compare(&Baseline::read(path)?, measured, threshold, all_groups);
+    for line in &comparison.lines {
+        print(line);
+    }
+    if comparison.regressed {
+        print(&format!("Cycles exceed baseline {}", path.display()));
+    }
+    Ok(!comparison.regressed)
+}
+
+fn check(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_file = matches.value_of("tx-file").unwrap();
+    let root = Path::new(tx_file).parent().unwrap_or_else(|| Path::new("."));
+    let mock_tx = template::render(&read_to_string(tx_file)?, root)?;
+    let repr_mock_tx: ReprMockTransaction = from_json_str(&mock_tx)?;
+    let problems = lint(&repr_mock_tx);
+    if problems.is_empty() {
+        println!("No problems found in {}", tx_file);
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!("{} problems found in {}", problems.len(), tx_file);
+    std::process::exit(1);
+}
+
+fn fetch_tx(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mut loader = RpcResourceLoader::new(matches.value_of("rpc").unwrap());
+    if let Some(dir) = matches.value_of("rpc-cache") {
+        loader = loader.with_cache_dir(dir);
+    }
+    let tx_arg = matches.value_of("tx").unwrap();
+    let tx: Transaction = if tx_arg.len() == 66 && tx_arg.starts_with("0x") && !Path::new(tx_arg).exists() {
+        let tx_hash: H256 = from_plain_str(tx_arg)?;
+        loader.get_transaction(&tx_hash)?.ok_or_else(|| format!("Transaction {} is not known to the node", tx_arg))?
+    } else {
+        // Accept both a bare transaction and the transaction view returned by the RPC, which carries its hash.
+        let mut value: serde_json::Value = from_json_str(&read_to_string(tx_arg)?)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("hash");
+        }
+        serde_json::from_value::<ckb_jsonrpc_types::Transaction>(value)?.into()
+    };
+    let mock_tx = MockTransaction::from_loader(tx, loader)?;
+    let mock_tx = serde_json::to_string_pretty(&ReprMockTransaction::from(mock_tx))?;
+    match matches.value_of("output") {
+        Some(output) => std::fs::write(output, mock_tx + "\n")?,
+        None => println!("{}", mock_tx),
+    }
+    Ok(())
+}
+
+fn inspect_tx(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_file = matches.value_of("tx-file").unwrap();
+    let root = Path::new(tx_file).parent().unwrap_or_else(|| Path::new("."));
+    let mock_tx = template::render(&read_to_string(tx_file)?, root)?;
+    let repr_mock_tx: ReprMockTransaction = from_json_str(&mock_tx)?;
+    print!("{}", inspect::inspect(&repr_mock_tx.into()));
+    Ok(())
+}
+
+fn sign_tx(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let tx_file = matches.value_of("tx-file").unwrap();
+    let input_index: usize = matches.value_of("input-index").unwrap().parse()?;
+    let root = Path::new(tx_file).parent().unwrap_or_else(|| Path::new("."));
+    let mock_tx = template::render(&read_to_string(tx_file)?, root)?;
+    let repr_mock_tx: ReprMockTransaction = from_json_str(&mock_tx)?;
+    let mock_tx: MockTransaction = repr_mock_tx.into();
+    let privkey_file = match matches.value_of("privkey") {
+        Some(privkey_file) => privkey_file,
+        None => {
+            println!("{:#x}", sign::sighash_all_message(&mock_tx, input_index)?);
+            return Ok(());
+        }
+    };
+    // Key files hold the key in hex, as written by ckb-cli.
+    let privkey_hex = read_to_string(privkey_file)?;
+    let privkey_hex = privkey_hex.lines().next().unwrap_or_default().trim();
+    let privkey_bytes = hex::decode(privkey_hex.trim_start_matches("0x"))?;
+    if privkey_bytes.len() != 32 {
+        return Err(format!("Private key in {} should be 32 bytes", privkey_file).into());
+    }
+    let privkey = Privkey::from_slice(&privkey_bytes);
+    let signed = sign::sign(&mock_tx, input_index, &privkey)?;
+    let signed = serde_json::to_string_pretty(&ReprMockTransaction::from(signed))?;
+    match matches.value_of("output") {
+        Some(output) => std::fs::write(output, signed + "\n")?,
+        None => println!("{}", signed),
+    }
+    Ok(())
+}
+
+fn run_tests(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest_file = matches.value_of("manifest").unwrap();
+    let manifest: suite::Manifest = from_json_str(&read_to_string(manifest_file)?)?;
+    let base = Path::new(manifest_file).parent().unwrap_or_else(|| Path::new("."));
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => jobs.parse()?,
+        None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    };
+    let outcomes = suite::run(&manifest, base, jobs);
+    if let Some(path) = matches.value_of("junit") {
+        std::fs::write(path, suite::junit(&outcomes))?;
+    }
+    let width = outcomes.iter().map(|o| o.name.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:<width$}  {:<6}  {:<16}  {}",
+        "Case",
+        "Result",
+        "Cycles",
+        "Time",
+        width = width
+    );
+    for outcome in &outcomes {
+        let result = match outcome.status {
+            suite::Status::Pass => "pass",
+            suite::Status::Fail(_) => "FAIL",
+            suite::Status::Error(_) => "ERROR",
+        };
+        let cycles = outcome.cycles.map(|c| HumanReadableCycles(c).to_string()).unwrap_or_default();
+        println!(
+            "{:<width$}  {:<6}  {:<16}  {:.3}s",
+            outcome.name,
+            result,
+            cycles,
+            outcome.time.as_secs_f64(),
+            width = width
+        );
+    }
+    let failed: Vec<&suite::Outcome> = outcomes.iter().filter(|o| !matches!(o.status, suite::Status::Pass)).collect();
+    for outcome in &failed {
+        if let suite::Status::Fail(message) | suite::Status::Error(message) = &outcome.status {
+            println!("{}:", outcome.name);
+            for line in message.lines() {
+                println!("  {}", line);
+            }
+        }
+    }
+    println!("{} passed, {} failed", outcomes.len() - failed.len(), failed.len());
+    if !failed.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn trace_dump(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let parse_hex = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16);
+    let start_pc = matches.value_of("start-pc").map(parse_hex).transpose()?;
+    let end_pc = matches.value_of("end-pc").map(parse_hex).transpose()?;
+    let address = matches.value_of("address").map(parse_hex).transpose()?;
+    let skip: usize = matches.value_of("skip").unwrap().parse()?;
+    let limit: Option<usize> = matches.value_of("limit").map(|s| s.parse()).transpose()?;
+    let reader = TraceReader::new(BufReader::new(File::open(matches.value_of("trace-file").unwrap())?))?;
+    let mut printed = 0;
+    for (index, record) in reader.enumerate().skip(skip) {
+        if limit.map_or(false, |limit| printed >= limit) {
+            break;
+        }
+        let record = record?;
+        if start_pc.map_or(false, |pc| record.pc < pc) || end_pc.map_or(false, |pc| record.pc >= pc) {
+            continue;
+        }
+        if address.map_or(false, |addr| !record.writes_memory(addr)) {
+            continue;
+        }
+        println!("{:>10} {}", index, record);
+        printed += 1;
+    }
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     drop(env_logger::init());
@@ -39,6 +394,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let matches = App::new("ckb-debugger")
         .version(crate_version!())
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("all-script-groups")
+                .long("all-script-groups")
+                .conflicts_with_all(&["bin", "cell-index", "cell-type", "script-hash", "script-group-type"])
+                .help("Run every lock and type script group in the transaction and print a summary"),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .help("JSON file with the expected cycles, exit with 1 when cycles grow over --baseline-threshold")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("baseline-functions")
+                .long("baseline-functions")
+                .requires("baseline")
+                .help("Also compare the self cycles of each function with the baseline, only available in full mode"),
+        )
+        .arg(
+            Arg::with_name("baseline-threshold")
+                .long("baseline-threshold")
+                .requires("baseline")
+                .help("How many cycles may be added to a baseline value, as <cycles> or <percent>%, 0 if not given")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("bin")
                 .long("bin")
@@ -53,7 +434,62 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Type of cell to run")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .help("Add the lines executed in full mode to an lcov tracefile, which is created if missing")
+                .takes_value(true),
+        )
         .arg(Arg::with_name("dump-file").long("dump-file").help("Dump file name").takes_value(true))
+        .arg(
+            Arg::with_name("fs-host")
+                .long("fs-host")
+                .help("Let the file syscalls of scripts reach host files"),
+        )
+        .arg(
+            Arg::with_name("fs-root")
+                .long("fs-root")
+                .help(
+                    "Directory the file syscalls are confined to, paths opened by scripts are resolved inside it and \
+                     may be written",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fuzz-exit-code")
+                .long("fuzz-exit-code")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Save inputs for which the script exits with this code in fuzz mode, can be repeated")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fuzz-iterations")
+                .long("fuzz-iterations")
+                .default_value("10000")
+                .help("Number of mutated transactions to run in fuzz mode, 0 to run until interrupted")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fuzz-output")
+                .long("fuzz-output")
+                .default_value("fuzz")
+                .help("Directory where fuzz mode saves the transactions it finds")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fuzz-target")
+                .long("fuzz-target")
+                .multiple(true)
+                .number_of_values(1)
+                .required_if("mode", "fuzz")
+                .help(
+                    "Bytes changed in fuzz mode, can be repeated: witness:<i>, input-data:<i>, output-data:<i>, \
+                     cell-dep-data:<i>, input-lock-args:<i>, input-type-args:<i>, output-lock-args:<i> or \
+                     output-type-args:<i>",
+                )
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("gdb-listen")
                 .long("gdb-listen")
@@ -71,17 +507,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::with_name("mode")
                 .long("mode")
                 .help("Execution mode of debugger")
-                .possible_values(&["full", "fast", "gdb"])
+                .possible_values(&["full", "fast", "gdb", "repl", "fuzz"])
                 .default_value(&default_mode)
                 .required(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("output-format")
+                .long("output-format")
+                .help("Format of the run result")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("pprof")
                 .long("pprof")
+                .conflicts_with("all-script-groups")
                 .help("Performance profiling, specify output file for further use")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("rpc")
+                .long("rpc")
+                .help("CKB node RPC URL to fetch cells and headers missing from the transaction file from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rpc-cache")
+                .long("rpc-cache")
+                .requires("rpc")
+                .help("Directory to cache committed transactions and headers fetched with --rpc in")
+                .takes_value(true),
+        )
         .arg(Arg::with_name("script-hash").long("script-hash").help("Script hash").takes_value(true))
         .arg(
             Arg::with_name("script-group-type")
@@ -97,6 +555,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Script version")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed of the random syscall, a random one is picked and printed if not given")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("skip-end")
                 .long("skip-end")
@@ -112,8 +576,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .arg(
             Arg::with_name("step")
                 .long("step")
+                .conflicts_with("all-script-groups")
                 .multiple(true)
-                .help("Set to true to enable step mode, where we print PC address for each instruction"),
+                .help("Set to true to enable step mode, where we print PC address, instruction and source line for each instruction"),
+        )
+        .arg(
+            Arg::with_name("tip-epoch")
+                .long("tip-epoch")
+                .requires("verify-consensus")
+                .help("Epoch of the tip block for --verify-consensus, as number,index,length or packed in hex")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tip-median-time")
+                .long("tip-median-time")
+                .requires("verify-consensus")
+                .help("Median time of the blocks before the tip in milliseconds for --verify-consensus")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tip-number")
+                .long("tip-number")
+                .requires("verify-consensus")
+                .help("Number of the tip block for --verify-consensus")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-file")
+                .long("trace-file")
+                .conflicts_with("all-script-groups")
+                .help("Record every executed instruction to a binary trace file, only available in full mode")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-syscalls")
+                .long("trace-syscalls")
+                .help("Log every syscall made by scripts with its arguments, result and cycles to a file, - for stdout")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-syscalls-format")
+                .long("trace-syscalls-format")
+                .requires("trace-syscalls")
+                .possible_values(&["text", "jsonl"])
+                .default_value("text")
+                .help("Format of the syscall log")
+                .takes_value(true),
         )
         .arg(
             Arg::with_name("tx-file")
@@ -125,7 +633,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .arg(
             Arg::with_name("read-file")
                 .long("read-file")
-                .help("Read content from local file or stdin. Then feed the content to syscall in scripts")
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Read content from local file or stdin. Then feed the content to syscall in scripts. Can be \
+                     repeated as <name>=<path> to give scripts several streams",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("update-baseline")
+                .long("update-baseline")
+                .requires("baseline")
+                .help("Write the measured cycles to the --baseline file instead of comparing them"),
+        )
+        .arg(
+            Arg::with_name("verify-consensus")
+                .long("verify-consensus")
+                .help("Check the transaction against the consensus rules besides scripts before running them")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("virtual-clock")
+                .long("virtual-clock")
+                .help(
+                    "Make the time syscall return this many nanoseconds since the epoch plus a step for every cycle \
+                     consumed, now to start from the current time",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("virtual-clock-step")
+                .long("virtual-clock-step")
+                .requires("virtual-clock")
+                .help("Nanoseconds the virtual clock advances for every cycle, 1 if not given")
                 .takes_value(true),
         )
         .arg(
@@ -136,65 +677,510 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("long log message with script group"),
         )
         .arg(Arg::with_name("args").multiple(true))
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check that a transaction file has everything needed to run its scripts")
+                .arg(Arg::with_name("tx-file").required(true).help("Filename containing JSON formatted transaction dump")),
+        )
+        .subcommand(
+            SubCommand::with_name("fetch-tx")
+                .about("Generate a mock transaction for an existing transaction, loading what it references from a node")
+                .arg(
+                    Arg::with_name("tx")
+                        .required(true)
+                        .help("Hash of the transaction, or a file containing the transaction in JSON"),
+                )
+                .arg(
+                    Arg::with_name("rpc")
+                        .long("rpc")
+                        .required(true)
+                        .help("CKB node RPC URL")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("rpc-cache")
+                        .long("rpc-cache")
+                        .help("Directory to cache committed transactions and headers in")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("File to write the mock transaction to, printed to stdout if not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Generate a mock transaction running the given script binaries")
+                .arg(
+                    Arg::with_name("lock")
+                        .long("lock")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .help("Lock script as <binary>[,<hash type>[,<args>]], hash type defaults to data1")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Type script as <binary>[,<hash type>[,<args>]], hash type defaults to data1")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("File to write the transaction to, printed to stdout if not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Print the cells, scripts, witnesses and script groups of a transaction file")
+                .arg(Arg::with_name("tx-file").required(true).help("Filename containing JSON formatted transaction dump")),
+        )
+        .subcommand(
+            SubCommand::with_name("sign")
+                .about("Print the sighash-all message of the lock script group of an input, or sign it with a key")
+                .arg(Arg::with_name("tx-file").required(true).help("Filename containing JSON formatted transaction dump"))
+                .arg(
+                    Arg::with_name("input-index")
+                        .long("input-index")
+                        .required(true)
+                        .help("Index of an input in the script group to sign")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("privkey")
+                        .long("privkey")
+                        .help("File holding the secp256k1 private key in hex, the signed transaction is written if given")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .requires("privkey")
+                        .help("File to write the signed transaction to, printed to stdout if not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run the cases listed in a manifest and check their outcome")
+                .arg(Arg::with_name("manifest").required(true).help("JSON file listing the cases"))
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .help("Number of cases run in parallel, the number of CPUs if not given")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("junit")
+                        .long("junit")
+                        .help("Write a JUnit XML report to this file")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trace")
+                .about("Work with trace files recorded by --trace-file")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("dump")
+                        .about("Print the records of a trace file")
+                        .arg(Arg::with_name("trace-file").required(true).help("Trace file to read"))
+                        .arg(
+                            Arg::with_name("start-pc")
+                                .long("start-pc")
+                                .help("Only print instructions at or above this hex address")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("end-pc")
+                                .long("end-pc")
+                                .help("Only print instructions below this hex address")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("address")
+                                .long("address")
+                                .help("Only print instructions writing to this hex memory address")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("skip")
+                                .long("skip")
+                                .default_value("0")
+                                .help("Number of records to skip from the start of the trace")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("limit")
+                                .long("limit")
+                                .help("Maximum number of records to print")
+                                .takes_value(true),
+                        ),
+                ),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("check") {
+        return check(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("fetch-tx") {
+        return fetch_tx(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("init") {
+        let locks = matches.values_of("lock").unwrap().map(|s| s.parse()).collect::<Result<Vec<ScriptSpec>, _>>()?;
+        let types = matches
+            .values_of("type")
+            .into_iter()
+            .flatten()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<ScriptSpec>, _>>()?;
+        let output = matches.value_of("output");
+        let base = match output.and_then(|o| Path::new(o).parent()) {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mock_tx = serde_json::to_string_pretty(&scaffold(&locks, &types, &base)?)?;
+        match output {
+            Some(output) => std::fs::write(output, mock_tx + "\n")?,
+            None => println!("{}", mock_tx),
+        }
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("inspect") {
+        return inspect_tx(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("sign") {
+        return sign_tx(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("test") {
+        return run_tests(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("trace") {
+        if let Some(matches) = matches.subcommand_matches("dump") {
+            return trace_dump(matches);
+        }
+        return Ok(());
+    }
+
+    let matches_all_script_groups = matches.is_present("all-script-groups");
+    let matches_baseline = matches.value_of("baseline");
+    let matches_baseline_functions = matches.is_present("baseline-functions");
+    let matches_baseline_threshold: Threshold = matches.value_of("baseline-threshold").unwrap_or("0").parse()?;
+    let matches_update_baseline = matches.is_present("update-baseline");
     let matches_bin = matches.value_of("bin");
     let matches_cell_index = matches.value_of("cell-index");
     let matches_cell_type = matches.value_of("cell-type");
+    let matches_output_format = matches.value_of("output-format").unwrap();
+    let matches_coverage = matches.value_of("coverage");
     let matches_pprof = matches.value_of("pprof");
     let matches_dump_file = matches.value_of("dump-file");
+    let matches_fs_host = matches.is_present("fs-host");
+    let matches_fs_root = matches.value_of("fs-root");
+    let matches_fuzz_exit_codes = matches.values_of("fuzz-exit-code").unwrap_or_default();
+    let matches_fuzz_iterations = matches.value_of("fuzz-iterations").unwrap();
+    let matches_fuzz_output = matches.value_of("fuzz-output").unwrap();
+    let matches_fuzz_targets = matches.values_of("fuzz-target").unwrap_or_default();
     let matches_gdb_listen = matches.value_of("gdb-listen");
     let matches_max_cycles = matches.value_of("max-cycles").unwrap();
     let matches_mode = matches.value_of("mode").unwrap();
+    let matches_rpc = matches.value_of("rpc");
+    let matches_rpc_cache = matches.value_of("rpc-cache");
     let matches_script_hash = matches.value_of("script-hash");
+    let matches_seed = matches.value_of("seed");
     let matches_script_group_type = matches.value_of("script-group-type");
     let matches_script_version = matches.value_of("script-version").unwrap();
     let matches_skip_end = matches.value_of("skip-end");
     let matches_skip_start = matches.value_of("skip-start");
     let matches_step = matches.occurrences_of("step");
+    let matches_tip_epoch = matches.value_of("tip-epoch");
+    let matches_tip_median_time = matches.value_of("tip-median-time");
+    let matches_tip_number = matches.value_of("tip-number");
+    let matches_trace_file = matches.value_of("trace-file");
+    let matches_trace_syscalls = matches.value_of("trace-syscalls");
+    let matches_trace_syscalls_format = matches.value_of("trace-syscalls-format").unwrap();
+    let matches_virtual_clock = matches.value_of("virtual-clock");
+    let matches_virtual_clock_step = matches.value_of("virtual-clock-step");
     let matches_tx_file = matches.value_of("tx-file");
     let matches_args = matches.values_of("args").unwrap_or_default();
-    let read_file_name = matches.value_of("read-file");
+    let read_file_names = matches.values_of("read-file");
+    let verify_consensus_rules = matches.is_present("verify-consensus");
     let long_log = matches.is_present("long-log");
+    let output_json = matches_output_format == "json";
+    if matches_trace_file.is_some() && matches_mode != "full" {
+        return Err(format!(
+            "--trace-file is only available in full mode, not in {} mode",
+            matches_mode
+        )
+        .into());
+    }
 
     let verifier_args: Vec<String> = matches_args.into_iter().map(|s| s.clone().into()).collect();
     let verifier_args_byte: Vec<Bytes> = verifier_args.into_iter().map(|s| s.into()).collect();
 
-    let fs_syscall = if let Some(file_name) = read_file_name {
-        Some(FileStream::new(file_name))
-    } else {
-        None
-    };
-
-    let verifier_max_cycles: u64 = matches_max_cycles.parse()?;
-    let verifier_mock_tx: MockTransaction = {
+    let verifier_repr_mock_tx: ReprMockTransaction = {
         let mock_tx = if matches_tx_file.is_none() {
             String::from_utf8_lossy(include_bytes!("./dummy_tx.json")).to_string()
         } else {
             read_to_string(matches_tx_file.unwrap())?
         };
-        let mock_re = Regex::new(r"\{\{ ?([a-z]+) (.+?) ?\}\}").unwrap();
-        let mock_tx = mock_re.replace_all(&mock_tx, |caps: &regex::Captures| -> String {
-            let cap1 = &caps[1];
-            let cap2 = &caps[2];
-            let path = if !Path::new(cap2).is_absolute() {
-                let root = Path::new(matches_tx_file.unwrap()).parent().unwrap();
-                root.join(cap2)
+        let root = match matches_tx_file.and_then(|f| Path::new(f).parent()) {
+            Some(parent) => parent.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        let mock_tx = template::render(&mock_tx, &root)?;
+        from_json_str(&mock_tx)?
+    };
+    let verifier_mock_tx: MockTransaction = verifier_repr_mock_tx.clone().into();
+    let verifier_max_cycles: u64 = matches_max_cycles.parse()?;
+    let syscall_settings = SyscallSettings {
+        seed: match matches_seed {
+            Some(seed) => seed.parse()?,
+            None => rand::random(),
+        },
+        clock: match matches_virtual_clock {
+            Some("now") => Clock::Virtual {
+                base: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_nanos() as u64,
+                ns_per_cycle: matches_virtual_clock_step.unwrap_or("1").parse()?,
+            },
+            Some(base) => Clock::Virtual {
+                base: base.parse()?,
+                ns_per_cycle: matches_virtual_clock_step.unwrap_or("1").parse()?,
+            },
+            None => Clock::Real,
+        },
+        fs_root: matches_fs_root.map(PathBuf::from),
+        files: verifier_mock_tx.mock_info.files.clone(),
+        fs_host: matches_fs_host,
+        trace: match matches_trace_syscalls {
+            Some(path) => {
+                let writer: Box<dyn Write> = match path {
+                    "-" => Box::new(std::io::stdout()),
+                    _ => Box::new(File::create(path)?),
+                };
+                let format = match matches_trace_syscalls_format {
+                    "jsonl" => TraceFormat::JsonLines,
+                    _ => TraceFormat::Text,
+                };
+                Some(SyscallTrace::new(writer, format))
+            }
+            None => None,
+        },
+    };
+    let fs_syscall = read_file_names
+        .map(|names| names.map(|name| FileStream::open(name, &syscall_settings)).collect::<Result<Vec<_>, _>>())
+        .transpose()?
+        .map(FileStreams::new)
+        .transpose()?;
+    let verifier_script_version = match matches_script_version {
+        "0" => ScriptVersion::V0,
+        "1" => ScriptVersion::V1,
+        _ => panic!("wrong script version"),
+    };
+    let rpc_loader = matches_rpc.map(|url| match matches_rpc_cache {
+        Some(dir) => RpcResourceLoader::new(url).with_cache_dir(dir),
+        None => RpcResourceLoader::new(url),
+    });
+    let verifier_resource = load_resource(&verifier_mock_tx, rpc_loader.as_ref())?;
+    let verifier_resolve_transaction = resolve_transaction(
+        verifier_mock_tx.core_transaction(),
+        &mut HashSet::new(),
+        &verifier_resource,
+        &verifier_resource,
+    )?;
+    if verify_consensus_rules {
+        let tip = ChainTip {
+            block_number: matches_tip_number.map(|n| n.parse()).transpose()?,
+            epoch: matches_tip_epoch.map(parse_epoch).transpose()?,
+            median_time: matches_tip_median_time.map(|t| t.parse()).transpose()?,
+        };
+        let violations = verify_consensus(&verifier_resolve_transaction, &verifier_resource, &tip);
+        if !violations.is_empty() {
+            for violation in &violations {
+                println!("{}", violation);
+            }
+            println!("{} consensus rules violated", violations.len());
+            std::process::exit(1);
+        }
+    }
+    let mut verifier = TransactionScriptsVerifier::new(&verifier_resolve_transaction, &verifier_resource);
+    let debug_messages: Rc<RefCell<Vec<DebugMessage>>> = Rc::new(RefCell::new(vec![]));
+    let debug_messages_printer = debug_messages.clone();
+    verifier.set_debug_printer(Box::new(move |hash: &Byte32, message: &str| {
+        if output_json {
+            debug_messages_printer.borrow_mut().push(DebugMessage::new(hash, message));
+        } else if long_log {
+            debug!("script group: {} DEBUG OUTPUT: {}", hash, message);
+        } else {
+            debug!("SCRIPT>{}", message);
+        }
+    }));
+
+    if matches_all_script_groups {
+        if matches_mode != "full" && matches_mode != "fast" {
+            return Err(format!(
+                "--all-script-groups is only available in full and fast mode, not in {} mode",
+                matches_mode
+            )
+            .into());
+        }
+        let mut total_cycles = 0u64;
+        let mut reports = vec![];
+        let mut measured = Baseline::default();
+        let mut coverage = matches_coverage.map(read_coverage).transpose()?;
+        for (script_hash, script_group) in verifier.groups() {
+            let program = verifier.extract_script(&script_group.script)?;
+            let remaining_cycles = verifier_max_cycles.saturating_sub(total_cycles);
+            let machine = machine_init(
+                &verifier,
+                script_group,
+                verifier_script_version,
+                remaining_cycles,
+                matches_dump_file,
+                debugger_syscalls(fs_syscall.clone(), syscall_settings.clone()),
+                syscall_settings.trace.clone(),
+            );
+            let mut transferred_cycles = 0;
+            let (result, cycles, trace, functions) = if matches_mode == "full" {
+                let mut machine = PProfMachine::new(machine, Profile::new(&program)?);
+                if coverage.is_some() {
+                    machine.profile.enable_coverage();
+                }
+                let result = machine
+                    .load_program(&program, &verifier_args_byte)
+                    .and_then(|bytes| {
+                        transferred_cycles = transferred_byte_cycles(bytes);
+                        machine.machine.add_cycles(transferred_cycles)
+                    })
+                    .and_then(|_| machine.run());
+                if let (Some(coverage), Some(pc_counts)) = (&mut coverage, machine.profile.pc_counts()) {
+                    coverage.add_run(&program, pc_counts)?;
+                }
+                let mut trace = vec![];
+                if result.is_err() {
+                    machine.profile.display_stacktrace("", &mut trace);
+                }
+                let functions = if matches_baseline_functions {
+                    Some(machine.profile.function_cycles())
+                } else {
+                    None
+                };
+                (result, machine.machine.cycles(), trace, functions)
             } else {
-                Path::new(cap2).to_path_buf()
+                let mut machine = machine;
+                let result = machine
+                    .load_program(&program, &verifier_args_byte)
+                    .and_then(|bytes| {
+                        transferred_cycles = transferred_byte_cycles(bytes);
+                        machine.add_cycles(transferred_cycles)
+                    })
+                    .and_then(|_| machine.run());
+                (result, machine.cycles(), vec![], None)
             };
-            let data = std::fs::read(path).unwrap();
-            if cap1 == "data" {
-                return format!("0x{}", hex::encode(data));
+            total_cycles = total_cycles.saturating_add(cycles);
+            measured.add_group(&format!("0x{:x}", script_hash), cycles, functions);
+            reports.push(GroupReport::new(
+                script_hash,
+                script_group,
+                &result,
+                cycles,
+                transferred_cycles,
+                &trace,
+            ));
+        }
+        if let (Some(coverage), Some(path)) = (&coverage, matches_coverage) {
+            coverage.write_lcov(&mut File::create(path)?)?;
+        }
+        let exceeded = total_cycles > verifier_max_cycles;
+        let failed = reports.iter().any(|r| !r.is_success());
+        if output_json {
+            let report = TxReport {
+                groups: reports,
+                total_cycles,
+                max_cycles: verifier_max_cycles,
+                debug_messages: debug_messages.borrow().clone(),
+                replay: ReplayReport::from(&syscall_settings),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!(
+                "{:<66}  {:<4}  {:<12}  {:<12}  {:<6}  {}",
+                "Script hash", "Type", "Inputs", "Outputs", "Result", "Cycles"
+            );
+            for report in &reports {
+                println!(
+                    "{}  {:<4}  {:<12}  {:<12}  {:<6}  {}",
+                    report.script_hash,
+                    report.script_group_type,
+                    format!("{:?}", report.input_indices),
+                    format!("{:?}", report.output_indices),
+                    match report.exit_code {
+                        Some(exit_code) => format!("{}", exit_code),
+                        None => String::from("error"),
+                    },
+                    HumanReadableCycles(report.cycles.total)
+                );
             }
-            if cap1 == "hash" {
-                return format!("0x{}", hex::encode(&blake2b_256(data)));
+            println!(
+                "Total cycles consumed: {} of max cycles {}",
+                HumanReadableCycles(total_cycles),
+                HumanReadableCycles(verifier_max_cycles)
+            );
+            print_syscall_settings(&syscall_settings);
+            if exceeded {
+                println!("Error:");
+                println!("  Total cycles exceed max cycles");
             }
-            unreachable!()
-        });
-        let repr_mock_tx: ReprMockTransaction = from_json_str(&mock_tx)?;
-        repr_mock_tx.into()
-    };
+            for report in reports.iter().filter(|r| !r.is_success()) {
+                println!("Script group {} failed:", report.script_hash);
+                if !report.stacktrace.is_empty() {
+                    println!("Trace:");
+                    for line in &report.stacktrace {
+                        println!("  {}", line);
+                    }
+                }
+                println!("Error:");
+                match (&report.error, report.exit_code) {
+                    (Some(err), _) => println!("  {}", err),
+                    (None, Some(exit_code)) => println!("  Run result: {}", exit_code),
+                    (None, None) => {}
+                }
+            }
+        }
+        // A failed run says nothing about the cycles the scripts need, it is neither compared nor recorded.
+        let regressed = match matches_baseline {
+            Some(path) if !failed => !check_baseline(
+                path,
+                &measured,
+                matches_baseline_threshold,
+                matches_update_baseline,
+                true,
+                output_json,
+            )?,
+            _ => false,
+        };
+        if exceeded || failed || regressed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let verifier_script_group_type = {
         let script_group_type = if matches_tx_file.is_none() {
             "type"
@@ -221,55 +1207,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let cell_type = matches_cell_type;
         let cell_index = matches_cell_index;
         if cell_type.is_none() || cell_index.is_none() {
-            panic!("You must provide either script hash, or cell type + cell index");
+            return Err("You must provide either script hash, or cell type + cell index".into());
         }
         let cell_type = cell_type.unwrap();
         let cell_index: usize = cell_index.unwrap().parse()?;
-        match (&verifier_script_group_type, cell_type) {
-            (ScriptGroupType::Lock, "input") => verifier_mock_tx.mock_info.inputs[cell_index].output.calc_lock_hash(),
-            (ScriptGroupType::Type, "input") => verifier_mock_tx.mock_info.inputs[cell_index]
-                .output
-                .type_()
-                .to_opt()
-                .expect("cell should have type script")
-                .calc_script_hash(),
-            (ScriptGroupType::Type, "output") => verifier_mock_tx
-                .tx
-                .raw()
-                .outputs()
-                .get(cell_index)
-                .expect("index out of bound")
-                .type_()
-                .to_opt()
-                .expect("cell should have type script")
-                .calc_script_hash(),
-            _ => panic!(
-                "Invalid specified script: {:?} {} {}",
-                verifier_script_group_type, cell_type, cell_index
-            ),
-        }
+        cell_script_hash(&verifier_mock_tx, verifier_script_group_type, cell_type, cell_index)?
     };
-    let verifier_script_version = match matches_script_version {
-        "0" => ScriptVersion::V0,
-        "1" => ScriptVersion::V1,
-        _ => panic!("wrong script version"),
-    };
-    let verifier_resource = Resource::from_both(&verifier_mock_tx, DummyResourceLoader {})?;
-    let verifier_resolve_transaction = resolve_transaction(
-        verifier_mock_tx.core_transaction(),
-        &mut HashSet::new(),
-        &verifier_resource,
-        &verifier_resource,
-    )?;
-    let mut verifier = TransactionScriptsVerifier::new(&verifier_resolve_transaction, &verifier_resource);
-    verifier.set_debug_printer(Box::new(move |hash: &Byte32, message: &str| {
-        if long_log {
-            debug!("script group: {} DEBUG OUTPUT: {}", hash, message);
-        } else {
-            debug!("SCRIPT>{}", message);
-        }
-    }));
-    let verifier_script_group = verifier.find_script_group(verifier_script_group_type, &verifier_script_hash).unwrap();
+    let verifier_script_group =
+        verifier.find_script_group(verifier_script_group_type, &verifier_script_hash).ok_or_else(|| {
+            format!(
+                "No {:?} script group with hash {:x}",
+                verifier_script_group_type, verifier_script_hash
+            )
+        })?;
     let verifier_program = match matches_bin {
         Some(path) => {
             let data = read(path)?;
@@ -278,45 +1228,88 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         None => verifier.extract_script(&verifier_script_group.script)?,
     };
 
+    if matches_mode == "fuzz" {
+        let targets = matches_fuzz_targets.map(|t| t.parse()).collect::<Result<Vec<FuzzTarget>, String>>()?;
+        let exit_codes = matches_fuzz_exit_codes.map(|c| c.parse()).collect::<Result<Vec<i8>, _>>()?;
+        let mut fuzzer = Fuzzer::new(
+            verifier_repr_mock_tx.clone(),
+            targets,
+            exit_codes,
+            PathBuf::from(matches_fuzz_output),
+            syscall_settings.seed,
+        )?;
+        fuzzer.run(matches_fuzz_iterations.parse()?, |repr_mock_tx| {
+            let mock_tx: MockTransaction = repr_mock_tx.clone().into();
+            let resource = load_resource(&mock_tx, rpc_loader.as_ref()).ok()?;
+            let rtx =
+                resolve_transaction(mock_tx.core_transaction(), &mut HashSet::new(), &resource, &resource).ok()?;
+            let mut verifier = TransactionScriptsVerifier::new(&rtx, &resource);
+            verifier.set_debug_printer(Box::new(|_: &Byte32, _: &str| {}));
+            // Script args may be fuzzed, in which case the hash of the selected script changes too.
+            let script_hash = match (matches_script_hash, matches_cell_type, matches_cell_index) {
+                (None, Some(cell_type), Some(cell_index)) => cell_script_hash(
+                    &mock_tx,
+                    verifier_script_group_type,
+                    cell_type,
+                    cell_index.parse().ok()?,
+                )
+                .ok()?,
+                _ => verifier_script_hash.clone(),
+            };
+            let script_group = verifier.find_script_group(verifier_script_group_type, &script_hash)?;
+            let program = match matches_bin {
+                Some(_) => verifier_program.clone(),
+                None => verifier.extract_script(&script_group.script).ok()?,
+            };
+            let mut machine = machine_init(
+                &verifier,
+                script_group,
+                verifier_script_version,
+                verifier_max_cycles,
+                None,
+                debugger_syscalls(fs_syscall.clone(), syscall_settings.clone()),
+                None,
+            );
+            let mut pcs = HashSet::new();
+            let result = machine
+                .load_program(&program, &verifier_args_byte)
+                .and_then(|bytes| machine.add_cycles(transferred_byte_cycles(bytes)))
+                .and_then(|_| {
+                    let mut decoder = build_decoder::<u64>(machine.isa(), machine.version());
+                    machine.set_running(true);
+                    while machine.running() {
+                        if machine.reset_signal() {
+                            decoder.reset_instructions_cache();
+                        }
+                        pcs.insert(*machine.pc());
+                        machine.step(&mut decoder)?;
+                    }
+                    Ok(machine.exit_code())
+                });
+            Some(Execution {
+                result,
+                cycles: machine.cycles(),
+                pcs,
+            })
+        })?;
+        return Ok(());
+    }
+
     let machine_init = || {
-        let machine_core = DefaultCoreMachine::<u64, WXorXMemory<SparseMemory<u64>>>::new(
-            verifier_script_version.vm_isa(),
-            verifier_script_version.vm_version(),
+        machine_init(
+            &verifier,
+            verifier_script_group,
+            verifier_script_version,
             verifier_max_cycles,
-        );
-        #[cfg(feature = "stdio")]
-        let mut machine_builder = DefaultMachineBuilder::new(machine_core)
-            .instruction_cycle_func(&instruction_cycles)
-            .syscall(Box::new(Stdio::new(false)));
-        #[cfg(not(feature = "stdio"))]
-        let mut machine_builder = DefaultMachineBuilder::new(machine_core).instruction_cycle_func(&instruction_cycles);
-        if let Some(data) = matches_dump_file {
-            machine_builder = machine_builder.syscall(Box::new(ElfDumper::new(data.to_string(), 4097, 64)));
-        }
-        let machine_syscalls = verifier.generate_syscalls(verifier_script_version, verifier_script_group);
-        machine_builder =
-            machine_syscalls.into_iter().fold(machine_builder, |builder, syscall| builder.syscall(syscall));
-        let machine_builder = if let Some(fs) = fs_syscall.clone() {
-            machine_builder.syscall(Box::new(fs))
-        } else {
-            machine_builder
-        };
-        let machine_builder = machine_builder.syscall(Box::new(TimeNow::new()));
-        let machine_builder = machine_builder.syscall(Box::new(Random::new()));
-        let machine_builder = machine_builder.syscall(Box::new(FileOperation::new()));
-        let machine = machine_builder.build();
-        machine
+            matches_dump_file,
+            debugger_syscalls(fs_syscall.clone(), syscall_settings.clone()),
+            syscall_settings.trace.clone(),
+        )
     };
 
-    let machine_step = |machine: &mut PProfMachine<
-        DefaultCoreMachine<u64, WXorXMemory<SparseMemory<u64>>>,
-    >|
-     -> Result<i8, ckb_vm::Error> {
+    let machine_step = |machine: &mut PProfMachine<CoreMachineType>| -> Result<i8, ckb_vm::Error> {
         machine.machine.set_running(true);
-        let mut decoder = build_decoder::<u64>(
-            verifier_script_version.vm_isa(),
-            verifier_script_version.vm_version(),
-        );
+        let mut decoder = build_decoder::<u64>(verifier_script_version.vm_isa(), verifier_script_version.vm_version());
         let mut step_result = Ok(());
         let skip_range = if let (Some(s), Some(e)) = (matches_skip_start, matches_skip_end) {
             let s = u64::from_str_radix(s.trim_start_matches("0x"), 16).expect("parse skip start");
@@ -325,20 +1318,44 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else {
             None
         };
+        let mut recorder = match matches_trace_file {
+            Some(path) => Some(TraceRecorder::new(BufWriter::new(File::create(path)?))?),
+            None => None,
+        };
         while machine.machine.running() && step_result.is_ok() {
-            let mut print_info = true;
+            let mut print_info = matches_step > 0;
             if let Some(skip_range) = &skip_range {
                 if skip_range.contains(machine.machine.pc()) {
                     print_info = false;
                 }
             }
             if print_info {
-                println!("PC: 0x{:x}", machine.machine.pc());
+                let pc = *machine.machine.pc();
+                let inst = match decoder.decode(machine.machine.memory_mut(), pc) {
+                    Ok(inst) => disassemble(inst),
+                    Err(_) => String::from("??"),
+                };
+                let mut info = format!("PC: 0x{:x} {:<32} {}", pc, inst, machine.profile.get_tag(pc).detail());
                 if matches_step > 1 {
-                    println!("Machine: {}", machine.machine);
+                    info = format!("{}\nMachine: {}", info, machine.machine);
+                }
+                // With JSON output, stdout carries nothing but the result.
+                if output_json {
+                    eprintln!("{}", info);
+                } else {
+                    println!("{}", info);
                 }
             }
-            step_result = machine.machine.step(&mut decoder);
+            if let Some(recorder) = &mut recorder {
+                recorder.before_step(&mut machine.machine, &mut decoder)?;
+            }
+            step_result = machine.step(&mut decoder);
+            if let (Some(recorder), Ok(_)) = (&mut recorder, &step_result) {
+                recorder.after_step(&mut machine.machine)?;
+            }
+        }
+        if let Some(recorder) = &mut recorder {
+            recorder.flush()?;
         }
         if step_result.is_err() {
             Err(step_result.unwrap_err())
@@ -349,36 +1366,90 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if matches_mode == "full" {
         let mut machine = PProfMachine::new(machine_init(), Profile::new(&verifier_program)?);
+        if matches_coverage.is_some() {
+            machine.profile.enable_coverage();
+        }
         let bytes = machine.load_program(&verifier_program, &verifier_args_byte)?;
         let transferred_cycles = transferred_byte_cycles(bytes);
         machine.machine.add_cycles(transferred_cycles)?;
-        let result = if matches_step > 0 {
+        let result = if matches_step > 0 || matches_trace_file.is_some() {
             machine_step(&mut machine)
         } else {
             machine.run()
         };
-        match result {
-            Ok(data) => {
-                println!("Run result: {:?}", data);
-                println!(
-                    "Total cycles consumed: {}",
-                    HumanReadableCycles(machine.machine.cycles())
-                );
-                println!(
-                    "Transfer cycles: {}, running cycles: {}",
-                    HumanReadableCycles(transferred_cycles),
-                    HumanReadableCycles(machine.machine.cycles() - transferred_cycles)
-                );
-                if let Some(fp) = matches_pprof {
-                    let mut output = std::fs::File::create(&fp)?;
-                    machine.profile.display_flamegraph(&mut output);
+        if let (Ok(_), Some(fp)) = (&result, matches_pprof) {
+            let mut output = std::fs::File::create(&fp)?;
+            machine.profile.display_flamegraph(&mut output);
+        }
+        if let (Some(path), Some(pc_counts)) = (matches_coverage, machine.profile.pc_counts()) {
+            let mut coverage = read_coverage(path)?;
+            coverage.add_run(&verifier_program, pc_counts)?;
+            coverage.write_lcov(&mut File::create(path)?)?;
+        }
+        if output_json {
+            let mut trace = vec![];
+            if result.is_err() {
+                machine.profile.display_stacktrace("", &mut trace);
+            }
+            let report = TxReport {
+                groups: vec![GroupReport::new(
+                    &verifier_script_hash,
+                    verifier_script_group,
+                    &result,
+                    machine.machine.cycles(),
+                    transferred_cycles,
+                    &trace,
+                )],
+                total_cycles: machine.machine.cycles(),
+                max_cycles: verifier_max_cycles,
+                debug_messages: debug_messages.borrow().clone(),
+                replay: ReplayReport::from(&syscall_settings),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            match result {
+                Ok(data) => {
+                    println!("Run result: {:?}", data);
+                    println!(
+                        "Total cycles consumed: {}",
+                        HumanReadableCycles(machine.machine.cycles())
+                    );
+                    println!(
+                        "Transfer cycles: {}, running cycles: {}",
+                        HumanReadableCycles(transferred_cycles),
+                        HumanReadableCycles(machine.machine.cycles() - transferred_cycles)
+                    );
+                }
+                Err(err) => {
+                    println!("Trace:");
+                    machine.profile.display_stacktrace("  ", &mut std::io::stdout());
+                    println!("Error:");
+                    println!("  {:?}", err);
                 }
             }
-            Err(err) => {
-                println!("Trace:");
-                machine.profile.display_stacktrace("  ", &mut std::io::stdout());
-                println!("Error:");
-                println!("  {:?}", err);
+            print_syscall_settings(&syscall_settings);
+        }
+        if let (Ok(_), Some(path)) = (&result, matches_baseline) {
+            let functions = if matches_baseline_functions {
+                Some(machine.profile.function_cycles())
+            } else {
+                None
+            };
+            let mut measured = Baseline::default();
+            measured.add_group(
+                &format!("0x{:x}", verifier_script_hash),
+                machine.machine.cycles(),
+                functions,
+            );
+            if !check_baseline(
+                path,
+                &measured,
+                matches_baseline_threshold,
+                matches_update_baseline,
+                false,
+                output_json,
+            )? {
+                std::process::exit(1);
             }
         }
         return Ok(());
@@ -389,13 +1460,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let bytes = machine.load_program(&verifier_program, &verifier_args_byte)?;
         let transferred_cycles = transferred_byte_cycles(bytes);
         machine.add_cycles(transferred_cycles)?;
-        println!("Run result: {:?}", machine.run());
-        println!("Total cycles consumed: {}", HumanReadableCycles(machine.cycles()));
-        println!(
-            "Transfer cycles: {}, running cycles: {}",
-            HumanReadableCycles(transferred_cycles),
-            HumanReadableCycles(machine.cycles() - transferred_cycles)
-        );
+        let result = machine.run();
+        if output_json {
+            let report = TxReport {
+                groups: vec![GroupReport::new(
+                    &verifier_script_hash,
+                    verifier_script_group,
+                    &result,
+                    machine.cycles(),
+                    transferred_cycles,
+                    &[],
+                )],
+                total_cycles: machine.cycles(),
+                max_cycles: verifier_max_cycles,
+                debug_messages: debug_messages.borrow().clone(),
+                replay: ReplayReport::from(&syscall_settings),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("Run result: {:?}", result);
+            println!("Total cycles consumed: {}", HumanReadableCycles(machine.cycles()));
+            println!(
+                "Transfer cycles: {}, running cycles: {}",
+                HumanReadableCycles(transferred_cycles),
+                HumanReadableCycles(machine.cycles() - transferred_cycles)
+            );
+            print_syscall_settings(&syscall_settings);
+        }
+        if let (Ok(_), Some(path)) = (&result, matches_baseline) {
+            let mut measured = Baseline::default();
+            measured.add_group(&format!("0x{:x}", verifier_script_hash), machine.cycles(), None);
+            if !check_baseline(
+                path,
+                &measured,
+                matches_baseline_threshold,
+                matches_update_baseline,
+                false,
+                output_json,
+            )? {
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if matches_mode == "repl" {
+        let mut machine = PProfMachine::new(machine_init(), Profile::new(&verifier_program)?);
+        let bytes = machine.load_program(&verifier_program, &verifier_args_byte)?;
+        let transferred_cycles = transferred_byte_cycles(bytes);
+        machine.machine.add_cycles(transferred_cycles)?;
+        let stdin = std::io::stdin();
+        Repl::new(&mut machine).run(stdin.lock(), &mut std::io::stdout())?;
         return Ok(());
     }
 
@@ -404,13 +1519,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let listener = TcpListener::bind(listen_address)?;
         for res in listener.incoming() {
             if let Ok(stream) = res {
-                let mut machine = machine_init();
+                // Going back re-executes the script on new machines, they all share the syscalls of the debugger.
+                let syscalls = RecordedSyscalls::new(debugger_syscalls(fs_syscall.clone(), syscall_settings.clone()));
+                let new_machine = |trace: Option<SyscallTrace>| {
+                    let debugger_syscalls: Vec<Box<dyn Syscalls<CoreMachineType>>> = vec![Box::new(syscalls.clone())];
+                    machine_init(
+                        &verifier,
+                        verifier_script_group,
+                        verifier_script_version,
+                        verifier_max_cycles,
+                        matches_dump_file,
+                        debugger_syscalls,
+                        trace,
+                    )
+                };
+                // Only the first machine traces its syscalls, the ones replaying the script would trace them again.
+                let replay_init = || new_machine(None);
+                let mut machine = new_machine(syscall_settings.trace.clone());
                 let bytes = machine.load_program(&verifier_program, &verifier_args_byte)?;
                 let transferred_cycles = transferred_byte_cycles(bytes);
                 machine.add_cycles(transferred_cycles)?;
                 machine.set_running(true);
-                let h = GdbHandler::new(machine);
-                process_packets_from(stream.try_clone().unwrap(), stream, h);
+                let h = GdbHandler::new_reversible(machine, Box::new(&replay_init));
+                process_packets_with_reverse(stream.try_clone().unwrap(), stream, h);
             }
         }
         return Ok(());
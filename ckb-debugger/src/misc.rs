@@ -1,16 +1,19 @@
-use std::io::Read;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Component, Path, PathBuf};
 use std::time::SystemTime;
 use std::{cmp::min, fmt, fs, io};
 
 use lazy_static::lazy_static;
-use libc::{
-    c_char, c_int, c_long, c_void, fclose, feof, ferror, fgetc, fopen, fread, freopen, fseek, ftell, size_t, FILE,
-};
 use rand::prelude::*;
 
+use crate::strace::SyscallTrace;
+use ckb_types::bytes::Bytes;
 use ckb_vm::{
     registers::{A0, A1, A2, A3, A7},
-    Error, Memory, Register, SupportMachine, Syscalls,
+    Error, Memory, Register, SupportMachine, Syscalls, RISCV_PAGESIZE,
 };
 
 pub const READ_SYSCALL_NUMBER: u64 = 9000;
@@ -25,11 +28,19 @@ pub const FGETC_SYSCALL_NUMBER: u64 = 9008;
 pub const FCLOSE_SYSCALL_NUMBER: u64 = 9009;
 pub const FTELL_SYSCALL_NUMBER: u64 = 9010;
 pub const FSEEK_SYSCALL_NUMBER: u64 = 9011;
+pub const FWRITE_SYSCALL_NUMBER: u64 = 9012;
+pub const FPUTC_SYSCALL_NUMBER: u64 = 9013;
+pub const FPUTS_SYSCALL_NUMBER: u64 = 9014;
+pub const FFLUSH_SYSCALL_NUMBER: u64 = 9015;
 
-// TODO: fprintf, fwrite
+pub const READ_STREAM_SYSCALL_NUMBER: u64 = 9016;
+pub const STREAM_INDEX_SYSCALL_NUMBER: u64 = 9017;
+pub const STREAM_LENGTH_SYSCALL_NUMBER: u64 = 9018;
+pub const STREAM_SEEK_SYSCALL_NUMBER: u64 = 9019;
 
 #[derive(Clone)]
 pub struct FileStream {
+    name: String,
     content: Vec<u8>,
     offset: usize,
 }
@@ -41,6 +52,7 @@ lazy_static! {
 impl Default for FileStream {
     fn default() -> Self {
         Self {
+            name: Default::default(),
             content: Default::default(),
             offset: 0,
         }
@@ -48,52 +60,107 @@ impl Default for FileStream {
 }
 
 impl FileStream {
-    pub fn new(file_name: &str) -> Self {
+    /// Opens a stream given as `<name>=<path>` or `<path>`, in which case the path is its name too. Names are made of
+    /// letters, digits and underscores, so a path containing `=` is taken as a whole. The path is `-` for stdin, a
+    /// file of the transaction, or else a host file: the user picked it, unlike paths opened by scripts.
+    pub fn open(spec: &str, settings: &SyscallSettings) -> Result<Self, String> {
+        let (name, file_name) = match spec.split_once('=') {
+            Some((name, file_name))
+                if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                (name, file_name)
+            }
+            _ => (spec, spec),
+        };
         let content = if file_name == "-" {
             let mut v = Vec::<u8>::new();
             let mut stdin = io::stdin();
-            stdin.read_to_end(&mut v).expect("should read from stdin");
+            stdin.read_to_end(&mut v).map_err(|e| format!("stdin: {}", e))?;
             v
+        } else if let Some(data) = settings.file(file_name) {
+            data.to_vec()
         } else {
-            fs::read(file_name).expect("should read the file")
+            fs::read(file_name).map_err(|e| format!("{}: {}", file_name, e))?
         };
-        FileStream { content, offset: 0 }
+        Ok(FileStream {
+            name: name.to_string(),
+            content,
+            offset: 0,
+        })
     }
     // mimic:  ssize_t read(int fd, void *buf, size_t count);
-    fn read(&mut self, buf: &mut [u8]) -> isize {
+    fn read(&mut self, count: u64) -> Option<&[u8]> {
         if self.offset >= self.content.len() {
-            return -1;
+            return None;
         }
         let remaining_size = self.content.len() - self.offset;
-        let read_size = min(buf.len(), remaining_size);
-        buf[0..read_size].copy_from_slice(&self.content[self.offset..self.offset + read_size]);
-
+        let read_size = min(count, remaining_size as u64) as usize;
         self.offset += read_size;
-        read_size as isize
+        Some(&self.content[self.offset - read_size..self.offset])
+    }
+}
+
+/// Input streams given with `--read-file`, each with its own offset. The read syscall consumes the first one, the
+/// stream syscalls select one by its index.
+#[derive(Clone)]
+pub struct FileStreams {
+    streams: Vec<FileStream>,
+}
+
+impl FileStreams {
+    /// Fails if two streams have the same name, the second one could never be selected.
+    pub fn new(streams: Vec<FileStream>) -> Result<Self, String> {
+        for (i, stream) in streams.iter().enumerate() {
+            if streams[..i].iter().any(|other| other.name == stream.name) {
+                return Err(format!("--read-file: duplicate stream name {}", stream.name));
+            }
+        }
+        Ok(Self { streams })
     }
 }
 
-impl<Mac: SupportMachine> Syscalls<Mac> for FileStream {
+impl<Mac: SupportMachine> Syscalls<Mac> for FileStreams {
     fn initialize(&mut self, _machine: &mut Mac) -> Result<(), Error> {
         Ok(())
     }
 
     fn ecall(&mut self, machine: &mut Mac) -> Result<bool, Error> {
         let id = machine.registers()[A7].to_u64();
-        if id != READ_SYSCALL_NUMBER {
-            return Ok(false);
-        }
-        let arg_buf = machine.registers()[A0].to_u64();
-        let arg_count = machine.registers()[A1].to_u64();
-        let mut buf = vec![0u8; arg_count as usize];
-        let read_size = self.read(&mut buf);
-        if read_size > 0 {
-            machine.memory_mut().store_bytes(arg_buf, &buf[0..read_size as usize])?;
-            machine.set_register(A0, Mac::REG::from_u64(read_size as u64));
-        } else {
-            machine.set_register(A0, Mac::REG::from_i64(-1));
-        }
-        return Ok(true);
+        let arg0 = machine.registers()[A0].to_u64();
+        let arg1 = machine.registers()[A1].to_u64();
+        let arg2 = machine.registers()[A2].to_u64();
+        let ret = match id {
+            READ_SYSCALL_NUMBER | READ_STREAM_SYSCALL_NUMBER => {
+                let index = if id == READ_SYSCALL_NUMBER { 0 } else { arg2 };
+                let data = self.streams.get_mut(index as usize).and_then(|stream| stream.read(arg1));
+                match data {
+                    Some(data) if !data.is_empty() => {
+                        machine.memory_mut().store_bytes(arg0, data)?;
+                        data.len() as i64
+                    }
+                    _ => -1,
+                }
+            }
+            STREAM_INDEX_SYSCALL_NUMBER => match FileOperation::fetch_string(machine, arg0)? {
+                Some(name) => {
+                    self.streams.iter().position(|stream| stream.name == name).map(|i| i as i64).unwrap_or(-1)
+                }
+                None => -1,
+            },
+            STREAM_LENGTH_SYSCALL_NUMBER => {
+                self.streams.get(arg0 as usize).map(|stream| stream.content.len() as i64).unwrap_or(-1)
+            }
+            STREAM_SEEK_SYSCALL_NUMBER => match self.streams.get_mut(arg0 as usize) {
+                Some(stream) if arg1 <= stream.content.len() as u64 => {
+                    stream.offset = arg1 as usize;
+                    0
+                }
+                _ => -1,
+            },
+            _ => return Ok(false),
+        };
+        machine.set_register(A0, Mac::REG::from_i64(ret));
+        Ok(true)
     }
 }
 
@@ -112,11 +179,59 @@ impl fmt::Display for HumanReadableCycles {
     }
 }
 
-pub struct TimeNow {}
+/// Where the time syscall takes the time from. The virtual clock starts at `base` nanoseconds since the epoch and
+/// advances `ns_per_cycle` for every cycle consumed, so runs can be replayed.
+#[derive(Clone, Copy, Debug)]
+pub enum Clock {
+    Real,
+    Virtual { base: u64, ns_per_cycle: u64 },
+}
+
+/// Settings of the debugger syscalls. The seed and clock are printed with the results of a run so that it can be
+/// replayed. The file syscalls serve `files`, the files of the transaction, and reach host files, confined to `fs_root`
+/// if given, only when `fs_host` is set. Every syscall is logged to `trace` if given.
+#[derive(Clone, Debug)]
+pub struct SyscallSettings {
+    pub seed: u64,
+    pub clock: Clock,
+    pub fs_root: Option<PathBuf>,
+    pub files: BTreeMap<String, Bytes>,
+    pub fs_host: bool,
+    pub trace: Option<SyscallTrace>,
+}
+
+// Normalizes a path lexically into a relative one, None if it goes above where it starts.
+fn normalize(path: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(normalized)
+}
+
+impl SyscallSettings {
+    /// Finds a file of the transaction. `a/./b` and `/a/b` both name the file `a/b`.
+    pub fn file(&self, path: &str) -> Option<&Bytes> {
+        let path = normalize(path)?;
+        self.files.iter().find(|(name, _)| normalize(name).as_ref() == Some(&path)).map(|(_, data)| data)
+    }
+}
+
+pub struct TimeNow {
+    clock: Clock,
+}
 
 impl TimeNow {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(clock: Clock) -> Self {
+        Self { clock }
     }
 }
 
@@ -130,18 +245,24 @@ impl<Mac: SupportMachine> Syscalls<Mac> for TimeNow {
         if id != NOW_SYSCALL_NUMBER {
             return Ok(false);
         }
-        let duration = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-        let now = duration.as_nanos();
-        machine.set_register(A0, Mac::REG::from_u64(now as u64));
+        let now = match self.clock {
+            Clock::Real => SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos() as u64,
+            Clock::Virtual { base, ns_per_cycle } => base.wrapping_add(machine.cycles().wrapping_mul(ns_per_cycle)),
+        };
+        machine.set_register(A0, Mac::REG::from_u64(now));
         return Ok(true);
     }
 }
 
-pub struct Random {}
+pub struct Random {
+    rng: StdRng,
+}
 
 impl Random {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
     }
 }
 
@@ -155,43 +276,207 @@ impl<Mac: SupportMachine> Syscalls<Mac> for Random {
         if id != RANDOM_SYSCALL_NUMBER {
             return Ok(false);
         }
-        let r: u64 = random();
+        let r: u64 = self.rng.gen();
         machine.set_register(A0, Mac::REG::from_u64(r));
         return Ok(true);
     }
 }
 
-pub struct FileOperation {}
+// Bytes a single fread, fwrite or fputs may transfer, larger transfers fail.
+const MAX_TRANSFER_SIZE: u64 = 3 * 1024 * 1024;
+// Longest path or mode string accepted from a script, including the terminating NUL.
+const MAX_STRING_SIZE: u64 = 8 * 1024;
+// Values returned by the file syscalls on failure, the same as their C counterparts.
+const NULL: u64 = 0;
+const EOF: i64 = -1;
+
+// Files of the transaction are read from memory and can't be written.
+enum Stream {
+    Host(File),
+    Memory(Cursor<Bytes>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Host(file) => file.read(buf),
+            Stream::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Host(file) => file.write(buf),
+            Stream::Memory(_) => Err(io::Error::new(io::ErrorKind::PermissionDenied, "file is read only")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Host(file) => file.flush(),
+            Stream::Memory(_) => Ok(()),
+        }
+    }
+}
+
+impl Seek for Stream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Stream::Host(file) => file.seek(pos),
+            Stream::Memory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+// A file opened by a script, along with the flags feof and ferror report.
+struct OpenFile {
+    file: Stream,
+    eof: bool,
+    error: bool,
+}
+
+/// Serves fopen and friends to scripts. Scripts get opaque handles into a table of the files they opened, never host
+/// pointers, so a bad handle is an error code rather than a crash. Files of the transaction come first and are read
+/// only. Host files are reached only if the settings allow it, and only written with a root, where paths are resolved
+/// inside it and can't escape it, through `..` or symlinks.
+pub struct FileOperation {
+    settings: SyscallSettings,
+    files: HashMap<u64, OpenFile>,
+    next_handle: u64,
+}
 
 impl FileOperation {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(settings: SyscallSettings) -> Self {
+        Self {
+            settings,
+            files: HashMap::new(),
+            next_handle: 1,
+        }
     }
-    fn fetch_string<Mac: SupportMachine>(machine: &mut Mac, addr: u64) -> Result<String, Error> {
+
+    fn fetch_bytes<Mac: SupportMachine>(machine: &mut Mac, addr: u64, size: u64) -> Result<Vec<u8>, Error> {
+        Ok(machine.memory_mut().load_bytes(addr, size)?.to_vec())
+    }
+
+    // Reads a NUL terminated string of at most `limit` bytes, the NUL included. None if it is longer. Memory is read a
+    // page at a time, the string may end right before the memory does.
+    fn fetch_c_string<Mac: SupportMachine>(machine: &mut Mac, addr: u64, limit: u64) -> Result<Option<Vec<u8>>, Error> {
+        let page_size = RISCV_PAGESIZE as u64;
         let mut res = Vec::<u8>::new();
-        let mut done = false;
-        let mut count = 0;
         let mut addr = addr;
-        while !done {
-            let reg = Mac::REG::from_u64(addr);
-            let eight_bytes = machine.memory_mut().load64(&reg)?;
-            let buf = eight_bytes.to_u64().to_le_bytes();
-            for c in buf {
-                if c != 0 {
-                    res.push(c);
-                } else {
-                    res.push(c);
-                    done = true;
+        while (res.len() as u64) < limit {
+            let size = (page_size - addr % page_size).min(limit - res.len() as u64);
+            let chunk = machine.memory_mut().load_bytes(addr, size)?;
+            if let Some(end) = chunk.iter().position(|c| *c == 0) {
+                res.extend_from_slice(&chunk[..end]);
+                return Ok(Some(res));
+            }
+            res.extend_from_slice(&chunk);
+            addr = addr.wrapping_add(size);
+        }
+        Ok(None)
+    }
+
+    // Reads a path or mode string, None if it is too long or not UTF-8.
+    fn fetch_string<Mac: SupportMachine>(machine: &mut Mac, addr: u64) -> Result<Option<String>, Error> {
+        Ok(Self::fetch_c_string(machine, addr, MAX_STRING_SIZE)?.and_then(|bytes| String::from_utf8(bytes).ok()))
+    }
+
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let root = match &self.settings.fs_root {
+            Some(root) => root,
+            None => return Some(PathBuf::from(path)),
+        };
+        let resolved = root.join(normalize(path)?);
+        // Links are resolved here, dangling ones too, so that the path returned has none left. The file may not exist
+        // yet when it's opened for writing, its directory must.
+        let canonical = if resolved.symlink_metadata().is_ok() {
+            resolved.canonicalize().ok()?
+        } else {
+            resolved.parent()?.canonicalize().ok()?.join(resolved.file_name()?)
+        };
+        if canonical.starts_with(root.canonicalize().ok()?) {
+            Some(canonical)
+        } else {
+            None
+        }
+    }
+
+    fn open(&self, path: &str, mode: &str) -> Option<Stream> {
+        let mode = mode.replace('b', "");
+        if let Some(data) = self.settings.file(path) {
+            return match mode.as_str() {
+                "r" => Some(Stream::Memory(Cursor::new(data.clone()))),
+                _ => None,
+            };
+        }
+        if !self.settings.fs_host {
+            return None;
+        }
+        // Writing to host files anywhere could clobber the files of the user, it needs a root to be confined to.
+        if mode != "r" && self.settings.fs_root.is_none() {
+            return None;
+        }
+        let path = self.resolve(path)?;
+        let mut options = OpenOptions::new();
+        match mode.as_str() {
+            "r" => options.read(true),
+            "r+" => options.read(true).write(true),
+            "w" => options.write(true).create(true).truncate(true),
+            "w+" => options.read(true).write(true).create(true).truncate(true),
+            "a" => options.append(true).create(true),
+            "a+" => options.read(true).append(true).create(true),
+            _ => return None,
+        };
+        // A link put in place of the resolved path since it was resolved isn't followed.
+        if self.settings.fs_root.is_some() {
+            options.custom_flags(libc::O_NOFOLLOW);
+        }
+        options.open(path).ok().map(Stream::Host)
+    }
+
+    fn add(&mut self, file: Stream) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.files.insert(
+            handle,
+            OpenFile {
+                file,
+                eof: false,
+                error: false,
+            },
+        );
+        handle
+    }
+
+    // Fills `buf` as far as the file goes, setting the eof and error flags like fread.
+    fn read(file: &mut OpenFile, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+        while read < buf.len() {
+            match file.file.read(&mut buf[read..]) {
+                Ok(0) => {
+                    file.eof = true;
+                    break;
+                }
+                Ok(n) => read += n,
+                Err(_) => {
+                    file.error = true;
                     break;
                 }
             }
-            count += 1;
-            if count > 1024 {
-                panic!("too long string");
-            }
-            addr += 8;
         }
-        Ok(String::from_utf8(res).expect("A valid UTF-8 string"))
+        read
+    }
+
+    // Writes all of `data`, setting the error flag like fwrite if it can't.
+    fn write(file: &mut OpenFile, data: &[u8]) -> bool {
+        if file.file.write_all(data).is_err() {
+            file.error = true;
+            return false;
+        }
+        true
     }
 }
 
@@ -199,6 +484,7 @@ impl<Mac: SupportMachine> Syscalls<Mac> for FileOperation {
     fn initialize(&mut self, _machine: &mut Mac) -> Result<(), Error> {
         Ok(())
     }
+
     fn ecall(&mut self, machine: &mut Mac) -> Result<bool, Error> {
         let id = machine.registers()[A7].to_u64();
         let arg0 = machine.registers()[A0].to_u64();
@@ -210,71 +496,179 @@ impl<Mac: SupportMachine> Syscalls<Mac> for FileOperation {
             FOPEN_SYSCALL_NUMBER => {
                 let path = Self::fetch_string(machine, arg0)?;
                 let mode = Self::fetch_string(machine, arg1)?;
-                let handler = unsafe {
-                    fopen(
-                        path.as_bytes().as_ptr() as *const c_char,
-                        mode.as_bytes().as_ptr() as *const c_char,
-                    )
+                let file = match (path, mode) {
+                    (Some(path), Some(mode)) => self.open(&path, &mode),
+                    _ => None,
                 };
-                machine.set_register(A0, Mac::REG::from_u64(handler as u64));
+                let handle = file.map(|file| self.add(file)).unwrap_or(NULL);
+                machine.set_register(A0, Mac::REG::from_u64(handle));
             }
             FREOPEN_SYSCALL_NUMBER => {
                 let path = Self::fetch_string(machine, arg0)?;
                 let mode = Self::fetch_string(machine, arg1)?;
                 let stream = arg2;
-                let handler = unsafe {
-                    freopen(
-                        path.as_bytes().as_ptr() as *const c_char,
-                        mode.as_bytes().as_ptr() as *const c_char,
-                        stream as *mut FILE,
-                    )
+                // Like freopen, the stream is closed even if the new file can't be opened.
+                let handle = match (self.files.remove(&stream), path, mode) {
+                    (Some(_), Some(path), Some(mode)) => match self.open(&path, &mode) {
+                        Some(file) => {
+                            self.files.insert(
+                                stream,
+                                OpenFile {
+                                    file,
+                                    eof: false,
+                                    error: false,
+                                },
+                            );
+                            stream
+                        }
+                        None => NULL,
+                    },
+                    _ => NULL,
                 };
-                machine.set_register(A0, Mac::REG::from_u64(handler as u64));
+                machine.set_register(A0, Mac::REG::from_u64(handle));
             }
             FREAD_SYSCALL_NUMBER => {
                 let ptr = arg0;
                 let size = arg1;
                 let nitems = arg2;
                 let stream = arg3;
-                let total_size = nitems * size;
-                if total_size > 3 * 1024 * 1024 {
-                    panic!("too much memory to read");
-                }
-                let buf = vec![0u8; total_size as usize];
-                let read_count = unsafe {
-                    fread(
-                        buf.as_ptr() as *mut c_void,
-                        size as size_t,
-                        nitems as size_t,
-                        stream as *mut FILE,
-                    )
+                let total_size = size.checked_mul(nitems).filter(|total| *total <= MAX_TRANSFER_SIZE);
+                let read_count = match (self.files.get_mut(&stream), total_size) {
+                    (Some(file), Some(total_size)) if size > 0 => {
+                        let mut buf = vec![0u8; total_size as usize];
+                        let read = Self::read(file, &mut buf);
+                        machine.memory_mut().store_bytes(ptr, &buf[0..read])?;
+                        read as u64 / size
+                    }
+                    (Some(file), None) => {
+                        file.error = true;
+                        0
+                    }
+                    _ => 0,
                 };
-                machine.memory_mut().store_bytes(ptr, &buf[0..read_count * size as usize])?;
-                machine.set_register(A0, Mac::REG::from_u64(read_count as u64));
+                machine.set_register(A0, Mac::REG::from_u64(read_count));
             }
             FEOF_SYSCALL_NUMBER => {
-                let eof = unsafe { feof(arg0 as *mut FILE) };
-                machine.set_register(A0, Mac::REG::from_i32(eof));
+                let eof = self.files.get(&arg0).map(|file| file.eof).unwrap_or(true);
+                machine.set_register(A0, Mac::REG::from_u64(eof as u64));
             }
             FERROR_SYSCALL_NUMBER => {
-                let error = unsafe { ferror(arg0 as *mut FILE) };
-                machine.set_register(A0, Mac::REG::from_i32(error));
+                let error = self.files.get(&arg0).map(|file| file.error).unwrap_or(true);
+                machine.set_register(A0, Mac::REG::from_u64(error as u64));
             }
             FGETC_SYSCALL_NUMBER => {
-                let ch = unsafe { fgetc(arg0 as *mut FILE) };
-                machine.set_register(A0, Mac::REG::from_i32(ch));
+                let ch = match self.files.get_mut(&arg0) {
+                    Some(file) => {
+                        let mut buf = [0u8; 1];
+                        match Self::read(file, &mut buf) {
+                            1 => buf[0] as i64,
+                            _ => EOF,
+                        }
+                    }
+                    None => EOF,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(ch));
             }
             FCLOSE_SYSCALL_NUMBER => {
-                let ret = unsafe { fclose(arg0 as *mut FILE) };
-                machine.set_register(A0, Mac::REG::from_i32(ret));
+                let ret = match self.files.remove(&arg0) {
+                    Some(_) => 0,
+                    None => EOF,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(ret));
             }
             FTELL_SYSCALL_NUMBER => {
-                let pos = unsafe { ftell(arg0 as *mut FILE) };
-                machine.set_register(A0, Mac::REG::from_i64(pos.into()));
+                let pos = match self.files.get_mut(&arg0).map(|file| file.file.stream_position()) {
+                    Some(Ok(pos)) => pos as i64,
+                    _ => -1,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(pos));
             }
             FSEEK_SYSCALL_NUMBER => {
-                let ret = unsafe { fseek(arg0 as *mut FILE, arg1 as c_long, arg2 as c_int) };
-                machine.set_register(A0, Mac::REG::from_i32(ret));
+                let offset = arg1 as i64;
+                let whence = match arg2 {
+                    0 => Some(SeekFrom::Start(offset as u64)),
+                    1 => Some(SeekFrom::Current(offset)),
+                    2 => Some(SeekFrom::End(offset)),
+                    _ => None,
+                };
+                let ret = match (self.files.get_mut(&arg0), whence) {
+                    (Some(file), Some(whence)) if !(arg2 == 0 && offset < 0) => match file.file.seek(whence) {
+                        Ok(_) => {
+                            file.eof = false;
+                            0
+                        }
+                        Err(_) => -1,
+                    },
+                    _ => -1,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(ret));
+            }
+            FWRITE_SYSCALL_NUMBER => {
+                let ptr = arg0;
+                let size = arg1;
+                let nitems = arg2;
+                let stream = arg3;
+                let total_size = size.checked_mul(nitems).filter(|total| *total <= MAX_TRANSFER_SIZE);
+                let write_count = match (self.files.get_mut(&stream), total_size) {
+                    (Some(file), Some(total_size)) if size > 0 => {
+                        let data = Self::fetch_bytes(machine, ptr, total_size)?;
+                        if Self::write(file, &data) {
+                            nitems
+                        } else {
+                            0
+                        }
+                    }
+                    (Some(file), None) => {
+                        file.error = true;
+                        0
+                    }
+                    _ => 0,
+                };
+                machine.set_register(A0, Mac::REG::from_u64(write_count));
+            }
+            FPUTC_SYSCALL_NUMBER => {
+                let ch = arg0 as u8;
+                let ret = match self.files.get_mut(&arg1) {
+                    Some(file) => {
+                        if Self::write(file, &[ch]) {
+                            ch as i64
+                        } else {
+                            EOF
+                        }
+                    }
+                    None => EOF,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(ret));
+            }
+            FPUTS_SYSCALL_NUMBER => {
+                let data = Self::fetch_c_string(machine, arg0, MAX_TRANSFER_SIZE)?;
+                let ret = match (self.files.get_mut(&arg1), data) {
+                    (Some(file), Some(data)) => {
+                        if Self::write(file, &data) {
+                            0
+                        } else {
+                            EOF
+                        }
+                    }
+                    (Some(file), None) => {
+                        file.error = true;
+                        EOF
+                    }
+                    _ => EOF,
+                };
+                machine.set_register(A0, Mac::REG::from_i64(ret));
+            }
+            FFLUSH_SYSCALL_NUMBER => {
+                // Like fflush, a null stream flushes every open file.
+                let flushed = if arg0 == NULL {
+                    self.files.values_mut().fold(true, |flushed, file| file.file.flush().is_ok() && flushed)
+                } else {
+                    match self.files.get_mut(&arg0) {
+                        Some(file) => file.file.flush().is_ok(),
+                        None => false,
+                    }
+                };
+                machine.set_register(A0, Mac::REG::from_i64(if flushed { 0 } else { EOF }));
             }
             _ => {
                 return Ok(false);
@@ -283,3 +677,232 @@ impl<Mac: SupportMachine> Syscalls<Mac> for FileOperation {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CoreMachineType;
+    use ckb_script::ScriptVersion;
+    use ckb_vm::CoreMachine;
+
+    fn new_machine() -> CoreMachineType {
+        CoreMachineType::new(
+            ScriptVersion::V1.vm_isa(),
+            ScriptVersion::V1.vm_version(),
+            u64::max_value(),
+        )
+    }
+
+    // Makes the syscall `number` on `machine` and returns a0.
+    fn ecall(syscall: &mut impl Syscalls<CoreMachineType>, machine: &mut CoreMachineType, number: u64) -> u64 {
+        machine.set_register(A7, number);
+        assert!(syscall.ecall(machine).unwrap());
+        machine.registers()[A0]
+    }
+
+    fn settings(files: &[(&str, &[u8])]) -> SyscallSettings {
+        SyscallSettings {
+            seed: 0,
+            clock: Clock::Real,
+            fs_root: None,
+            files: files.iter().map(|(name, data)| (name.to_string(), Bytes::from(data.to_vec()))).collect(),
+            fs_host: false,
+            trace: None,
+        }
+    }
+
+    fn read_all(stream: Option<Stream>) -> Option<Vec<u8>> {
+        let mut data = vec![];
+        stream?.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    #[test]
+    fn test_file_normalization() {
+        let settings = settings(&[("vectors/a.bin", b"a"), ("./b", b"b")]);
+        let a = Some(Bytes::from_static(b"a"));
+        assert_eq!(settings.file("vectors/a.bin").cloned(), a);
+        assert_eq!(settings.file("/vectors/./a.bin").cloned(), a);
+        assert_eq!(settings.file("vectors/c/../a.bin").cloned(), a);
+        assert_eq!(settings.file("b").cloned(), Some(Bytes::from_static(b"b")));
+        assert_eq!(settings.file("../vectors/a.bin"), None);
+        assert_eq!(settings.file("vectors"), None);
+        assert_eq!(settings.file("a.bin"), None);
+    }
+
+    #[test]
+    fn test_open_precedence() {
+        // Tests run in the directory of the crate, which has a Cargo.toml of its own.
+        let mut settings = settings(&[("Cargo.toml", b"served")]);
+        let operation = FileOperation::new(settings.clone());
+        assert_eq!(read_all(operation.open("Cargo.toml", "r")), Some(b"served".to_vec()));
+        assert_eq!(read_all(operation.open("./Cargo.toml", "rb")), Some(b"served".to_vec()));
+        assert!(operation.open("Cargo.toml", "w").is_none());
+        assert!(operation.open("src/main.rs", "r").is_none());
+
+        settings.fs_host = true;
+        let operation = FileOperation::new(settings);
+        assert_eq!(read_all(operation.open("Cargo.toml", "r")), Some(b"served".to_vec()));
+        assert!(read_all(operation.open("src/main.rs", "r")).unwrap().starts_with(b"#[macro_use]"));
+        assert!(operation.open("src/main.rs", "r+").is_none());
+        assert!(operation.open("src/main.rs", "x").is_none());
+    }
+
+    #[test]
+    fn test_root_escape() {
+        let dir = std::env::temp_dir().join(format!("ckb-debugger-fs-root-{}", std::process::id()));
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("inside.txt"), b"inside").unwrap();
+        fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("created.txt"), root.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("dir_link")).unwrap();
+        std::os::unix::fs::symlink(root.join("inside.txt"), root.join("inner_link")).unwrap();
+
+        let mut settings = settings(&[]);
+        settings.fs_host = true;
+        settings.fs_root = Some(root.clone());
+        let operation = FileOperation::new(settings);
+        assert_eq!(read_all(operation.open("inside.txt", "r")), Some(b"inside".to_vec()));
+        assert_eq!(
+            read_all(operation.open("/sub/../inside.txt", "r")),
+            Some(b"inside".to_vec())
+        );
+        assert_eq!(read_all(operation.open("inner_link", "r")), Some(b"inside".to_vec()));
+        assert!(operation.open("../outside/secret.txt", "r").is_none());
+        assert!(operation.open("sub/../../outside/secret.txt", "r").is_none());
+        assert!(operation.open("link", "r").is_none());
+        assert!(operation.open("link", "w").is_none());
+        assert!(operation.open("dir_link/secret.txt", "r").is_none());
+        assert!(operation.open("dir_link/new.txt", "w").is_none());
+        assert!(operation.open("dangling", "w").is_none());
+        assert!(operation.open("dangling", "a").is_none());
+        assert!(operation.open("missing/new.txt", "w").is_none());
+        assert!(operation.open("sub/new.txt", "w").is_some());
+        assert!(root.join("sub/new.txt").exists());
+        assert_eq!(fs::read(outside.join("secret.txt")).unwrap(), b"secret");
+        assert!(!outside.join("created.txt").exists());
+        assert!(!outside.join("new.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_handles() {
+        let mut operation = FileOperation::new(settings(&[("a.bin", b"abc")]));
+        let mut machine = new_machine();
+        machine.memory_mut().store_bytes(0x100, b"a.bin\0").unwrap();
+        machine.memory_mut().store_bytes(0x200, b"r\0").unwrap();
+        let mut call = |number, args: &[u64]| {
+            for (reg, arg) in [A0, A1, A2, A3].iter().zip(args) {
+                machine.set_register(*reg, *arg);
+            }
+            ecall(&mut operation, &mut machine, number)
+        };
+        let eof = EOF as u64;
+        for handle in [NULL, 42] {
+            assert_eq!(call(FREAD_SYSCALL_NUMBER, &[0x1000, 1, 1, handle]), 0);
+            assert_eq!(call(FWRITE_SYSCALL_NUMBER, &[0x1000, 1, 1, handle]), 0);
+            assert_eq!(call(FEOF_SYSCALL_NUMBER, &[handle]), 1);
+            assert_eq!(call(FERROR_SYSCALL_NUMBER, &[handle]), 1);
+            assert_eq!(call(FGETC_SYSCALL_NUMBER, &[handle]), eof);
+            assert_eq!(call(FTELL_SYSCALL_NUMBER, &[handle]), eof);
+            assert_eq!(call(FSEEK_SYSCALL_NUMBER, &[handle, 0, 0]), eof);
+            assert_eq!(call(FPUTC_SYSCALL_NUMBER, &[b'a' as u64, handle]), eof);
+            assert_eq!(call(FCLOSE_SYSCALL_NUMBER, &[handle]), eof);
+        }
+        assert_eq!(call(FFLUSH_SYSCALL_NUMBER, &[42]), eof);
+        assert_eq!(call(FREOPEN_SYSCALL_NUMBER, &[0x100, 0x200, 42]), NULL);
+        let handle = call(FOPEN_SYSCALL_NUMBER, &[0x100, 0x200]);
+        assert_ne!(handle, NULL);
+        assert_eq!(call(FGETC_SYSCALL_NUMBER, &[handle]), b'a' as u64);
+        // Files of the transaction are read only.
+        assert_eq!(call(FPUTC_SYSCALL_NUMBER, &[b'a' as u64, handle]), eof);
+        assert_eq!(call(FERROR_SYSCALL_NUMBER, &[handle]), 1);
+        assert_eq!(call(FCLOSE_SYSCALL_NUMBER, &[handle]), 0);
+        // A closed handle is as invalid as one never returned.
+        assert_eq!(call(FCLOSE_SYSCALL_NUMBER, &[handle]), eof);
+        assert_eq!(call(FGETC_SYSCALL_NUMBER, &[handle]), eof);
+    }
+
+    #[test]
+    fn test_fetch_c_string() {
+        let mut machine = new_machine();
+        // Crosses from one page into the next.
+        machine.memory_mut().store_bytes(0xffd, b"abcdef\0").unwrap();
+        let fetch = |machine: &mut CoreMachineType, addr, limit| FileOperation::fetch_c_string(machine, addr, limit);
+        assert_eq!(fetch(&mut machine, 0xffd, 16).unwrap(), Some(b"abcdef".to_vec()));
+        assert_eq!(fetch(&mut machine, 0xffd, 7).unwrap(), Some(b"abcdef".to_vec()));
+        assert_eq!(fetch(&mut machine, 0xffd, 6).unwrap(), None);
+        assert_eq!(
+            FileOperation::fetch_bytes(&mut machine, 0xffd, 6).unwrap(),
+            b"abcdef".to_vec()
+        );
+
+        // A string ending with the memory is read without going past it, one that doesn't end is an error.
+        let end = ckb_vm::RISCV_MAX_MEMORY as u64;
+        machine.memory_mut().store_bytes(end - 2, b"x\0").unwrap();
+        assert_eq!(fetch(&mut machine, end - 2, 100).unwrap(), Some(b"x".to_vec()));
+        machine.memory_mut().store_bytes(end - 2, b"xy").unwrap();
+        assert!(fetch(&mut machine, end - 2, 100).is_err());
+    }
+
+    #[test]
+    fn test_stream_names() {
+        let settings = settings(&[("vectors/key.bin", b"key"), ("vectors/a=b.bin", b"a=b")]);
+        let stream = FileStream::open("key=vectors/key.bin", &settings).unwrap();
+        assert_eq!((stream.name.as_str(), stream.content.as_slice()), ("key", &b"key"[..]));
+        let stream = FileStream::open("vectors/key.bin", &settings).unwrap();
+        assert_eq!(stream.name, "vectors/key.bin");
+        // Only a plain name is split off, the rest of the path stays whole.
+        let stream = FileStream::open("vectors/a=b.bin", &settings).unwrap();
+        assert_eq!(
+            (stream.name.as_str(), stream.content.as_slice()),
+            ("vectors/a=b.bin", &b"a=b"[..])
+        );
+        let stream = FileStream::open("a_1=vectors/a=b.bin", &settings).unwrap();
+        assert_eq!(stream.name, "a_1");
+        assert!(FileStream::open("=vectors/key.bin", &settings).is_err());
+
+        let open = |spec| FileStream::open(spec, &settings).unwrap();
+        assert!(FileStreams::new(vec![open("key=vectors/key.bin"), open("vectors/key.bin")]).is_ok());
+        assert!(FileStreams::new(vec![open("key=vectors/key.bin"), open("key=vectors/a=b.bin")]).is_err());
+        assert!(FileStreams::new(vec![open("vectors/key.bin"), open("vectors/key.bin")]).is_err());
+    }
+
+    #[test]
+    fn test_random_seed() {
+        let mut machine = new_machine();
+        let draw = |seed| {
+            let mut random = Random::new(seed);
+            let mut machine = new_machine();
+            (0..4).map(|_| ecall(&mut random, &mut machine, RANDOM_SYSCALL_NUMBER)).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(42), draw(42));
+        assert_ne!(draw(42), draw(43));
+        machine.set_register(A7, NOW_SYSCALL_NUMBER);
+        assert!(!Random::new(42).ecall(&mut machine).unwrap());
+    }
+
+    #[test]
+    fn test_virtual_clock() {
+        let mut now = TimeNow::new(Clock::Virtual {
+            base: 1_700_000_000_000_000_000,
+            ns_per_cycle: 3,
+        });
+        let mut machine = new_machine();
+        assert_eq!(
+            ecall(&mut now, &mut machine, NOW_SYSCALL_NUMBER),
+            1_700_000_000_000_000_000
+        );
+        machine.add_cycles(1000).unwrap();
+        assert_eq!(
+            ecall(&mut now, &mut machine, NOW_SYSCALL_NUMBER),
+            1_700_000_000_000_003_000
+        );
+        machine.set_register(A7, RANDOM_SYSCALL_NUMBER);
+        assert!(!now.ecall(&mut machine).unwrap());
+    }
+}
@@ -0,0 +1,325 @@
+use ckb_vm::{
+    decoder::{build_decoder, Decoder},
+    registers::REGISTER_ABI_NAMES,
+    CoreMachine, Error, Memory, SupportMachine,
+};
+use ckb_vm_pprof::PProfMachine;
+use std::io::{BufRead, Write};
+
+use crate::misc::HumanReadableCycles;
+use crate::CoreMachineType;
+
+const HELP: &str = "\
+Commands:
+  break <addr|symbol>     (b)   Set a breakpoint
+  delete <addr|symbol>    (d)   Remove a breakpoint, or all of them if none is given
+  info                    (i)   List breakpoints
+  step [count]            (s)   Execute one or more instructions
+  next                    (n)   Execute one instruction, stepping over function calls
+  continue                (c)   Run until a breakpoint is hit or the script exits
+  registers               (r)   Dump general purpose registers
+  memory <addr> [length]  (x)   Dump memory, 64 bytes by default
+  backtrace               (bt)  Print the call stack
+  quit                    (q)   Leave the debugger
+";
+
+enum Stop {
+    Breakpoint,
+    Exited(i8),
+    Failed(Error),
+    Stepped,
+}
+
+pub struct Repl<'a, 'b> {
+    machine: &'b mut PProfMachine<'a, CoreMachineType>,
+    decoder: Decoder,
+    breakpoints: Vec<u64>,
+    finished: bool,
+}
+
+impl<'a, 'b> Repl<'a, 'b> {
+    pub fn new(machine: &'b mut PProfMachine<'a, CoreMachineType>) -> Self {
+        machine.machine.set_running(true);
+        let decoder = build_decoder::<u64>(machine.machine.isa(), machine.machine.version());
+        Self {
+            machine,
+            decoder,
+            breakpoints: vec![],
+            finished: false,
+        }
+    }
+
+    pub fn run(&mut self, input: impl BufRead, output: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+        self.print_location(output)?;
+        write!(output, "(ckb-debugger) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if let Some((command, args)) = words.split_first() {
+                if !self.execute(command, args, output)? {
+                    return Ok(());
+                }
+            }
+            write!(output, "(ckb-debugger) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    // Returns false when the user asks to leave.
+    fn execute(&mut self, command: &str, args: &[&str], output: &mut impl Write) -> Result<bool, std::io::Error> {
+        match command {
+            "b" | "break" => match args.first().map(|a| self.parse_addr(a)) {
+                Some(Some(addr)) => {
+                    let number = match self.breakpoints.iter().position(|b| *b == addr) {
+                        Some(i) => i + 1,
+                        None => {
+                            self.breakpoints.push(addr);
+                            self.breakpoints.len()
+                        }
+                    };
+                    writeln!(output, "Breakpoint {} at 0x{:x}", number, addr)?;
+                }
+                Some(None) => writeln!(output, "Unknown address or symbol: {}", args[0])?,
+                None => writeln!(output, "Usage: break <addr|symbol>")?,
+            },
+            "d" | "delete" => match args.first().map(|a| self.parse_addr(a)) {
+                Some(Some(addr)) => self.breakpoints.retain(|b| *b != addr),
+                Some(None) => writeln!(output, "Unknown address or symbol: {}", args[0])?,
+                None => self.breakpoints.clear(),
+            },
+            "i" | "info" => {
+                for (i, addr) in self.breakpoints.clone().iter().enumerate() {
+                    let tag = self.machine.profile.get_tag(*addr);
+                    writeln!(output, "{:<4} 0x{:016x} {}", i + 1, addr, tag.detail())?;
+                }
+            }
+            "s" | "step" => {
+                let count = args.first().and_then(|a| a.parse::<u64>().ok()).unwrap_or(1);
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.step();
+                    if !matches!(stop, Stop::Stepped) {
+                        break;
+                    }
+                }
+                self.report(stop, output)?;
+            }
+            "n" | "next" => {
+                let depth = self.machine.profile.stack_depth();
+                let mut stop = self.step();
+                while matches!(stop, Stop::Stepped) && self.machine.profile.stack_depth() > depth {
+                    stop = self.step();
+                    if matches!(stop, Stop::Stepped) && self.at_breakpoint() {
+                        stop = Stop::Breakpoint;
+                    }
+                }
+                self.report(stop, output)?;
+            }
+            "c" | "continue" => {
+                let mut stop = self.step();
+                while matches!(stop, Stop::Stepped) {
+                    if self.at_breakpoint() {
+                        stop = Stop::Breakpoint;
+                        break;
+                    }
+                    stop = self.step();
+                }
+                self.report(stop, output)?;
+            }
+            "r" | "registers" => {
+                let registers = self.machine.machine.registers();
+                for (i, name) in REGISTER_ABI_NAMES.iter().enumerate() {
+                    write!(output, "{:<4} 0x{:016x}", name, registers[i])?;
+                    if i % 4 == 3 {
+                        writeln!(output)?;
+                    } else {
+                        write!(output, "  ")?;
+                    }
+                }
+                writeln!(output, "pc   0x{:016x}", self.machine.machine.pc())?;
+                writeln!(output, "cycles {}", HumanReadableCycles(self.machine.machine.cycles()))?;
+            }
+            "x" | "memory" => match args.first().map(|a| self.parse_addr(a)) {
+                Some(Some(addr)) => {
+                    let length = args.get(1).and_then(|a| parse_number(a)).unwrap_or(64);
+                    self.dump_memory(addr, length, output)?;
+                }
+                Some(None) => writeln!(output, "Unknown address or symbol: {}", args[0])?,
+                None => writeln!(output, "Usage: memory <addr> [length]")?,
+            },
+            "bt" | "backtrace" => self.machine.profile.display_stacktrace("  ", output),
+            "q" | "quit" => return Ok(false),
+            "h" | "help" => write!(output, "{}", HELP)?,
+            _ => writeln!(
+                output,
+                "Unknown command: {}. Type help for a list of commands.",
+                command
+            )?,
+        }
+        Ok(true)
+    }
+
+    fn parse_addr(&self, arg: &str) -> Option<u64> {
+        parse_number(arg).or_else(|| self.machine.profile.get_func_addr(arg))
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(self.machine.machine.pc())
+    }
+
+    fn step(&mut self) -> Stop {
+        if self.finished {
+            return Stop::Exited(self.machine.machine.exit_code());
+        }
+        match self.machine.step(&mut self.decoder) {
+            Ok(()) if self.machine.machine.running() => Stop::Stepped,
+            Ok(()) => {
+                self.finished = true;
+                Stop::Exited(self.machine.machine.exit_code())
+            }
+            Err(err) => {
+                self.finished = true;
+                Stop::Failed(err)
+            }
+        }
+    }
+
+    fn report(&mut self, stop: Stop, output: &mut impl Write) -> Result<(), std::io::Error> {
+        match stop {
+            Stop::Breakpoint => {
+                write!(output, "Breakpoint hit, ")?;
+                self.print_location(output)
+            }
+            Stop::Stepped => self.print_location(output),
+            Stop::Exited(exit_code) => {
+                writeln!(output, "Script exited with code {}", exit_code)?;
+                writeln!(
+                    output,
+                    "Total cycles consumed: {}",
+                    HumanReadableCycles(self.machine.machine.cycles())
+                )
+            }
+            Stop::Failed(err) => {
+                writeln!(output, "Trace:")?;
+                self.machine.profile.display_stacktrace("  ", output);
+                writeln!(output, "Error:")?;
+                writeln!(output, "  {:?}", err)
+            }
+        }
+    }
+
+    fn print_location(&mut self, output: &mut impl Write) -> Result<(), std::io::Error> {
+        let pc = *self.machine.machine.pc();
+        let tag = self.machine.profile.get_tag(pc);
+        writeln!(output, "pc 0x{:x} in {}", pc, tag.detail())
+    }
+
+    fn dump_memory(&mut self, addr: u64, length: u64, output: &mut impl Write) -> Result<(), std::io::Error> {
+        for line_addr in (addr..addr.saturating_add(length)).step_by(16) {
+            write!(output, "0x{:016x}:", line_addr)?;
+            for byte_addr in line_addr..line_addr.saturating_add(16).min(addr.saturating_add(length)) {
+                match self.machine.machine.memory_mut().load8(&byte_addr) {
+                    Ok(value) => write!(output, " {:02x}", value as u8)?,
+                    Err(_) => write!(output, " ??")?,
+                }
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(arg: &str) -> Option<u64> {
+    if let Some(hex) = arg.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        arg.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_script::{cost_model::instruction_cycles, ScriptVersion};
+    use ckb_vm::{Bytes, DefaultMachineBuilder};
+    use ckb_vm_pprof::Profile;
+
+    fn new_machine<'a>() -> PProfMachine<'a, CoreMachineType> {
+        let program = Bytes::from(std::fs::read("examples/fib").unwrap());
+        let core = CoreMachineType::new(
+            ScriptVersion::V1.vm_isa(),
+            ScriptVersion::V1.vm_version(),
+            u64::max_value(),
+        );
+        let machine = DefaultMachineBuilder::new(core).instruction_cycle_func(&instruction_cycles).build();
+        let mut machine = PProfMachine::new(machine, Profile::new(&program).unwrap());
+        machine.load_program(&program, &[]).unwrap();
+        machine
+    }
+
+    fn run(machine: &mut PProfMachine<CoreMachineType>, input: &str) -> String {
+        let mut output = vec![];
+        Repl::new(machine).run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut machine = new_machine();
+        let fib = machine.profile.get_func_addr("fib").unwrap();
+        let output = run(&mut machine, "b fib\nbreak 0x10\nb fib\ni\nd 0x10\ninfo\nc\nd\nc\nq\n");
+        let breakpoint = format!("Breakpoint 1 at 0x{:x}\n", fib);
+        assert_eq!(output.matches(&breakpoint).count(), 2);
+        assert!(output.contains("Breakpoint 2 at 0x10\n"));
+        assert_eq!(output.matches("2    0x0000000000000010").count(), 1);
+        assert_eq!(output.matches(&format!("1    0x{:016x}", fib)).count(), 2);
+        assert!(output.contains(&format!("Breakpoint hit, pc 0x{:x} in", fib)));
+        assert!(output.contains("Script exited with code 0\n"));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut machine = new_machine();
+        let output = run(&mut machine, "b\nb nowhere\nx\nx nowhere\nbogus\n\nhelp\n");
+        assert!(output.contains("Usage: break <addr|symbol>\n"));
+        assert!(output.contains("Unknown address or symbol: nowhere\n"));
+        assert!(output.contains("Usage: memory <addr> [length]\n"));
+        assert!(output.contains("Unknown command: bogus. Type help for a list of commands.\n"));
+        assert!(output.contains(HELP));
+        // Input ending without quit leaves the loop too, with the prompt printed once per line.
+        assert_eq!(output.matches("(ckb-debugger) ").count(), 8);
+
+        let pc = *machine.machine.pc();
+        let output = run(&mut machine, "s 3\nr\n");
+        assert_ne!(*machine.machine.pc(), pc);
+        assert!(output.contains(&format!("pc   0x{:016x}\n", machine.machine.pc())));
+        assert!(output.contains("zero 0x0000000000000000"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut machine = new_machine();
+        let entry = *machine.machine.pc();
+        let output = run(&mut machine, &format!("x 0x{:x} 20\n", entry));
+        let lines: Vec<&str> = output
+            .lines()
+            .map(|line| line.trim_start_matches("(ckb-debugger) "))
+            .filter(|line| line.starts_with("0x"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("0x{:016x}:", entry)));
+        assert_eq!(lines[0].split_whitespace().count(), 1 + 16);
+        assert_eq!(lines[1].split_whitespace().count(), 1 + 4);
+        assert!(!lines[0].contains("??"));
+
+        // Addresses close to the end of the address space neither wrap around nor overflow.
+        let output = run(&mut machine, "x 0xfffffffffffffff8 32\n");
+        assert!(output.contains("0xfffffffffffffff8: ?? ?? ?? ?? ?? ?? ??\n"));
+        assert_eq!(parse_number("0x10"), Some(16));
+        assert_eq!(parse_number("16"), Some(16));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("fib"), None);
+    }
+}
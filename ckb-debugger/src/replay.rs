@@ -0,0 +1,84 @@
+use crate::misc::{FREAD_SYSCALL_NUMBER, READ_STREAM_SYSCALL_NUMBER, READ_SYSCALL_NUMBER};
+use ckb_vm::{
+    registers::{A0, A1, A7},
+    Error, Memory, Register, SupportMachine, Syscalls,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// What a syscall left in the machine: the content of a0 and the bytes it wrote to memory, if any.
+struct Outcome {
+    number: u64,
+    ret: u64,
+    written: Option<(u64, Vec<u8>)>,
+}
+
+/// Shares syscalls between the machines of a reversible gdb session and records what each call did. Going back
+/// re-executes the script from a snapshot on a new machine, the calls it makes again are answered from the record
+/// instead of the syscalls: the real clock would tell another time, random numbers and host files would differ, and
+/// files would be written twice. Only calls past the furthest point reached go to the syscalls, whose state is that
+/// of this point. Calls are told apart by the cycles consumed when they are made, which grow with every instruction.
+pub struct RecordedSyscalls<'a, Mac> {
+    syscalls: Rc<RefCell<Vec<Box<dyn Syscalls<Mac> + 'a>>>>,
+    record: Rc<RefCell<BTreeMap<u64, Outcome>>>,
+}
+
+impl<'a, Mac> Clone for RecordedSyscalls<'a, Mac> {
+    fn clone(&self) -> Self {
+        Self {
+            syscalls: self.syscalls.clone(),
+            record: self.record.clone(),
+        }
+    }
+}
+
+impl<'a, Mac> RecordedSyscalls<'a, Mac> {
+    pub fn new(syscalls: Vec<Box<dyn Syscalls<Mac> + 'a>>) -> Self {
+        Self {
+            syscalls: Rc::new(RefCell::new(syscalls)),
+            record: Rc::new(RefCell::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl<'a, Mac: SupportMachine> Syscalls<Mac> for RecordedSyscalls<'a, Mac> {
+    fn initialize(&mut self, machine: &mut Mac) -> Result<(), Error> {
+        for syscall in self.syscalls.borrow_mut().iter_mut() {
+            syscall.initialize(machine)?;
+        }
+        Ok(())
+    }
+
+    fn ecall(&mut self, machine: &mut Mac) -> Result<bool, Error> {
+        let number = machine.registers()[A7].to_u64();
+        let cycles = machine.cycles();
+        if let Some(outcome) = self.record.borrow().get(&cycles).filter(|outcome| outcome.number == number) {
+            if let Some((addr, data)) = &outcome.written {
+                machine.memory_mut().store_bytes(*addr, data)?;
+            }
+            machine.set_register(A0, Mac::REG::from_u64(outcome.ret));
+            return Ok(true);
+        }
+
+        let addr = machine.registers()[A0].to_u64();
+        let size = machine.registers()[A1].to_u64();
+        for syscall in self.syscalls.borrow_mut().iter_mut() {
+            if syscall.ecall(machine)? {
+                let ret = machine.registers()[A0].to_u64();
+                let written = match number {
+                    READ_SYSCALL_NUMBER | READ_STREAM_SYSCALL_NUMBER if (ret as i64) > 0 => Some(ret),
+                    FREAD_SYSCALL_NUMBER => Some(ret.saturating_mul(size)),
+                    _ => None,
+                };
+                let written = match written {
+                    Some(length) => Some((addr, machine.memory_mut().load_bytes(addr, length)?.to_vec())),
+                    None => None,
+                };
+                self.record.borrow_mut().insert(cycles, Outcome { number, ret, written });
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::misc::{Clock, SyscallSettings};
+use ckb_script::{ScriptGroup, ScriptGroupType};
+use ckb_types::packed::Byte32;
+use serde::Serialize;
+
+#[derive(Clone, Serialize)]
+pub struct DebugMessage {
+    pub script_hash: String,
+    pub message: String,
+}
+
+impl DebugMessage {
+    pub fn new(script_hash: &Byte32, message: &str) -> Self {
+        Self {
+            script_hash: format!("0x{:x}", script_hash),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct CyclesReport {
+    pub total: u64,
+    pub transfer: u64,
+    pub running: u64,
+}
+
+/// Outcome of running a single script group.
+#[derive(Clone, Serialize)]
+pub struct GroupReport {
+    pub script_hash: String,
+    pub script_group_type: String,
+    pub input_indices: Vec<usize>,
+    pub output_indices: Vec<usize>,
+    pub exit_code: Option<i8>,
+    pub error: Option<String>,
+    pub cycles: CyclesReport,
+    pub stacktrace: Vec<String>,
+}
+
+impl GroupReport {
+    pub fn new(
+        script_hash: &Byte32,
+        script_group: &ScriptGroup,
+        result: &Result<i8, ckb_vm::Error>,
+        cycles: u64,
+        transfer_cycles: u64,
+        stacktrace: &[u8],
+    ) -> Self {
+        let (exit_code, error) = match result {
+            Ok(exit_code) => (Some(*exit_code), None),
+            Err(err) => (None, Some(format!("{:?}", err))),
+        };
+        Self {
+            script_hash: format!("0x{:x}", script_hash),
+            script_group_type: String::from(match script_group.group_type {
+                ScriptGroupType::Lock => "lock",
+                ScriptGroupType::Type => "type",
+            }),
+            input_indices: script_group.input_indices.clone(),
+            output_indices: script_group.output_indices.clone(),
+            exit_code,
+            error,
+            cycles: CyclesReport {
+                total: cycles,
+                transfer: transfer_cycles,
+                running: cycles.saturating_sub(transfer_cycles),
+            },
+            stacktrace: String::from_utf8_lossy(stacktrace).lines().map(String::from).collect(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// The document printed by `--output-format json`.
+#[derive(Clone, Serialize)]
+pub struct TxReport {
+    pub groups: Vec<GroupReport>,
+    pub total_cycles: u64,
+    pub max_cycles: u64,
+    pub debug_messages: Vec<DebugMessage>,
+    pub replay: ReplayReport,
+}
+
+/// Settings of the time and random syscalls the run used.
+#[derive(Clone, Serialize)]
+pub struct ReplayReport {
+    pub seed: u64,
+    pub virtual_clock: Option<u64>,
+    pub virtual_clock_step: Option<u64>,
+}
+
+impl From<&SyscallSettings> for ReplayReport {
+    fn from(settings: &SyscallSettings) -> Self {
+        let (virtual_clock, virtual_clock_step) = match settings.clock {
+            Clock::Real => (None, None),
+            Clock::Virtual { base, ns_per_cycle } => (Some(base), Some(ns_per_cycle)),
+        };
+        Self {
+            seed: settings.seed,
+            virtual_clock,
+            virtual_clock_step,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use ckb_crypto::secp::Privkey;
+use ckb_hash::new_blake2b;
+use ckb_mock_tx_types::MockTransaction;
+use ckb_types::{
+    bytes::Bytes,
+    packed::{self, Byte32, BytesVec, WitnessArgs},
+    prelude::*,
+    H256,
+};
+
+// Length of a recoverable secp256k1 signature, the lock field of the first witness of a group is zeroed to it.
+const SIGNATURE_SIZE: usize = 65;
+
+// Indices of the inputs locked by the same script as the input at `input_index`, that input included.
+fn group_indices(mock_tx: &MockTransaction, input_index: usize) -> Result<Vec<usize>, String> {
+    let lock_hashes = mock_tx
+        .tx
+        .raw()
+        .inputs()
+        .into_iter()
+        .map(|input| {
+            let out_point = input.previous_output();
+            mock_tx
+                .mock_info
+                .inputs
+                .iter()
+                .find(|mock_input| mock_input.input.previous_output() == out_point)
+                .map(|mock_input| mock_input.output.calc_lock_hash())
+                .ok_or_else(|| format!("input {} is missing from mock_info.inputs", out_point))
+        })
+        .collect::<Result<Vec<Byte32>, String>>()?;
+    let lock_hash =
+        lock_hashes.get(input_index).ok_or_else(|| format!("input index {} is out of bound", input_index))?;
+    Ok((0..lock_hashes.len()).filter(|i| &lock_hashes[*i] == lock_hash).collect())
+}
+
+// The witnesses of the transaction, with as many empty ones appended as needed to cover `len`.
+fn padded_witnesses(mock_tx: &MockTransaction, len: usize) -> Vec<packed::Bytes> {
+    let mut witnesses: Vec<packed::Bytes> = mock_tx.tx.witnesses().into_iter().collect();
+    while witnesses.len() < len {
+        witnesses.push(packed::Bytes::default());
+    }
+    witnesses
+}
+
+fn first_witness_args(witness: &packed::Bytes) -> Result<WitnessArgs, String> {
+    if witness.is_empty() {
+        return Ok(WitnessArgs::default());
+    }
+    WitnessArgs::from_slice(&witness.raw_data())
+        .map_err(|err| format!("first witness of the group is not a WitnessArgs: {}", err))
+}
+
+fn with_lock(witness_args: WitnessArgs, lock: Bytes) -> packed::Bytes {
+    witness_args.as_builder().lock(Some(lock).pack()).build().as_bytes().pack()
+}
+
+/// Computes the message the standard secp256k1 sighash-all lock signs for the script group of the input at
+/// `input_index`: blake2b over the transaction hash, the first witness of the group as a `WitnessArgs` with its lock
+/// zeroed, the other witnesses of the group and the witnesses past the inputs, each prefixed by its length.
+pub fn sighash_all_message(mock_tx: &MockTransaction, input_index: usize) -> Result<H256, String> {
+    let group = group_indices(mock_tx, input_index)?;
+    let inputs_len = mock_tx.tx.raw().inputs().len();
+    let witnesses = padded_witnesses(mock_tx, group[0] + 1);
+    let first = first_witness_args(&witnesses[group[0]])?;
+    let zeroed = with_lock(first, Bytes::from(vec![0u8; SIGNATURE_SIZE])).raw_data();
+
+    let mut hasher = new_blake2b();
+    hasher.update(mock_tx.tx.calc_tx_hash().as_slice());
+    hasher.update(&(zeroed.len() as u64).to_le_bytes());
+    hasher.update(&zeroed);
+    // The lock stops at the first witness it can't load, group witnesses past the end of the witnesses are not hashed.
+    let others = group[1..].iter().copied().chain(inputs_len..witnesses.len());
+    for witness in others.filter_map(|i| witnesses.get(i)) {
+        let witness = witness.raw_data();
+        hasher.update(&(witness.len() as u64).to_le_bytes());
+        hasher.update(&witness);
+    }
+    let mut message = [0u8; 32];
+    hasher.finalize(&mut message);
+    Ok(message.into())
+}
+
+/// Signs the script group of the input at `input_index` with `privkey`, the signature is put in the lock field of the
+/// first witness of the group.
+pub fn sign(mock_tx: &MockTransaction, input_index: usize, privkey: &Privkey) -> Result<MockTransaction, String> {
+    let message = sighash_all_message(mock_tx, input_index)?;
+    let signature = privkey.sign_recoverable(&message).map_err(|err| err.to_string())?;
+    let first = group_indices(mock_tx, input_index)?[0];
+    let mut witnesses = padded_witnesses(mock_tx, first + 1);
+    witnesses[first] = with_lock(
+        first_witness_args(&witnesses[first])?,
+        Bytes::from(signature.serialize()),
+    );
+    let tx = mock_tx.tx.clone().as_builder().witnesses(BytesVec::new_builder().set(witnesses).build()).build();
+    Ok(MockTransaction {
+        mock_info: mock_tx.mock_info.clone(),
+        tx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_crypto::secp::Signature;
+    use ckb_mock_tx_types::{MockInfo, MockInput};
+    use ckb_types::{
+        core::{ScriptHashType, TransactionBuilder},
+        h256,
+        packed::{CellInput, CellOutput, OutPoint, Script},
+    };
+
+    // Three inputs, the first and the last locked by the same secp256k1_blake160_sighash_all script. The messages
+    // expected below were computed apart from this crate, serializing the transaction and hashing it by hand.
+    fn mock_tx(witnesses: Vec<Bytes>) -> MockTransaction {
+        let lock = |arg: u8| {
+            Script::new_builder()
+                .code_hash(h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").pack())
+                .hash_type(ScriptHashType::Type.into())
+                .args(Bytes::from(vec![arg; 20]).pack())
+                .build()
+        };
+        let inputs: Vec<CellInput> =
+            (0..3u8).map(|i| CellInput::new(OutPoint::new(Byte32::new([i + 1; 32]), i as u32), 0)).collect();
+        let tx = TransactionBuilder::default()
+            .inputs(inputs.clone())
+            .output(CellOutput::new_builder().capacity(1000u64.pack()).lock(lock(0xaa)).build())
+            .output_data(Bytes::new().pack())
+            .witnesses(witnesses.into_iter().map(|witness| witness.pack()))
+            .build();
+        let mock_inputs = inputs
+            .into_iter()
+            .zip([0xaa, 0xbb, 0xaa])
+            .map(|(input, arg)| MockInput {
+                input,
+                output: CellOutput::new_builder().lock(lock(arg)).build(),
+                ..Default::default()
+            })
+            .collect();
+        MockTransaction {
+            mock_info: MockInfo {
+                inputs: mock_inputs,
+                ..Default::default()
+            },
+            tx: tx.data(),
+        }
+    }
+
+    fn full_witnesses() -> Vec<Bytes> {
+        let first = WitnessArgs::new_builder()
+            .lock(Some(Bytes::from(vec![0xff; 3])).pack())
+            .input_type(Some(Bytes::from(vec![0x01])).pack())
+            .build();
+        vec![
+            first.as_bytes(),
+            Bytes::new(),
+            Bytes::from(vec![0x33, 0x44]),
+            Bytes::from(vec![0x55, 0x66]),
+        ]
+    }
+
+    #[test]
+    fn test_sighash_all_message() {
+        let mock_tx = mock_tx(full_witnesses());
+        assert_eq!(
+            mock_tx.tx.calc_tx_hash().unpack(),
+            h256!("0xa36419e1fac013841d32c916d9233dbf52b604a8bac05b8d07998a8b98a13b89")
+        );
+        // The group of input 0 hashes its first witness with the lock zeroed, witness 2 and the witness past the inputs.
+        let message = h256!("0x110d4c63c553334d04ccb3b003b90caeaafd893a41ec02c8264e5b89bb7356ad");
+        assert_eq!(sighash_all_message(&mock_tx, 0), Ok(message.clone()));
+        assert_eq!(sighash_all_message(&mock_tx, 2), Ok(message));
+        // Input 1 is alone in its group, its empty witness is taken as an empty WitnessArgs.
+        assert_eq!(
+            sighash_all_message(&mock_tx, 1),
+            Ok(h256!(
+                "0x7c98c83f77d3ae2f3cc6c038d2a95ff39dc6acc5626c81525a33224eb134627f"
+            ))
+        );
+        assert!(sighash_all_message(&mock_tx, 3).is_err());
+    }
+
+    #[test]
+    fn test_sighash_all_message_missing_witnesses() {
+        let mock_tx = mock_tx(vec![Bytes::new(), Bytes::from(vec![0x11, 0x22])]);
+        assert_eq!(
+            sighash_all_message(&mock_tx, 0),
+            Ok(h256!(
+                "0x122d47cdd0e43e58d31fe49bb9128540f1d3978a8a8b1fa148bb19d33e673d9b"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        let privkey = Privkey::from(h256!(
+            "0xd00c06bfd800d27397002dca6fb0993d5ba6399b4238b2f29ee9deb97593d2bc"
+        ));
+        let mock_tx = mock_tx(full_witnesses());
+        let signed = sign(&mock_tx, 2, &privkey).unwrap();
+        let witnesses: Vec<packed::Bytes> = signed.tx.witnesses().into_iter().collect();
+        let first = WitnessArgs::from_slice(&witnesses[0].raw_data()).unwrap();
+        assert_eq!(first.input_type().to_opt().unwrap().raw_data(), Bytes::from(vec![0x01]));
+        let signature = Signature::from_slice(&first.lock().to_opt().unwrap().raw_data()).unwrap();
+        let message = sighash_all_message(&mock_tx, 2).unwrap();
+        assert_eq!(signature.recover(&message).unwrap(), privkey.pubkey().unwrap());
+        // The signature doesn't change the message, which is computed with the lock zeroed.
+        assert_eq!(sighash_all_message(&signed, 2), Ok(message));
+        let others: Vec<Bytes> = witnesses[1..].iter().map(|witness| witness.raw_data()).collect();
+        assert_eq!(others, full_witnesses()[1..].to_vec());
+    }
+}
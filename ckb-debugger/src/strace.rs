@@ -0,0 +1,586 @@
+use crate::misc::{
+    FCLOSE_SYSCALL_NUMBER, FEOF_SYSCALL_NUMBER, FERROR_SYSCALL_NUMBER, FFLUSH_SYSCALL_NUMBER, FGETC_SYSCALL_NUMBER,
+    FOPEN_SYSCALL_NUMBER, FPUTC_SYSCALL_NUMBER, FPUTS_SYSCALL_NUMBER, FREAD_SYSCALL_NUMBER, FREOPEN_SYSCALL_NUMBER,
+    FSEEK_SYSCALL_NUMBER, FTELL_SYSCALL_NUMBER, FWRITE_SYSCALL_NUMBER, NOW_SYSCALL_NUMBER, RANDOM_SYSCALL_NUMBER,
+    READ_STREAM_SYSCALL_NUMBER, READ_SYSCALL_NUMBER, STREAM_INDEX_SYSCALL_NUMBER, STREAM_LENGTH_SYSCALL_NUMBER,
+    STREAM_SEEK_SYSCALL_NUMBER,
+};
+use ckb_types::packed::Byte32;
+use ckb_vm::{
+    registers::{A0, A1, A2, A3, A4, A5, A7},
+    Error, Memory, Register, SupportMachine, Syscalls,
+};
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+// Syscalls served by ckb-script, see ckb_consts.h of ckb-c-stdlib.
+const VM_VERSION: u64 = 2041;
+const CURRENT_CYCLES: u64 = 2042;
+const EXEC: u64 = 2043;
+const LOAD_TRANSACTION: u64 = 2051;
+const LOAD_SCRIPT: u64 = 2052;
+const LOAD_TX_HASH: u64 = 2061;
+const LOAD_SCRIPT_HASH: u64 = 2062;
+const LOAD_CELL: u64 = 2071;
+const LOAD_HEADER: u64 = 2072;
+const LOAD_INPUT: u64 = 2073;
+const LOAD_WITNESS: u64 = 2074;
+const LOAD_CELL_BY_FIELD: u64 = 2081;
+const LOAD_HEADER_BY_FIELD: u64 = 2082;
+const LOAD_INPUT_BY_FIELD: u64 = 2083;
+const LOAD_CELL_DATA_AS_CODE: u64 = 2091;
+const LOAD_CELL_DATA: u64 = 2092;
+const DEBUG: u64 = 2177;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Address,
+    Number,
+    // Address of the buffer length, which the syscall overwrites with the length of the whole data.
+    Length,
+    Source,
+    CellField,
+    HeaderField,
+    InputField,
+}
+
+use Kind::*;
+
+type Signature = (&'static str, &'static [(&'static str, Kind)]);
+
+const LOAD: &[(&str, Kind)] = &[("addr", Address), ("length", Length), ("offset", Number)];
+const LOAD_FROM: &[(&str, Kind)] = &[
+    ("addr", Address),
+    ("length", Length),
+    ("offset", Number),
+    ("index", Number),
+    ("source", Source),
+];
+const STREAM: &[(&str, Kind)] = &[("stream", Number)];
+const UNKNOWN: &[(&str, Kind)] = &[
+    ("a0", Number),
+    ("a1", Number),
+    ("a2", Number),
+    ("a3", Number),
+    ("a4", Number),
+    ("a5", Number),
+];
+
+fn load_by_field(field: Kind) -> &'static [(&'static str, Kind)] {
+    match field {
+        CellField => &[
+            ("addr", Address),
+            ("length", Length),
+            ("offset", Number),
+            ("index", Number),
+            ("source", Source),
+            ("field", CellField),
+        ],
+        HeaderField => &[
+            ("addr", Address),
+            ("length", Length),
+            ("offset", Number),
+            ("index", Number),
+            ("source", Source),
+            ("field", HeaderField),
+        ],
+        _ => &[
+            ("addr", Address),
+            ("length", Length),
+            ("offset", Number),
+            ("index", Number),
+            ("source", Source),
+            ("field", InputField),
+        ],
+    }
+}
+
+fn signature(number: u64) -> Option<Signature> {
+    let signature: Signature = match number {
+        VM_VERSION => ("vm_version", &[]),
+        CURRENT_CYCLES => ("current_cycles", &[]),
+        EXEC => (
+            "exec",
+            &[
+                ("index", Number),
+                ("source", Source),
+                ("place", Number),
+                ("bounds", Number),
+                ("argc", Number),
+                ("argv", Address),
+            ],
+        ),
+        LOAD_TRANSACTION => ("load_transaction", LOAD),
+        LOAD_SCRIPT => ("load_script", LOAD),
+        LOAD_TX_HASH => ("load_tx_hash", LOAD),
+        LOAD_SCRIPT_HASH => ("load_script_hash", LOAD),
+        LOAD_CELL => ("load_cell", LOAD_FROM),
+        LOAD_HEADER => ("load_header", LOAD_FROM),
+        LOAD_INPUT => ("load_input", LOAD_FROM),
+        LOAD_WITNESS => ("load_witness", LOAD_FROM),
+        LOAD_CELL_BY_FIELD => ("load_cell_by_field", load_by_field(CellField)),
+        LOAD_HEADER_BY_FIELD => ("load_header_by_field", load_by_field(HeaderField)),
+        LOAD_INPUT_BY_FIELD => ("load_input_by_field", load_by_field(InputField)),
+        LOAD_CELL_DATA_AS_CODE => (
+            "load_cell_data_as_code",
+            &[
+                ("addr", Address),
+                ("memory_size", Number),
+                ("offset", Number),
+                ("length", Number),
+                ("index", Number),
+                ("source", Source),
+            ],
+        ),
+        LOAD_CELL_DATA => ("load_cell_data", LOAD_FROM),
+        DEBUG => ("debug", &[("message", Address)]),
+        READ_SYSCALL_NUMBER => ("read", &[("buf", Address), ("count", Number)]),
+        NOW_SYSCALL_NUMBER => ("now", &[]),
+        RANDOM_SYSCALL_NUMBER => ("random", &[]),
+        FOPEN_SYSCALL_NUMBER => ("fopen", &[("path", Address), ("mode", Address)]),
+        FREOPEN_SYSCALL_NUMBER => ("freopen", &[("path", Address), ("mode", Address), ("stream", Number)]),
+        FREAD_SYSCALL_NUMBER => (
+            "fread",
+            &[
+                ("ptr", Address),
+                ("size", Number),
+                ("nitems", Number),
+                ("stream", Number),
+            ],
+        ),
+        FEOF_SYSCALL_NUMBER => ("feof", STREAM),
+        FERROR_SYSCALL_NUMBER => ("ferror", STREAM),
+        FGETC_SYSCALL_NUMBER => ("fgetc", STREAM),
+        FCLOSE_SYSCALL_NUMBER => ("fclose", STREAM),
+        FTELL_SYSCALL_NUMBER => ("ftell", STREAM),
+        FSEEK_SYSCALL_NUMBER => ("fseek", &[("stream", Number), ("offset", Number), ("whence", Number)]),
+        FWRITE_SYSCALL_NUMBER => (
+            "fwrite",
+            &[
+                ("ptr", Address),
+                ("size", Number),
+                ("nitems", Number),
+                ("stream", Number),
+            ],
+        ),
+        FPUTC_SYSCALL_NUMBER => ("fputc", &[("ch", Number), ("stream", Number)]),
+        FPUTS_SYSCALL_NUMBER => ("fputs", &[("s", Address), ("stream", Number)]),
+        FFLUSH_SYSCALL_NUMBER => ("fflush", STREAM),
+        READ_STREAM_SYSCALL_NUMBER => (
+            "read_stream",
+            &[("buf", Address), ("count", Number), ("stream", Number)],
+        ),
+        STREAM_INDEX_SYSCALL_NUMBER => ("stream_index", &[("name", Address)]),
+        STREAM_LENGTH_SYSCALL_NUMBER => ("stream_length", STREAM),
+        STREAM_SEEK_SYSCALL_NUMBER => ("stream_seek", &[("stream", Number), ("offset", Number)]),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+fn source_name(source: u64) -> Option<&'static str> {
+    Some(match source {
+        1 => "input",
+        2 => "output",
+        3 => "cell_dep",
+        4 => "header_dep",
+        0x0100000000000001 => "group_input",
+        0x0100000000000002 => "group_output",
+        _ => return None,
+    })
+}
+
+fn field_name(kind: Kind, field: u64) -> Option<&'static str> {
+    let names: &[&str] = match kind {
+        CellField => &[
+            "capacity",
+            "data_hash",
+            "lock",
+            "lock_hash",
+            "type",
+            "type_hash",
+            "occupied_capacity",
+        ],
+        HeaderField => &["epoch_number", "epoch_start_block_number", "epoch_length"],
+        InputField => &["out_point", "since"],
+        _ => &[],
+    };
+    names.get(field as usize).copied()
+}
+
+enum ArgValue {
+    Address(u64),
+    Number(u64),
+    Name(&'static str),
+}
+
+impl fmt::Display for ArgValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgValue::Address(addr) => write!(f, "0x{:x}", addr),
+            ArgValue::Number(n) => write!(f, "{}", n),
+            ArgValue::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<&ArgValue> for Value {
+    fn from(value: &ArgValue) -> Value {
+        match value {
+            ArgValue::Address(n) | ArgValue::Number(n) => json!(n),
+            ArgValue::Name(name) => json!(name),
+        }
+    }
+}
+
+// A single ecall made by a script.
+struct SyscallEntry {
+    number: u64,
+    name: &'static str,
+    args: Vec<(&'static str, ArgValue)>,
+    // Content of a0 after the call, None if the call failed.
+    ret: Option<u64>,
+    error: Option<String>,
+    // Bytes the call wrote to the memory of the script, when it's known.
+    written: Option<u64>,
+    cycles_before: u64,
+    cycles_after: u64,
+}
+
+impl fmt::Display for SyscallEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        write!(
+            f,
+            "[{}..{}] {}({})",
+            self.cycles_before,
+            self.cycles_after,
+            self.name,
+            args.join(", ")
+        )?;
+        if let Some(ret) = self.ret {
+            write!(f, " = {}", ret as i64)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " = {}", error)?;
+        }
+        if let Some(written) = self.written {
+            write!(f, ", {} bytes written", written)?;
+        }
+        Ok(())
+    }
+}
+
+impl SyscallEntry {
+    fn to_json(&self, script_hash: &Byte32) -> Value {
+        let args: Map<String, Value> = self.args.iter().map(|(name, value)| (name.to_string(), value.into())).collect();
+        json!({
+            "script_hash": format!("0x{:x}", script_hash),
+            "number": self.number,
+            "name": self.name,
+            "args": args,
+            "ret": self.ret.map(|ret| ret as i64),
+            "error": self.error,
+            "written": self.written,
+            "cycles_before": self.cycles_before,
+            "cycles_after": self.cycles_after,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    JsonLines,
+}
+
+struct Sink {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    // Text traces print a line whenever entries switch to another script group.
+    last_group: Option<Byte32>,
+}
+
+/// Where syscall entries of every machine are written to, in the order they are made.
+#[derive(Clone)]
+pub struct SyscallTrace {
+    sink: Rc<RefCell<Sink>>,
+}
+
+impl fmt::Debug for SyscallTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyscallTrace").field("format", &self.sink.borrow().format).finish()
+    }
+}
+
+impl SyscallTrace {
+    pub fn new(writer: Box<dyn Write>, format: TraceFormat) -> Self {
+        Self {
+            sink: Rc::new(RefCell::new(Sink {
+                writer,
+                format,
+                last_group: None,
+            })),
+        }
+    }
+
+    // Entries are written as they are made, so that the trace of a run that aborts is complete.
+    fn write(&self, script_hash: &Byte32, entry: &SyscallEntry) {
+        let mut sink = self.sink.borrow_mut();
+        let result = match sink.format {
+            TraceFormat::Text => {
+                let mut result = Ok(());
+                if sink.last_group.as_ref() != Some(script_hash) {
+                    sink.last_group = Some(script_hash.clone());
+                    result = writeln!(sink.writer, "Script group 0x{:x}:", script_hash);
+                }
+                result.and_then(|_| writeln!(sink.writer, "{}", entry))
+            }
+            TraceFormat::JsonLines => writeln!(sink.writer, "{}", entry.to_json(script_hash)),
+        };
+        if let Err(err) = result.and_then(|_| sink.writer.flush()) {
+            warn!("Failed to write the syscall trace: {}", err);
+        }
+    }
+}
+
+/// Wraps the syscalls of a machine, dispatching each ecall to them and logging it to a trace.
+pub struct SyscallTracer<'a, Mac> {
+    syscalls: Vec<Box<dyn Syscalls<Mac> + 'a>>,
+    trace: SyscallTrace,
+    script_hash: Byte32,
+}
+
+impl<'a, Mac> SyscallTracer<'a, Mac> {
+    pub fn new(syscalls: Vec<Box<dyn Syscalls<Mac> + 'a>>, trace: SyscallTrace, script_hash: Byte32) -> Self {
+        Self {
+            syscalls,
+            trace,
+            script_hash,
+        }
+    }
+}
+
+fn load_u64<Mac: SupportMachine>(machine: &mut Mac, addr: u64) -> Option<u64> {
+    machine.memory_mut().load64(&Mac::REG::from_u64(addr)).ok().map(|value| value.to_u64())
+}
+
+impl<'a, Mac: SupportMachine> Syscalls<Mac> for SyscallTracer<'a, Mac> {
+    fn initialize(&mut self, machine: &mut Mac) -> Result<(), Error> {
+        for syscall in &mut self.syscalls {
+            syscall.initialize(machine)?;
+        }
+        Ok(())
+    }
+
+    fn ecall(&mut self, machine: &mut Mac) -> Result<bool, Error> {
+        let number = machine.registers()[A7].to_u64();
+        let regs: Vec<u64> = [A0, A1, A2, A3, A4, A5].iter().map(|r| machine.registers()[*r].to_u64()).collect();
+        let (name, kinds) = signature(number).unwrap_or(("unknown", UNKNOWN));
+        let mut args = vec![];
+        let mut length_before = None;
+        for (i, (arg, kind)) in kinds.iter().enumerate() {
+            let value = match kind {
+                Address => ArgValue::Address(regs[i]),
+                Number => ArgValue::Number(regs[i]),
+                Length => {
+                    length_before = load_u64(machine, regs[i]);
+                    match length_before {
+                        Some(length) => ArgValue::Number(length),
+                        None => ArgValue::Address(regs[i]),
+                    }
+                }
+                Source => source_name(regs[i]).map(ArgValue::Name).unwrap_or(ArgValue::Number(regs[i])),
+                kind => field_name(*kind, regs[i]).map(ArgValue::Name).unwrap_or(ArgValue::Number(regs[i])),
+            };
+            args.push((*arg, value));
+        }
+
+        let cycles_before = machine.cycles();
+        let mut result = Ok(false);
+        for syscall in &mut self.syscalls {
+            result = syscall.ecall(machine);
+            if !matches!(result, Ok(false)) {
+                break;
+            }
+        }
+        let (ret, error) = match &result {
+            Ok(true) => (Some(machine.registers()[A0].to_u64()), None),
+            Ok(false) => (None, Some(String::from("not a syscall"))),
+            Err(err) => (None, Some(format!("{:?}", err))),
+        };
+        let written = match (number, ret) {
+            (_, Some(0)) if kinds.iter().any(|(_, kind)| matches!(kind, Length)) => {
+                let length_after = load_u64(machine, regs[1]);
+                length_before.zip(length_after).map(|(before, after)| before.min(after))
+            }
+            (LOAD_CELL_DATA_AS_CODE, Some(0)) => Some(regs[3]),
+            (READ_SYSCALL_NUMBER | READ_STREAM_SYSCALL_NUMBER, Some(ret)) if (ret as i64) > 0 => Some(ret),
+            (FREAD_SYSCALL_NUMBER, Some(ret)) => Some(ret.saturating_mul(regs[1])),
+            _ => None,
+        };
+        let entry = SyscallEntry {
+            number,
+            name,
+            args,
+            ret,
+            error,
+            written,
+            cycles_before,
+            cycles_after: machine.cycles(),
+        };
+        self.trace.write(&self.script_hash, &entry);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CoreMachineType;
+    use ckb_script::ScriptVersion;
+    use ckb_vm::CoreMachine;
+
+    // Where the length argument of load syscalls points to.
+    const LENGTH_ADDR: u64 = 0x1000;
+
+    // A writer whose content can still be read after the trace took it.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Answers a few syscalls the way ckb-script and the debugger do, load syscalls have 100 bytes of data.
+    struct Stub;
+
+    impl Syscalls<CoreMachineType> for Stub {
+        fn initialize(&mut self, _machine: &mut CoreMachineType) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn ecall(&mut self, machine: &mut CoreMachineType) -> Result<bool, Error> {
+            let ret = match machine.registers()[A7] {
+                LOAD_CELL_BY_FIELD | LOAD_HEADER_BY_FIELD | LOAD_INPUT_BY_FIELD => {
+                    let addr = machine.registers()[A1];
+                    machine.memory_mut().store64(&addr, &100)?;
+                    0
+                }
+                LOAD_CELL_DATA_AS_CODE => 0,
+                READ_SYSCALL_NUMBER => 5,
+                READ_STREAM_SYSCALL_NUMBER => u64::max_value(),
+                FREAD_SYSCALL_NUMBER => 3,
+                _ => return Ok(false),
+            };
+            machine.set_register(A0, ret);
+            Ok(true)
+        }
+    }
+
+    // Makes the syscall `number` with `args` through a tracer, the length argument of load syscalls being `length`,
+    // and returns the entry written to the trace.
+    fn trace(number: u64, args: &[u64], length: u64) -> Value {
+        let buffer = Buffer::default();
+        let trace = SyscallTrace::new(Box::new(buffer.clone()), TraceFormat::JsonLines);
+        let mut tracer = SyscallTracer::new(vec![Box::new(Stub)], trace, Byte32::default());
+        let mut machine = CoreMachineType::new(
+            ScriptVersion::V1.vm_isa(),
+            ScriptVersion::V1.vm_version(),
+            u64::max_value(),
+        );
+        machine.memory_mut().store64(&LENGTH_ADDR, &length).unwrap();
+        for (reg, arg) in [A0, A1, A2, A3, A4, A5].iter().zip(args) {
+            machine.set_register(*reg, *arg);
+        }
+        machine.set_register(A7, number);
+        let _ = tracer.ecall(&mut machine);
+        let data = buffer.0.borrow();
+        serde_json::from_slice(&data).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(signature(LOAD_CELL).map(|(name, _)| name), Some("load_cell"));
+        assert_eq!(
+            signature(STREAM_SEEK_SYSCALL_NUMBER).map(|(name, _)| name),
+            Some("stream_seek")
+        );
+        assert!(signature(2000).is_none());
+        assert_eq!(source_name(3), Some("cell_dep"));
+        assert_eq!(source_name(0x0100000000000002), Some("group_output"));
+        assert_eq!(source_name(0x0100000000000003), None);
+        assert_eq!(field_name(CellField, 6), Some("occupied_capacity"));
+        assert_eq!(field_name(HeaderField, 2), Some("epoch_length"));
+        assert_eq!(field_name(InputField, 2), None);
+        assert_eq!(field_name(Number, 0), None);
+    }
+
+    #[test]
+    fn test_args() {
+        let entry = trace(
+            LOAD_CELL_BY_FIELD,
+            &[0x2000, LENGTH_ADDR, 0, 1, 0x0100000000000001, 3],
+            40,
+        );
+        assert_eq!(entry["name"], "load_cell_by_field");
+        assert_eq!(
+            entry["args"],
+            json!({"addr": 0x2000, "length": 40, "offset": 0, "index": 1, "source": "group_input", "field": "lock_hash"})
+        );
+        assert_eq!(entry["ret"], 0);
+
+        let entry = trace(LOAD_HEADER_BY_FIELD, &[0x2000, LENGTH_ADDR, 0, 0, 4, 1], 40);
+        assert_eq!(entry["args"]["source"], "header_dep");
+        assert_eq!(entry["args"]["field"], "epoch_start_block_number");
+
+        // Values without a name are kept as numbers.
+        let entry = trace(LOAD_INPUT_BY_FIELD, &[0x2000, LENGTH_ADDR, 0, 0, 9, 7], 40);
+        assert_eq!(entry["args"]["source"], 9);
+        assert_eq!(entry["args"]["field"], 7);
+
+        let entry = trace(1234, &[1, 2, 3, 4, 5, 6], 0);
+        assert_eq!(entry["name"], "unknown");
+        assert_eq!(
+            entry["args"],
+            json!({"a0": 1, "a1": 2, "a2": 3, "a3": 4, "a4": 5, "a5": 6})
+        );
+        assert_eq!(entry["ret"], Value::Null);
+        assert_eq!(entry["error"], "not a syscall");
+    }
+
+    #[test]
+    fn test_written() {
+        // Load syscalls write as much of the data as fits in the buffer.
+        assert_eq!(
+            trace(LOAD_CELL_BY_FIELD, &[0x2000, LENGTH_ADDR, 0, 0, 1, 0], 40)["written"],
+            40
+        );
+        assert_eq!(
+            trace(LOAD_CELL_BY_FIELD, &[0x2000, LENGTH_ADDR, 0, 0, 1, 0], 200)["written"],
+            100
+        );
+        assert_eq!(
+            trace(LOAD_CELL_DATA_AS_CODE, &[0x2000, 4096, 0, 64, 0, 3], 0)["written"],
+            64
+        );
+        assert_eq!(trace(READ_SYSCALL_NUMBER, &[0x2000, 16], 0)["written"], 5);
+        let entry = trace(READ_STREAM_SYSCALL_NUMBER, &[0x2000, 16, 1], 0);
+        assert_eq!(
+            (entry["ret"].clone(), entry["written"].clone()),
+            (json!(-1), Value::Null)
+        );
+        assert_eq!(trace(FREAD_SYSCALL_NUMBER, &[0x2000, 4, 10, 1], 0)["written"], 12);
+        assert_eq!(trace(1234, &[], 0)["written"], Value::Null);
+    }
+}
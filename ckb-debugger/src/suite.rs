@@ -0,0 +1,461 @@
+use crate::misc::{Clock, SyscallSettings};
+use crate::{debugger_syscalls, load_resource, machine_init, template};
+use ckb_mock_tx_types::{MockTransaction, ReprMockTransaction};
+use ckb_script::{cost_model::transferred_byte_cycles, ScriptGroupType, ScriptVersion, TransactionScriptsVerifier};
+use ckb_types::{
+    core::cell::resolve_transaction,
+    packed::{Byte32, CellOutput},
+    prelude::*,
+};
+use ckb_vm::{CoreMachine, SupportMachine};
+use ckb_vm_pprof::{PProfMachine, Profile};
+use regex::Regex;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+fn default_max_cycles() -> u64 {
+    70_000_000
+}
+
+/// A script run with its expected outcome. Without `expected_exit_code` or `expected_error` the script is expected
+/// to exit with 0.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    pub name: Option<String>,
+    /// Path of the tx file, relative to the manifest.
+    pub tx_file: PathBuf,
+    pub script_group_type: String,
+    pub script_hash: Option<String>,
+    pub cell_type: Option<String>,
+    pub cell_index: Option<usize>,
+    #[serde(default = "default_max_cycles")]
+    pub max_cycles: u64,
+    /// `fast` or `full`, `fast` if not given.
+    pub mode: Option<String>,
+    pub script_version: Option<u32>,
+    /// Seed of the random syscall, 0 if not given.
+    pub seed: Option<u64>,
+    /// Base of the virtual clock in nanoseconds, advancing 1 ns per cycle. The real clock is used if not given.
+    pub virtual_clock: Option<u64>,
+    pub expected_exit_code: Option<i8>,
+    /// Text the error of the run should contain.
+    pub expected_error: Option<String>,
+    /// Regex the debug output of the script should match, messages are joined by newlines.
+    pub expected_debug_output: Option<String>,
+}
+
+impl Case {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.tx_file.to_string_lossy().to_string())
+    }
+}
+
+/// The manifest read by the `test` subcommand.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub cases: Vec<Case>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Status {
+    Pass,
+    /// The script ran but not as expected.
+    Fail(String),
+    /// The case couldn't be run.
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub status: Status,
+    pub cycles: Option<u64>,
+    pub time: Duration,
+}
+
+fn select_script(mock_tx: &MockTransaction, case: &Case, group_type: ScriptGroupType) -> Result<Byte32, String> {
+    if let Some(script_hash) = &case.script_hash {
+        let bytes = hex::decode(script_hash.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+        if bytes.len() != 32 {
+            return Err(format!("Invalid script hash {}", script_hash));
+        }
+        return Ok(Byte32::from_slice(&bytes).unwrap());
+    }
+    let (cell_type, cell_index) = match (&case.cell_type, case.cell_index) {
+        (Some(cell_type), Some(cell_index)) => (cell_type.as_str(), cell_index),
+        _ => {
+            return Err(String::from(
+                "Either script_hash, or cell_type and cell_index must be given",
+            ))
+        }
+    };
+    let output: Option<CellOutput> = match cell_type {
+        "input" => mock_tx.mock_info.inputs.get(cell_index).map(|input| input.output.clone()),
+        "output" => mock_tx.tx.raw().outputs().get(cell_index),
+        _ => return Err(format!("Invalid cell type {}", cell_type)),
+    };
+    let output = output.ok_or_else(|| format!("No {} cell at index {}", cell_type, cell_index))?;
+    match (group_type, cell_type) {
+        (ScriptGroupType::Lock, "input") => Ok(output.calc_lock_hash()),
+        (ScriptGroupType::Type, _) => output
+            .type_()
+            .to_opt()
+            .map(|script| script.calc_script_hash())
+            .ok_or_else(|| format!("The {} cell at index {} has no type script", cell_type, cell_index)),
+        _ => Err(String::from("Lock scripts of outputs don't run")),
+    }
+}
+
+// Loads and runs the case, returning the run result with the cycles and debug output, or why it can't be run.
+fn execute(case: &Case, base: &Path) -> Result<(Result<i8, ckb_vm::Error>, u64, String), String> {
+    let tx_file = base.join(&case.tx_file);
+    let source = std::fs::read_to_string(&tx_file).map_err(|e| format!("{}: {}", tx_file.display(), e))?;
+    let root = tx_file.parent().unwrap_or_else(|| Path::new("."));
+    let rendered = template::render(&source, root).map_err(|e| e.to_string())?;
+    let repr_mock_tx: ReprMockTransaction = serde_json::from_str(&rendered).map_err(|e| e.to_string())?;
+    let mock_tx: MockTransaction = repr_mock_tx.into();
+    let group_type: ScriptGroupType = serde_plain::from_str(&case.script_group_type)
+        .map_err(|_| format!("Invalid script group type {}", case.script_group_type))?;
+    let script_version = match case.script_version {
+        Some(0) => ScriptVersion::V0,
+        None | Some(1) => ScriptVersion::V1,
+        Some(version) => return Err(format!("Invalid script version {}", version)),
+    };
+    let script_hash = select_script(&mock_tx, case, group_type)?;
+
+    let resource = load_resource(&mock_tx, None)?;
+    let rtx = resolve_transaction(mock_tx.core_transaction(), &mut HashSet::new(), &resource, &resource)
+        .map_err(|e| e.to_string())?;
+    let mut verifier = TransactionScriptsVerifier::new(&rtx, &resource);
+    let debug_output = Rc::new(RefCell::new(vec![]));
+    let debug_printer = debug_output.clone();
+    verifier.set_debug_printer(Box::new(move |_: &Byte32, message: &str| {
+        debug_printer.borrow_mut().push(message.to_string());
+    }));
+    let script_group = verifier
+        .find_script_group(group_type, &script_hash)
+        .ok_or_else(|| format!("No script group with hash 0x{:x}", script_hash))?;
+    let program = verifier.extract_script(&script_group.script).map_err(|e| e.to_string())?;
+    let syscall_settings = SyscallSettings {
+        seed: case.seed.unwrap_or(0),
+        clock: match case.virtual_clock {
+            Some(base) => Clock::Virtual { base, ns_per_cycle: 1 },
+            None => Clock::Real,
+        },
+        fs_root: None,
+        files: mock_tx.mock_info.files.clone(),
+        fs_host: false,
+        trace: None,
+    };
+    let machine = machine_init(
+        &verifier,
+        script_group,
+        script_version,
+        case.max_cycles,
+        None,
+        debugger_syscalls(None, syscall_settings),
+        None,
+    );
+    let (result, cycles) = match case.mode.as_deref() {
+        None | Some("fast") => {
+            let mut machine = machine;
+            let result = machine
+                .load_program(&program, &[])
+                .and_then(|bytes| machine.add_cycles(transferred_byte_cycles(bytes)))
+                .and_then(|_| machine.run());
+            (result, machine.cycles())
+        }
+        Some("full") => {
+            let mut machine = PProfMachine::new(machine, Profile::new(&program).map_err(|e| e.to_string())?);
+            let result = machine
+                .load_program(&program, &[])
+                .and_then(|bytes| machine.machine.add_cycles(transferred_byte_cycles(bytes)))
+                .and_then(|_| machine.run());
+            (result, machine.machine.cycles())
+        }
+        Some(mode) => return Err(format!("Invalid mode {}", mode)),
+    };
+    let debug_output = debug_output.borrow().join("\n");
+    Ok((result, cycles, debug_output))
+}
+
+fn check(case: &Case, result: &Result<i8, ckb_vm::Error>, debug_output: &str) -> Result<Status, String> {
+    match (result, &case.expected_error) {
+        (Ok(exit_code), None) => {
+            let expected = case.expected_exit_code.unwrap_or(0);
+            if *exit_code != expected {
+                return Ok(Status::Fail(format!("exit code {}, expected {}", exit_code, expected)));
+            }
+        }
+        (Ok(exit_code), Some(expected)) => {
+            return Ok(Status::Fail(format!(
+                "exit code {}, expected error {}",
+                exit_code, expected
+            )));
+        }
+        (Err(err), None) => return Ok(Status::Fail(format!("error {:?}", err))),
+        (Err(err), Some(expected)) => {
+            if !format!("{:?}", err).contains(expected.as_str()) {
+                return Ok(Status::Fail(format!("error {:?}, expected {}", err, expected)));
+            }
+        }
+    }
+    if let Some(pattern) = &case.expected_debug_output {
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        if !regex.is_match(debug_output) {
+            return Ok(Status::Fail(format!(
+                "debug output doesn't match {}:\n{}",
+                pattern, debug_output
+            )));
+        }
+    }
+    Ok(Status::Pass)
+}
+
+fn run_case(case: &Case, base: &Path) -> Outcome {
+    let start = Instant::now();
+    // Panics in a case are reported like any other error, instead of taking the other cases down with it.
+    let run = catch_unwind(AssertUnwindSafe(|| {
+        let (result, cycles, debug_output) = execute(case, base)?;
+        Ok((check(case, &result, &debug_output)?, cycles))
+    }));
+    let (status, cycles) = match run {
+        Ok(Ok((status, cycles))) => (status, Some(cycles)),
+        Ok(Err(err)) => (Status::Error(err), None),
+        Err(_) => (Status::Error(String::from("panicked")), None),
+    };
+    Outcome {
+        name: case.name(),
+        status,
+        cycles,
+        time: start.elapsed(),
+    }
+}
+
+/// Runs the cases of `manifest` on `jobs` threads. Tx files are looked up relative to `base`. Outcomes are returned
+/// in the order of the cases.
+pub fn run(manifest: &Manifest, base: &Path, jobs: usize) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new(vec![None; manifest.cases.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let case = match manifest.cases.get(i) {
+                    Some(case) => case,
+                    None => break,
+                };
+                let outcome = run_case(case, base);
+                outcomes.lock().unwrap()[i] = Some(outcome);
+            });
+        }
+    });
+    outcomes.into_inner().unwrap().into_iter().map(|outcome| outcome.unwrap()).collect()
+}
+
+// Control characters other than tabs and line breaks aren't allowed in XML 1.0, not even as character references.
+// Scripts may print them in debug messages, they are written as Rust escapes, e.g. `\u{7}`.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => escaped.extend(c.escape_unicode()),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats outcomes as a JUnit XML report, failures and errors carry their message.
+pub fn junit(outcomes: &[Outcome]) -> String {
+    let failures = outcomes.iter().filter(|o| matches!(o.status, Status::Fail(_))).count();
+    let errors = outcomes.iter().filter(|o| matches!(o.status, Status::Error(_))).count();
+    let time: f64 = outcomes.iter().map(|o| o.time.as_secs_f64()).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!(
+        "<testsuite name=\"ckb-debugger\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+        outcomes.len(),
+        failures,
+        errors,
+        time
+    );
+    for outcome in outcomes {
+        xml += &format!(
+            "  <testcase name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&outcome.name),
+            outcome.time.as_secs_f64()
+        );
+        let (tag, message) = match &outcome.status {
+            Status::Pass => {
+                xml += "/>\n";
+                continue;
+            }
+            Status::Fail(message) => ("failure", message),
+            Status::Error(message) => ("error", message),
+        };
+        let first_line = message.lines().next().unwrap_or_default();
+        xml += &format!(
+            ">\n    <{} message=\"{}\">{}</{}>\n  </testcase>\n",
+            tag,
+            xml_escape(first_line),
+            xml_escape(message),
+            tag
+        );
+    }
+    xml += "</testsuite>\n";
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(json: &str) -> Case {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn fail_message(status: Result<Status, String>) -> String {
+        match status {
+            Ok(Status::Fail(message)) => message,
+            other => panic!("expected a failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"cases": [
+                {"tx_file": "a.json", "script_group_type": "lock", "cell_type": "input", "cell_index": 0},
+                {"name": "b", "tx_file": "b.json", "script_group_type": "type", "script_hash": "0x00",
+                 "max_cycles": 100, "mode": "full", "expected_exit_code": -1}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.cases.len(), 2);
+        assert_eq!(manifest.cases[0].name(), "a.json");
+        assert_eq!(manifest.cases[0].max_cycles, 70_000_000);
+        assert_eq!(manifest.cases[0].cell_index, Some(0));
+        assert_eq!(manifest.cases[1].name(), "b");
+        assert_eq!(manifest.cases[1].max_cycles, 100);
+        assert_eq!(manifest.cases[1].expected_exit_code, Some(-1));
+
+        assert!(serde_json::from_str::<Manifest>(r#"{"cases": [{"tx_file": "a.json"}]}"#).is_err());
+        assert!(serde_json::from_str::<Manifest>(
+            r#"{"cases": [{"tx_file": "a.json", "script_group_type": "lock", "exit_code": 0}]}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Manifest>(r#"{"cases": [], "jobs": 2}"#).is_err());
+    }
+
+    #[test]
+    fn test_check_exit_code() {
+        let default = case(r#"{"tx_file": "a.json", "script_group_type": "lock"}"#);
+        assert!(matches!(check(&default, &Ok(0), ""), Ok(Status::Pass)));
+        assert_eq!(fail_message(check(&default, &Ok(1), "")), "exit code 1, expected 0");
+        assert_eq!(
+            fail_message(check(&default, &Err(ckb_vm::Error::MemOutOfBound), "")),
+            "error MemOutOfBound"
+        );
+
+        let failing = case(r#"{"tx_file": "a.json", "script_group_type": "lock", "expected_exit_code": 5}"#);
+        assert!(matches!(check(&failing, &Ok(5), ""), Ok(Status::Pass)));
+        assert_eq!(fail_message(check(&failing, &Ok(0), "")), "exit code 0, expected 5");
+    }
+
+    #[test]
+    fn test_check_expected_error() {
+        let case = case(r#"{"tx_file": "a.json", "script_group_type": "lock", "expected_error": "OutOfBound"}"#);
+        assert!(matches!(
+            check(&case, &Err(ckb_vm::Error::MemOutOfBound), ""),
+            Ok(Status::Pass)
+        ));
+        assert_eq!(
+            fail_message(check(&case, &Err(ckb_vm::Error::CyclesExceeded), "")),
+            "error CyclesExceeded, expected OutOfBound"
+        );
+        assert_eq!(
+            fail_message(check(&case, &Ok(0), "")),
+            "exit code 0, expected error OutOfBound"
+        );
+    }
+
+    #[test]
+    fn test_check_debug_output() {
+        let case = case(r#"{"tx_file": "a.json", "script_group_type": "lock", "expected_debug_output": "^sum \\d+$"}"#);
+        assert!(matches!(check(&case, &Ok(0), "sum 42"), Ok(Status::Pass)));
+        assert_eq!(
+            fail_message(check(&case, &Ok(0), "sum x")),
+            "debug output doesn't match ^sum \\d+$:\nsum x"
+        );
+        // The exit code is checked first.
+        assert_eq!(fail_message(check(&case, &Ok(1), "sum 42")), "exit code 1, expected 0");
+
+        let invalid = Case {
+            expected_debug_output: Some(String::from("(")),
+            ..case
+        };
+        assert!(check(&invalid, &Ok(0), "").is_err());
+    }
+
+    #[test]
+    fn test_run_missing_tx_file() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"cases": [
+                {"name": "first", "tx_file": "missing-1.json", "script_group_type": "lock"},
+                {"name": "second", "tx_file": "missing-2.json", "script_group_type": "lock"}
+            ]}"#,
+        )
+        .unwrap();
+        let outcomes = run(&manifest, Path::new("does-not-exist"), 4);
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].name, "first");
+        assert_eq!(outcomes[1].name, "second");
+        for outcome in &outcomes {
+            assert!(matches!(&outcome.status, Status::Error(message) if message.contains("missing-")));
+            assert_eq!(outcome.cycles, None);
+        }
+    }
+
+    #[test]
+    fn test_junit() {
+        let outcome = |name: &str, status: Status| Outcome {
+            name: name.to_string(),
+            status,
+            cycles: None,
+            time: Duration::from_millis(1500),
+        };
+        let xml = junit(&[
+            outcome("pass", Status::Pass),
+            outcome(
+                "a<b>",
+                Status::Fail(String::from("output \"x\" & \u{7}bell\u{0}\nsecond\tline")),
+            ),
+            outcome("error", Status::Error(String::from("no file"))),
+        ]);
+        assert!(xml.contains("tests=\"3\" failures=\"1\" errors=\"1\" time=\"4.500\""));
+        assert!(xml.contains("<testcase name=\"pass\" time=\"1.500\"/>\n"));
+        assert!(xml.contains("<testcase name=\"a&lt;b&gt;\""));
+        assert!(xml.contains(
+            "<failure message=\"output &quot;x&quot; &amp; \\u{7}bell\\u{0}\">\
+             output &quot;x&quot; &amp; \\u{7}bell\\u{0}\nsecond\tline</failure>"
+        ));
+        assert!(xml.contains("<error message=\"no file\">no file</error>"));
+        assert!(!xml.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')));
+
+        assert_eq!(xml_escape("\u{1f}\u{ffff}\u{7f}é"), "\\u{1f}\\u{ffff}\u{7f}é");
+    }
+}
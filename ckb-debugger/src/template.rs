@@ -0,0 +1,427 @@
+//! Templates in tx files, written as `{{ <directive> <argument>... }}`. Arguments are separated by whitespace, can be
+//! quoted with `"` when they contain spaces, and can be templates themselves, e.g.
+//! `{{ script_hash {{ hash build/lock }} data1 0x00 }}`. The directives are:
+//!
+//! - `data <path>`: content of a file.
+//! - `hash <path>`: blake2b hash of a file.
+//! - `hex <path> [<offset> [<length>]]`: part of a file, from `offset` to the end when no length is given.
+//! - `env <name>`: value of an environment variable, inserted as is.
+//! - `type_id [<output index>]`: type id args computed from the first input of the transaction, output index defaults
+//!   to 0.
+//! - `script_hash <code hash> <hash type> <args>`: hash of a script.
+//! - `witness_args <lock> <input type> <output type>`: molecule encoded WitnessArgs, `_` leaves a field empty.
+//!
+//! Relative paths are resolved against the directory of the tx file. The path given to `data` and `hash` may also
+//! contain spaces without quotes, as long as no other argument follows.
+use ckb_hash::{blake2b_256, new_blake2b};
+use ckb_mock_tx_types::ReprMockTransaction;
+use ckb_types::{
+    bytes::Bytes,
+    core::ScriptHashType,
+    packed::{Byte32, CellInput, Script, WitnessArgs},
+    prelude::*,
+};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub struct TemplateError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+// Errors returned from main are printed with Debug, which should read the same as Display.
+impl fmt::Debug for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "template error at line {} column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    root: PathBuf,
+    first_input: Option<CellInput>,
+    uses_type_id: bool,
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    match s.strip_prefix("0x") {
+        Some(h) => hex::decode(h).map_err(|e| format!("invalid hex string {}: {}", s, e)),
+        None => Err(format!("invalid hex string {}, expect a 0x prefix", s)),
+    }
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    let result = match s.strip_prefix("0x") {
+        Some(h) => u64::from_str_radix(h, 16),
+        None => s.parse(),
+    };
+    result.map_err(|_| format!("invalid number {}", s))
+}
+
+fn to_hex(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(data))
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, pos: usize, message: String) -> TemplateError {
+        let before = &self.source[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+        TemplateError { line, column, message }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // Parses the template starting at the current position, which is right at its `{{`.
+    fn template(&mut self) -> Result<String, TemplateError> {
+        let start = self.pos;
+        self.pos += 2;
+        let mut args: Vec<(usize, String)> = vec![];
+        // Whether all arguments are plain words, neither quoted nor templates.
+        let mut plain = true;
+        let close;
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            let arg_start = self.pos;
+            if rest.is_empty() {
+                return Err(self.error(start, String::from("unclosed template")));
+            } else if rest.starts_with("}}") {
+                close = self.pos;
+                self.pos += 2;
+                break;
+            } else if rest.starts_with("{{") {
+                plain = false;
+                let value = self.template()?;
+                args.push((arg_start, value));
+            } else if let Some(quoted) = rest.strip_prefix('"') {
+                plain = false;
+                let end = quoted.find('"').ok_or_else(|| self.error(arg_start, String::from("unclosed quote")))?;
+                args.push((arg_start, quoted[..end].to_string()));
+                self.pos += end + 2;
+            } else {
+                let end = rest.find(|c: char| c.is_whitespace() || c == '"').unwrap_or(rest.len());
+                let end = [rest.find("}}"), rest.find("{{")].into_iter().flatten().fold(end, |end, i| end.min(i));
+                args.push((arg_start, rest[..end].to_string()));
+                self.pos += end;
+            }
+        }
+        if args.is_empty() {
+            return Err(self.error(start, String::from("empty template")));
+        }
+        let (_, directive) = args.remove(0);
+        // Before arguments could be quoted, everything after `data` and `hash` was the path, spaces included.
+        if (directive == "data" || directive == "hash") && plain && args.len() > 1 {
+            let path = self.source[args[0].0..close].trim_end().to_string();
+            args = vec![(args[0].0, path)];
+        }
+        self.evaluate(&directive, &args).map_err(|(pos, message)| self.error(pos.unwrap_or(start), message))
+    }
+
+    fn read_file(&self, arg: &(usize, String)) -> Result<Vec<u8>, (Option<usize>, String)> {
+        let path = self.root.join(&arg.1);
+        std::fs::read(&path).map_err(|e| (Some(arg.0), format!("failed to read {}: {}", path.display(), e)))
+    }
+
+    // Errors carry the position of the argument at fault, or None to point at the whole template.
+    fn evaluate(&mut self, directive: &str, args: &[(usize, String)]) -> Result<String, (Option<usize>, String)> {
+        let arity = |min: usize, max: usize| {
+            if args.len() < min || args.len() > max {
+                let expected = if min == max {
+                    min.to_string()
+                } else {
+                    format!("{} to {}", min, max)
+                };
+                Err((
+                    None,
+                    format!("{} expects {} arguments, got {}", directive, expected, args.len()),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let at = |arg: &(usize, String)| {
+            let pos = arg.0;
+            move |message: String| (Some(pos), message)
+        };
+        match directive {
+            "data" => {
+                arity(1, 1)?;
+                Ok(to_hex(&self.read_file(&args[0])?))
+            }
+            "hash" => {
+                arity(1, 1)?;
+                Ok(to_hex(&blake2b_256(self.read_file(&args[0])?)))
+            }
+            "hex" => {
+                arity(1, 3)?;
+                let data = self.read_file(&args[0])?;
+                let offset = match args.get(1) {
+                    Some(arg) => parse_number(&arg.1).map_err(at(arg))? as usize,
+                    None => 0,
+                };
+                let end = match args.get(2) {
+                    Some(arg) => offset.saturating_add(parse_number(&arg.1).map_err(at(arg))? as usize),
+                    None => data.len(),
+                };
+                match data.get(offset..end) {
+                    Some(slice) => Ok(to_hex(slice)),
+                    None => Err((
+                        None,
+                        format!(
+                            "range {}..{} is out of bounds, {} has {} bytes",
+                            offset,
+                            end,
+                            args[0].1,
+                            data.len()
+                        ),
+                    )),
+                }
+            }
+            "env" => {
+                arity(1, 1)?;
+                std::env::var(&args[0].1).map_err(|e| (Some(args[0].0), format!("{}: {}", args[0].1, e)))
+            }
+            "type_id" => {
+                arity(0, 1)?;
+                let index = match args.first() {
+                    Some(arg) => parse_number(&arg.1).map_err(at(arg))?,
+                    None => 0,
+                };
+                self.uses_type_id = true;
+                let first_input = match &self.first_input {
+                    Some(first_input) => first_input,
+                    // Stands in until the first input is known, see render.
+                    None => return Ok(to_hex(&[0u8; 32])),
+                };
+                let mut hasher = new_blake2b();
+                hasher.update(first_input.as_slice());
+                hasher.update(&index.to_le_bytes());
+                let mut result = [0u8; 32];
+                hasher.finalize(&mut result);
+                Ok(to_hex(&result))
+            }
+            "script_hash" => {
+                arity(3, 3)?;
+                let code_hash = parse_hex(&args[0].1).map_err(at(&args[0]))?;
+                let code_hash: [u8; 32] = code_hash
+                    .try_into()
+                    .map_err(|_| (Some(args[0].0), format!("code hash {} is not 32 bytes long", args[0].1)))?;
+                let hash_type = match args[1].1.as_str() {
+                    "data" => ScriptHashType::Data,
+                    "type" => ScriptHashType::Type,
+                    "data1" => ScriptHashType::Data1,
+                    other => return Err((Some(args[1].0), format!("invalid hash type {}", other))),
+                };
+                let script_args = parse_hex(&args[2].1).map_err(at(&args[2]))?;
+                let script = Script::new_builder()
+                    .code_hash(Byte32::new(code_hash))
+                    .hash_type(hash_type.into())
+                    .args(Bytes::from(script_args).pack())
+                    .build();
+                Ok(to_hex(script.calc_script_hash().as_slice()))
+            }
+            "witness_args" => {
+                arity(3, 3)?;
+                let mut fields = vec![];
+                for arg in args {
+                    let field = if arg.1 == "_" {
+                        None
+                    } else {
+                        Some(Bytes::from(parse_hex(&arg.1).map_err(at(arg))?))
+                    };
+                    fields.push(field);
+                }
+                let witness_args = WitnessArgs::new_builder()
+                    .lock(fields[0].clone().pack())
+                    .input_type(fields[1].clone().pack())
+                    .output_type(fields[2].clone().pack())
+                    .build();
+                Ok(to_hex(witness_args.as_slice()))
+            }
+            _ => Err((None, format!("unknown directive {}", directive))),
+        }
+    }
+
+    fn render(&mut self) -> Result<String, TemplateError> {
+        let mut output = String::with_capacity(self.source.len());
+        while let Some(i) = self.rest().find("{{") {
+            output.push_str(&self.rest()[..i]);
+            self.pos += i;
+            output.push_str(&self.template()?);
+        }
+        output.push_str(self.rest());
+        Ok(output)
+    }
+}
+
+fn render_with(source: &str, root: &Path, first_input: Option<CellInput>) -> Result<(String, bool), TemplateError> {
+    let mut parser = Parser {
+        source,
+        pos: 0,
+        root: root.to_path_buf(),
+        first_input,
+        uses_type_id: false,
+    };
+    let output = parser.render()?;
+    Ok((output, parser.uses_type_id))
+}
+
+/// Replaces all templates in a tx file. `root` is the directory relative paths are resolved against.
+pub fn render(source: &str, root: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let (output, uses_type_id) = render_with(source, root, None)?;
+    if !uses_type_id {
+        return Ok(output);
+    }
+    // The first input has to be known before type ids can be computed, so they are filled in by a second pass over
+    // the file once the rest of it can be parsed.
+    let repr_tx: ReprMockTransaction = serde_json::from_str(&output)?;
+    let first_input: CellInput = match repr_tx.tx.inputs.into_iter().next() {
+        Some(input) => input.into(),
+        None => return Err("type_id requires the transaction to have at least one input".into()),
+    };
+    let (output, _) = render_with(source, root, Some(first_input))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ckb-debugger-template-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.bin"), (0..8).collect::<Vec<u8>>()).unwrap();
+        std::fs::write(root.join("with space.bin"), [1, 2]).unwrap();
+        root
+    }
+
+    fn render_str(source: &str) -> Result<String, String> {
+        render(source, &root()).map_err(|e| e.to_string())
+    }
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let err = render_with(source, &root(), None).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn test_file_directives() {
+        let hash = "0x19e145c7a2bb9afd9bf0f3ce20cc415645a6c0c996c6e6b367e72f28fe0d5107";
+        assert_eq!(render_str("\"{{ data a.bin }}\"").unwrap(), "\"0x0001020304050607\"");
+        assert_eq!(render_str("{{hash a.bin}}").unwrap(), hash);
+        assert_eq!(render_str("{{ hex a.bin 2 3 }}").unwrap(), "0x020304");
+        assert_eq!(render_str("{{ hex a.bin 0x6 }}").unwrap(), "0x0607");
+        assert_eq!(render_str("{{ hex a.bin }}").unwrap(), "0x0001020304050607");
+        assert_eq!(
+            render_str("{{ script_hash {{ hash a.bin }} data1 0x00 }}").unwrap(),
+            "0x3a2286d7f9dc3646010e10a74261aac73c9f5706795094c4b7f6ec0d251e7617"
+        );
+    }
+
+    #[test]
+    fn test_paths_with_spaces() {
+        assert_eq!(render_str("{{ data \"with space.bin\" }}").unwrap(), "0x0102");
+        assert_eq!(render_str("{{ data with space.bin }}").unwrap(), "0x0102");
+        assert_eq!(render_str("{{ hex \"with space.bin\" 1 }}").unwrap(), "0x02");
+        assert_eq!(
+            render_str("{{ hash  with space.bin  }}").unwrap(),
+            render_str("{{ hash \"with space.bin\" }}").unwrap()
+        );
+        assert!(render_str("{{ data with {{ env HOME }} }}").is_err());
+    }
+
+    #[test]
+    fn test_value_directives() {
+        std::env::set_var("CKB_DEBUGGER_TEMPLATE_TEST", "0xabcd");
+        assert_eq!(render_str("{{ env CKB_DEBUGGER_TEMPLATE_TEST }}").unwrap(), "0xabcd");
+        assert_eq!(
+            render_str("{{ witness_args 0x0102 _ \"0x03\" }}").unwrap(),
+            "0x1b0000001000000016000000160000000200000001020100000003"
+        );
+        assert_eq!(
+            render_str("{{ witness_args _ _ _ }}").unwrap(),
+            format!("0x{}", hex::encode(WitnessArgs::default().as_slice()))
+        );
+    }
+
+    #[test]
+    fn test_type_id() {
+        let tx = r#"{
+            "mock_info": { "inputs": [], "cell_deps": [], "header_deps": [] },
+            "tx": {
+                "version": "0x0",
+                "cell_deps": [],
+                "header_deps": [],
+                "inputs": [{
+                    "since": "0x0",
+                    "previous_output": {
+                        "tx_hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+                        "index": "0x2"
+                    }
+                }],
+                "outputs": [],
+                "outputs_data": [],
+                "witnesses": ["{{ type_id 1 }}"]
+            }
+        }"#;
+        let rendered = render_str(tx).unwrap();
+        assert!(rendered.contains("\"0xfcbe2b07ca150c99c434f5e4b8c5ce53530cd93998c331a467f450bcc22dca23\""));
+        let no_input = tx.replace(&tx[tx.find("[{").unwrap()..tx.find("}]").unwrap() + 2], "[]");
+        assert!(render_str(&no_input).unwrap_err().contains("at least one input"));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        // Errors point at the argument at fault when there is one, at the template otherwise.
+        let (line, column, message) = error_at("{\n  \"data\": \"{{ data missing.bin }}\"\n}");
+        assert_eq!((line, column), (2, 20));
+        assert!(message.starts_with("failed to read"));
+        assert_eq!(
+            error_at("x {{ magic a.bin }}"),
+            (1, 3, String::from("unknown directive magic"))
+        );
+        assert_eq!(
+            error_at("{{ hex a.bin 6 4 }}"),
+            (1, 1, String::from("range 6..10 is out of bounds, a.bin has 8 bytes"))
+        );
+        assert_eq!(error_at("{{ hex a.bin 1 x }}").1, 16);
+        assert_eq!(error_at("{{ script_hash 0x00 data1 0x00 }}").1, 16);
+        assert_eq!(error_at("{{ script_hash {{ hash a.bin }} data2 0x00 }}").1, 33);
+        assert_eq!(error_at("{{ data }}").2, "data expects 1 arguments, got 0");
+        assert_eq!(error_at("\n\n  {{ data a.bin").0, 3);
+        assert_eq!(error_at("\n\n  {{ data a.bin").1, 3);
+        assert_eq!(error_at("{{ data \"a.bin }}").1, 9);
+        assert_eq!(error_at("{{ }}").2, "empty template");
+        assert_eq!(
+            TemplateError {
+                line: 2,
+                column: 7,
+                message: String::from("unclosed template")
+            }
+            .to_string(),
+            "template error at line 2 column 7: unclosed template"
+        );
+    }
+}
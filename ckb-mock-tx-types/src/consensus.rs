@@ -0,0 +1,182 @@
+//! The transaction rules CKB enforces besides running scripts, checked by the transaction verifiers of
+//! `ckb-verification`. The verifiers take the block committing the transaction from the tip given by the user, and
+//! read the headers they need from the resource of the mock transaction.
+use crate::Resource;
+use ckb_chain_spec::consensus::Consensus;
+use ckb_traits::HeaderProvider;
+use ckb_types::{
+    core::{cell::ResolvedTransaction, BlockNumber, EpochNumberWithFraction, HeaderBuilder, HeaderView},
+    packed::Byte32,
+    prelude::*,
+};
+use ckb_verification::{
+    CapacityVerifier, NonContextualTransactionVerifier, TimeRelativeTransactionVerifier, TxVerifyEnv,
+};
+use std::fmt;
+
+/// Code hash of the Nervos DAO type script, the same on mainnet and testnet.
+pub const DAO_TYPE_HASH: [u8; 32] = [
+    0x82, 0xd7, 0x6d, 0x1b, 0x75, 0xfe, 0x2f, 0xd9, 0xa2, 0x7d, 0xfb, 0xaa, 0x65, 0xa0, 0x39, 0x22, 0x1a, 0x38, 0x0d,
+    0x76, 0xc9, 0x26, 0xf3, 0x78, 0xd3, 0xf8, 0x1c, 0xf3, 0xe7, 0xe1, 0x3f, 0x2e,
+];
+
+const SINCE_RELATIVE_FLAG: u64 = 1 << 63;
+const SINCE_METRIC_MASK: u64 = 0b0110_0000 << 56;
+const SINCE_RESERVED_MASK: u64 = 0b0001_1111 << 56;
+
+/// The block committing the transaction, `since` fields of inputs and the maturity of cellbase inputs are checked
+/// against it. Those checks are skipped, with a message saying so, when a tip value they need isn't given.
+#[derive(Clone, Debug, Default)]
+pub struct ChainTip {
+    pub block_number: Option<BlockNumber>,
+    pub epoch: Option<EpochNumberWithFraction>,
+    /// Median time of the 37 blocks before the tip in milliseconds, which is what timestamp since values are
+    /// compared with.
+    pub median_time: Option<u64>,
+}
+
+/// A consensus rule the transaction breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsensusViolation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ConsensusViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+// Serves the headers of the resource, plus the parent of the tip, whose timestamp is the median time given by the
+// user. The parent is numbered 0 so that the median time of the tip is taken from it alone.
+struct TipLoader<'a> {
+    resource: &'a Resource,
+    parent: HeaderView,
+}
+
+impl<'a> HeaderProvider for TipLoader<'a> {
+    fn get_header(&self, hash: &Byte32) -> Option<HeaderView> {
+        if hash == &self.parent.hash() {
+            Some(self.parent.clone())
+        } else {
+            self.resource.get_header(hash)
+        }
+    }
+}
+
+// The median time a relative timestamp counts from is taken from the blocks before the block of the input, `count` of
+// them or back to the genesis block. Returns the hash of the first header of those, or of the block itself, missing
+// from the resource.
+fn missing_ancestor(resource: &Resource, block_hash: &Byte32, count: usize) -> Option<Byte32> {
+    let mut hash = match resource.get_header(block_hash) {
+        Some(header) => header.parent_hash(),
+        None => return Some(block_hash.clone()),
+    };
+    for _ in 0..count {
+        match resource.get_header(&hash) {
+            Some(header) if header.number() == 0 => return None,
+            Some(header) => hash = header.parent_hash(),
+            None => return Some(hash),
+        }
+    }
+    None
+}
+
+fn report<E: fmt::Display>(violations: &mut Vec<ConsensusViolation>, rule: &'static str, result: Result<(), E>) {
+    if let Err(err) = result {
+        violations.push(ConsensusViolation {
+            rule,
+            message: err.to_string(),
+        });
+    }
+}
+
+// The time relative rules need the tip values their inputs are compared with, and the headers of the block of an input
+// whose since is a relative timestamp and of the blocks before it. Reports what is missing.
+fn missing_context(
+    rtx: &ResolvedTransaction,
+    resource: &Resource,
+    tip: &ChainTip,
+    consensus: &Consensus,
+) -> Vec<ConsensusViolation> {
+    let mut violations = vec![];
+    for (i, (input, cell)) in rtx.transaction.inputs().into_iter().zip(&rtx.resolved_inputs).enumerate() {
+        let info = cell.transaction_info.as_ref();
+        if info.map(|info| info.block_number > 0 && info.is_cellbase()).unwrap_or(false) && tip.epoch.is_none() {
+            violations.push(ConsensusViolation {
+                rule: "since",
+                message: format!(
+                    "input {} spends a cellbase, its maturity can't be checked without a tip epoch",
+                    i
+                ),
+            });
+        }
+        let since: u64 = input.since().unpack();
+        if since == 0 || since & SINCE_RESERVED_MASK != 0 {
+            continue;
+        }
+        let relative = since & SINCE_RELATIVE_FLAG != 0;
+        let missing = match (since & SINCE_METRIC_MASK) >> 61 {
+            0 if tip.block_number.is_none() => String::from("a tip block number"),
+            1 if tip.epoch.is_none() => String::from("a tip epoch"),
+            2 if tip.median_time.is_none() => String::from("a tip median time"),
+            2 if relative => {
+                let count = consensus.median_time_block_count();
+                let block_hash = info.map(|info| info.block_hash.clone()).unwrap_or_default();
+                match missing_ancestor(resource, &block_hash, count) {
+                    Some(hash) => format!(
+                        "the header 0x{:x}, the block of the input and the {} blocks before it are needed",
+                        hash, count
+                    ),
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        violations.push(ConsensusViolation {
+            rule: "since",
+            message: format!("since of input {} can't be checked without {}", i, missing),
+        });
+    }
+    violations
+}
+
+/// Checks `rtx` against the consensus rules other than scripts, with the mainnet consensus parameters: version, size,
+/// empty inputs or outputs, duplicate deps and outputs data count under the rule `transaction`, capacity under
+/// `capacity`, and the `since` of inputs and the maturity of cellbase inputs under `since`. Each verifier stops at the
+/// first rule broken, so at most one violation is returned for each.
+pub fn verify_consensus(rtx: &ResolvedTransaction, resource: &Resource, tip: &ChainTip) -> Vec<ConsensusViolation> {
+    let consensus = Consensus::default();
+    let mut violations = vec![];
+    report(
+        &mut violations,
+        "transaction",
+        NonContextualTransactionVerifier::new(&rtx.transaction, &consensus).verify(),
+    );
+    report(
+        &mut violations,
+        "capacity",
+        CapacityVerifier::new(rtx, Byte32::new(DAO_TYPE_HASH)).verify(),
+    );
+
+    let missing = missing_context(rtx, resource, tip, &consensus);
+    if !missing.is_empty() {
+        violations.extend(missing);
+        return violations;
+    }
+    let parent = HeaderBuilder::default().number(0u64.pack()).timestamp(tip.median_time.unwrap_or(0).pack()).build();
+    let tip = HeaderBuilder::default()
+        .number(tip.block_number.unwrap_or(0).pack())
+        .epoch(tip.epoch.unwrap_or_else(|| EpochNumberWithFraction::new(0, 0, 1)).full_value().pack())
+        .parent_hash(parent.hash())
+        .build();
+    let tx_env = TxVerifyEnv::new_commit(&tip);
+    let loader = TipLoader { resource, parent };
+    report(
+        &mut violations,
+        "since",
+        TimeRelativeTransactionVerifier::new(rtx, &consensus, &loader, &tx_env).verify(),
+    );
+    violations
+}
@@ -7,21 +7,48 @@ use ckb_types::{
     core::{
         cell::{CellMeta, CellMetaBuilder, CellProvider, CellStatus, HeaderChecker},
         error::OutPointError,
-        DepType, EpochNumberWithFraction, HeaderView, TransactionInfo, TransactionView,
+        BlockNumber, DepType, EpochNumberWithFraction, HeaderView, TransactionInfo, TransactionView,
     },
     packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, OutPointVec, Transaction},
     prelude::*,
     H256,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "consensus")]
+mod consensus;
+mod lint;
+#[cfg(feature = "rpc")]
+mod rpc;
+#[cfg(feature = "consensus")]
+pub use consensus::{verify_consensus, ChainTip, ConsensusViolation, DAO_TYPE_HASH};
+pub use lint::{lint, LintProblem};
+#[cfg(feature = "rpc")]
+pub use rpc::RpcResourceLoader;
+
+/// Code hash of the type id script built into CKB, which needs no cell dep.
+pub const TYPE_ID_CODE_HASH: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x54, 0x59, 0x50, 0x45, 0x5f, 0x49, 0x44,
+];
+
+/// Formats an out point as `0x<tx hash>#<index>`, the way problems and summaries refer to cells.
+pub fn short_out_point(out_point: &OutPoint) -> String {
+    let index: u32 = out_point.index().unpack();
+    format!("0x{:x}#{}", out_point.tx_hash(), index)
+}
 
+// Besides the hash of the block a cell is in, its number and epoch, and the index of the transaction creating the
+// cell in the block can be given. They default to block 0, epoch 0/0/1800 and index 0.
 #[derive(Clone, Default)]
 pub struct MockCellDep {
     pub cell_dep: CellDep,
     pub output: CellOutput,
     pub data: Bytes,
     pub header: Option<Byte32>,
+    pub block_number: Option<BlockNumber>,
+    pub epoch: Option<EpochNumberWithFraction>,
+    pub tx_index: Option<usize>,
 }
 
 #[derive(Clone, Default)]
@@ -30,6 +57,9 @@ pub struct MockInput {
     pub output: CellOutput,
     pub data: Bytes,
     pub header: Option<Byte32>,
+    pub block_number: Option<BlockNumber>,
+    pub epoch: Option<EpochNumberWithFraction>,
+    pub tx_index: Option<usize>,
 }
 
 #[derive(Clone, Default)]
@@ -37,6 +67,14 @@ pub struct MockInfo {
     pub inputs: Vec<MockInput>,
     pub cell_deps: Vec<MockCellDep>,
     pub header_deps: Vec<HeaderView>,
+    /// Content of the files the debugger syscalls serve to scripts, by path.
+    pub files: BTreeMap<String, Bytes>,
+}
+
+impl MockInfo {
+    fn has_cell_dep(&self, out_point: &OutPoint) -> bool {
+        self.cell_deps.iter().any(|mock_cell| &mock_cell.cell_dep.out_point() == out_point)
+    }
 }
 
 /// A wrapper transaction with mock inputs and deps
@@ -98,6 +136,123 @@ impl MockTransaction {
     pub fn core_transaction(&self) -> TransactionView {
         self.tx.clone().into_view()
     }
+
+    // Block context of a cell, filled from the mock info when the cell is there.
+    fn transaction_info(&self, out_point: &OutPoint, header: Option<Byte32>) -> TransactionInfo {
+        let context = self
+            .mock_info
+            .inputs
+            .iter()
+            .find(|mock_input| &mock_input.input.previous_output() == out_point)
+            .map(|mock_input| (mock_input.block_number, mock_input.epoch, mock_input.tx_index))
+            .or_else(|| {
+                self.mock_info
+                    .cell_deps
+                    .iter()
+                    .find(|mock_cell| &mock_cell.cell_dep.out_point() == out_point)
+                    .map(|mock_cell| (mock_cell.block_number, mock_cell.epoch, mock_cell.tx_index))
+            });
+        let (block_number, epoch, tx_index) = context.unwrap_or_default();
+        TransactionInfo::new(
+            block_number.unwrap_or(0),
+            epoch.unwrap_or_else(|| EpochNumberWithFraction::new(0, 0, 1800)),
+            header.unwrap_or_default(),
+            tx_index.unwrap_or(0),
+        )
+    }
+
+    /// Build a complete mock transaction for `tx`, with every input, cell dep and header dep loaded through
+    /// `loader`. The cells listed in dep groups are included as well, each cell once even when several deps bring it
+    /// in. The number and epoch of the block a cell is in are taken from its header, the index of the transaction in
+    /// the block is left out.
+    pub fn from_loader<L: MockResourceLoader>(tx: Transaction, mut loader: L) -> Result<MockTransaction, String> {
+        let view = tx.clone().into_view();
+        let mut mock_info = MockInfo::default();
+
+        for input in view.inputs().into_iter() {
+            let (output, data, header) = loader
+                .get_live_cell(input.previous_output())?
+                .ok_or_else(|| format!("Can not get CellOutput by input={}", input))?;
+            let (block_number, epoch) = load_block_context(&mut loader, &header)?;
+            mock_info.inputs.push(MockInput {
+                input,
+                output,
+                data,
+                header,
+                block_number,
+                epoch,
+                tx_index: None,
+            });
+        }
+
+        for cell_dep in view.cell_deps().into_iter() {
+            let (output, data, header) = loader
+                .get_live_cell(cell_dep.out_point())?
+                .ok_or_else(|| format!("Can not get CellOutput by dep={}", cell_dep))?;
+            if cell_dep.dep_type() == DepType::DepGroup.into() {
+                for sub_out_point in OutPointVec::from_slice(&data)
+                    .map_err(|err| format!("Parse dep group data error: {}", err))?
+                    .into_iter()
+                {
+                    if mock_info.has_cell_dep(&sub_out_point) {
+                        continue;
+                    }
+                    let (sub_output, sub_data, sub_header) = loader
+                        .get_live_cell(sub_out_point.clone())?
+                        .ok_or_else(|| format!("(dep group) Can not get CellOutput by out_point={}", sub_out_point))?;
+                    let (block_number, epoch) = load_block_context(&mut loader, &sub_header)?;
+                    mock_info.cell_deps.push(MockCellDep {
+                        cell_dep: CellDep::new_builder()
+                            .out_point(sub_out_point)
+                            .dep_type(DepType::Code.into())
+                            .build(),
+                        output: sub_output,
+                        data: sub_data,
+                        header: sub_header,
+                        block_number,
+                        epoch,
+                        tx_index: None,
+                    });
+                }
+            }
+            if mock_info.has_cell_dep(&cell_dep.out_point()) {
+                continue;
+            }
+            let (block_number, epoch) = load_block_context(&mut loader, &header)?;
+            mock_info.cell_deps.push(MockCellDep {
+                cell_dep,
+                output,
+                data,
+                header,
+                block_number,
+                epoch,
+                tx_index: None,
+            });
+        }
+
+        for block_hash in view.header_deps().into_iter() {
+            let header = loader
+                .get_header(block_hash.unpack())?
+                .ok_or_else(|| format!("Can not get header: {:x}", block_hash))?;
+            mock_info.header_deps.push(header);
+        }
+
+        Ok(MockTransaction { mock_info, tx })
+    }
+}
+
+// Number and epoch of the block a loaded cell is in.
+fn load_block_context<L: MockResourceLoader>(
+    loader: &mut L,
+    header: &Option<Byte32>,
+) -> Result<(Option<BlockNumber>, Option<EpochNumberWithFraction>), String> {
+    match header {
+        Some(hash) => Ok(loader
+            .get_header(hash.unpack())?
+            .map(|header| (Some(header.number()), Some(header.epoch())))
+            .unwrap_or_default()),
+        None => Ok((None, None)),
+    }
 }
 
 pub trait MockResourceLoader {
@@ -128,7 +283,7 @@ impl Resource {
                 .ok_or_else(|| format!("Can not get CellOutput by input={}", input))?;
             let cell_meta = CellMetaBuilder::from_cell_output(output, data)
                 .out_point(input.previous_output())
-                .transaction_info(Self::build_transaction_info(header))
+                .transaction_info(mock_tx.transaction_info(&input.previous_output(), header))
                 .build();
             required_cells.insert(input.previous_output(), cell_meta);
         }
@@ -149,14 +304,14 @@ impl Resource {
 
                     let sub_cell_meta = CellMetaBuilder::from_cell_output(sub_output, sub_data)
                         .out_point(sub_out_point.clone())
-                        .transaction_info(Self::build_transaction_info(sub_header))
+                        .transaction_info(mock_tx.transaction_info(&sub_out_point, sub_header))
                         .build();
                     required_cells.insert(sub_out_point, sub_cell_meta);
                 }
             }
             let cell_meta = CellMetaBuilder::from_cell_output(output, data)
                 .out_point(cell_dep.out_point())
-                .transaction_info(Self::build_transaction_info(header))
+                .transaction_info(mock_tx.transaction_info(&cell_dep.out_point(), header))
                 .build();
             required_cells.insert(cell_dep.out_point(), cell_meta);
         }
@@ -173,16 +328,6 @@ impl Resource {
             required_headers,
         })
     }
-
-    fn build_transaction_info(header: Option<Byte32>) -> TransactionInfo {
-        // Only block hash might be used by script syscalls
-        TransactionInfo::new(
-            0,
-            EpochNumberWithFraction::new(0, 0, 1800),
-            header.unwrap_or_default(),
-            0,
-        )
-    }
 }
 
 impl HeaderChecker for Resource {
@@ -222,6 +367,12 @@ pub struct ReprMockCellDep {
     pub output: json_types::CellOutput,
     pub data: json_types::JsonBytes,
     pub header: Option<H256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<json_types::BlockNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<json_types::EpochNumberWithFraction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<json_types::Uint32>,
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ReprMockInput {
@@ -229,12 +380,20 @@ pub struct ReprMockInput {
     pub output: json_types::CellOutput,
     pub data: json_types::JsonBytes,
     pub header: Option<H256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<json_types::BlockNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<json_types::EpochNumberWithFraction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<json_types::Uint32>,
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ReprMockInfo {
     pub inputs: Vec<ReprMockInput>,
     pub cell_deps: Vec<ReprMockCellDep>,
     pub header_deps: Vec<json_types::HeaderView>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, json_types::JsonBytes>,
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ReprMockTransaction {
@@ -249,6 +408,9 @@ impl From<MockCellDep> for ReprMockCellDep {
             output: dep.output.into(),
             data: json_types::JsonBytes::from_bytes(dep.data),
             header: dep.header.map(|h| h.unpack()),
+            block_number: dep.block_number.map(Into::into),
+            epoch: dep.epoch.map(|e| e.full_value().into()),
+            tx_index: dep.tx_index.map(|i| (i as u32).into()),
         }
     }
 }
@@ -259,6 +421,9 @@ impl From<ReprMockCellDep> for MockCellDep {
             output: dep.output.into(),
             data: dep.data.into_bytes(),
             header: dep.header.map(|h| h.pack()),
+            block_number: dep.block_number.map(Into::into),
+            epoch: dep.epoch.map(|e| EpochNumberWithFraction::from_full_value(e.into())),
+            tx_index: dep.tx_index.map(|i| u32::from(i) as usize),
         }
     }
 }
@@ -270,6 +435,9 @@ impl From<MockInput> for ReprMockInput {
             output: input.output.into(),
             data: json_types::JsonBytes::from_bytes(input.data),
             header: input.header.map(|h| h.unpack()),
+            block_number: input.block_number.map(Into::into),
+            epoch: input.epoch.map(|e| e.full_value().into()),
+            tx_index: input.tx_index.map(|i| (i as u32).into()),
         }
     }
 }
@@ -280,6 +448,9 @@ impl From<ReprMockInput> for MockInput {
             output: input.output.into(),
             data: input.data.into_bytes(),
             header: input.header.map(|h| h.pack()),
+            block_number: input.block_number.map(Into::into),
+            epoch: input.epoch.map(|e| EpochNumberWithFraction::from_full_value(e.into())),
+            tx_index: input.tx_index.map(|i| u32::from(i) as usize),
         }
     }
 }
@@ -300,6 +471,7 @@ impl From<MockInfo> for ReprMockInfo {
                     json_header
                 })
                 .collect(),
+            files: info.files.into_iter().map(|(path, data)| (path, json_types::JsonBytes::from_bytes(data))).collect(),
         }
     }
 }
@@ -318,6 +490,7 @@ impl From<ReprMockInfo> for MockInfo {
                     HeaderView::from(json_header).fake_hash(hash)
                 })
                 .collect(),
+            files: info.files.into_iter().map(|(path, data)| (path, data.into_bytes())).collect(),
         }
     }
 }
@@ -0,0 +1,243 @@
+use crate::{short_out_point, MockTransaction, ReprMockTransaction, TYPE_ID_CODE_HASH};
+use ckb_types::{
+    bytes::Bytes,
+    core::{DepType, ScriptHashType},
+    packed::{Byte32, CellOutput, OutPoint, OutPointVec, Script},
+    prelude::*,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A problem found in a mock transaction. `path` points at the offending value in the JSON form of the transaction,
+/// e.g. `tx.cell_deps[1].out_point`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+struct Linter {
+    problems: Vec<LintProblem>,
+}
+
+impl Linter {
+    fn report(&mut self, path: String, message: String) {
+        self.problems.push(LintProblem { path, message });
+    }
+
+    // Reports every item of `keys` that is the same as an earlier one.
+    fn duplicates<K: Eq + std::hash::Hash>(&mut self, path: &str, keys: impl Iterator<Item = K>, what: &str) {
+        let mut seen: HashMap<K, usize> = HashMap::new();
+        for (i, key) in keys.enumerate() {
+            if let Some(first) = seen.get(&key) {
+                self.report(
+                    format!("{}[{}]", path, i),
+                    format!("duplicate {} of {}[{}]", what, path, first),
+                );
+            } else {
+                seen.insert(key, i);
+            }
+        }
+    }
+}
+
+/// Checks that `mock_tx` has everything needed to run its scripts. Every problem found is returned, not just the
+/// first one, so an empty list means the transaction can be resolved.
+pub fn lint(mock_tx: &ReprMockTransaction) -> Vec<LintProblem> {
+    let mut linter = Linter { problems: vec![] };
+    let packed: MockTransaction = mock_tx.clone().into();
+    let tx = packed.core_transaction();
+    let info = &packed.mock_info;
+
+    // Out points are compared by their serialized form, packed types make for poor hash map keys.
+    let key = |out_point: &OutPoint| out_point.as_slice().to_vec();
+    linter.duplicates(
+        "tx.inputs",
+        tx.inputs().into_iter().map(|i| key(&i.previous_output())),
+        "input",
+    );
+    linter.duplicates(
+        "tx.cell_deps",
+        tx.cell_deps().into_iter().map(|d| key(&d.out_point())),
+        "cell dep",
+    );
+    linter.duplicates(
+        "tx.header_deps",
+        tx.header_deps().into_iter().map(|h| h.as_slice().to_vec()),
+        "header dep",
+    );
+    linter.duplicates(
+        "mock_info.inputs",
+        info.inputs.iter().map(|i| key(&i.input.previous_output())),
+        "input",
+    );
+    linter.duplicates(
+        "mock_info.cell_deps",
+        info.cell_deps.iter().map(|d| key(&d.cell_dep.out_point())),
+        "cell",
+    );
+    linter.duplicates(
+        "mock_info.header_deps",
+        mock_tx.mock_info.header_deps.iter().map(|h| h.hash.clone()),
+        "header",
+    );
+
+    // Scripts that run and the path of their code hash.
+    let mut scripts: Vec<(String, Script)> = vec![];
+    for (i, input) in tx.inputs().into_iter().enumerate() {
+        match info.inputs.iter().position(|mock_input| mock_input.input == input) {
+            Some(j) => {
+                let output = &info.inputs[j].output;
+                scripts.push((format!("mock_info.inputs[{}].output.lock.code_hash", j), output.lock()));
+                if let Some(type_script) = output.type_().to_opt() {
+                    scripts.push((format!("mock_info.inputs[{}].output.type.code_hash", j), type_script));
+                }
+            }
+            None => {
+                let out_point = input.previous_output();
+                let message = match info.inputs.iter().position(|m| m.input.previous_output() == out_point) {
+                    Some(j) => format!("since differs from the one of mock_info.inputs[{}]", j),
+                    None => format!("input {} is missing from mock_info.inputs", short_out_point(&out_point)),
+                };
+                linter.report(format!("tx.inputs[{}]", i), message);
+            }
+        }
+    }
+    for (i, output) in tx.outputs().into_iter().enumerate() {
+        if let Some(type_script) = output.type_().to_opt() {
+            scripts.push((format!("tx.outputs[{}].type.code_hash", i), type_script));
+        }
+    }
+
+    // Cells that scripts can be loaded from, with the path they were found at.
+    let find_cell = |out_point: &OutPoint| info.cell_deps.iter().position(|d| &d.cell_dep.out_point() == out_point);
+    let mut dep_cells: Vec<(String, CellOutput, Bytes)> = vec![];
+    for (i, cell_dep) in tx.cell_deps().into_iter().enumerate() {
+        let j = match find_cell(&cell_dep.out_point()) {
+            Some(j) => j,
+            None => {
+                linter.report(
+                    format!("tx.cell_deps[{}].out_point", i),
+                    format!(
+                        "cell {} is missing from mock_info.cell_deps",
+                        short_out_point(&cell_dep.out_point())
+                    ),
+                );
+                continue;
+            }
+        };
+        let mock_cell = &info.cell_deps[j];
+        if cell_dep.dep_type() == DepType::DepGroup.into() {
+            let group = match OutPointVec::from_slice(&mock_cell.data) {
+                Ok(group) => group,
+                Err(err) => {
+                    linter.report(
+                        format!("mock_info.cell_deps[{}].data", j),
+                        format!("dep group data can't be parsed as out points: {}", err),
+                    );
+                    continue;
+                }
+            };
+            for (k, sub_out_point) in group.into_iter().enumerate() {
+                match find_cell(&sub_out_point) {
+                    Some(l) => dep_cells.push((
+                        format!("mock_info.cell_deps[{}]", l),
+                        info.cell_deps[l].output.clone(),
+                        info.cell_deps[l].data.clone(),
+                    )),
+                    None => linter.report(
+                        format!("mock_info.cell_deps[{}].data", j),
+                        format!(
+                            "cell {} at index {} of the dep group is missing from mock_info.cell_deps",
+                            short_out_point(&sub_out_point),
+                            k
+                        ),
+                    ),
+                }
+            }
+        } else {
+            dep_cells.push((
+                format!("mock_info.cell_deps[{}]", j),
+                mock_cell.output.clone(),
+                mock_cell.data.clone(),
+            ));
+        }
+    }
+
+    for (i, header_dep) in tx.header_deps().into_iter().enumerate() {
+        if !info.header_deps.iter().any(|header| header.hash() == header_dep) {
+            linter.report(
+                format!("tx.header_deps[{}]", i),
+                format!("header {:x} is missing from mock_info.header_deps", header_dep),
+            );
+        }
+    }
+
+    let type_id_code_hash = Byte32::new(TYPE_ID_CODE_HASH);
+    for (path, script) in scripts {
+        let code_hash = script.code_hash();
+        let matches: Vec<&str> = if script.hash_type() == ScriptHashType::Type.into() {
+            if code_hash == type_id_code_hash {
+                continue;
+            }
+            dep_cells
+                .iter()
+                .filter(|(_, output, _)| {
+                    output.type_().to_opt().map(|t| t.calc_script_hash()) == Some(code_hash.clone())
+                })
+                .map(|(cell_path, _, _)| cell_path.as_str())
+                .collect()
+        } else {
+            dep_cells
+                .iter()
+                .filter(|(_, _, data)| CellOutput::calc_data_hash(data) == code_hash)
+                .map(|(cell_path, _, _)| cell_path.as_str())
+                .collect()
+        };
+        if matches.is_empty() {
+            linter.report(path, format!("no cell dep matches code hash {:x}", code_hash));
+        } else if script.hash_type() == ScriptHashType::Type.into() && matches.len() > 1 {
+            let data: Vec<&Bytes> = dep_cells
+                .iter()
+                .filter(|(cell_path, _, _)| matches.contains(&cell_path.as_str()))
+                .map(|(_, _, data)| data)
+                .collect();
+            if data.iter().any(|d| d != &data[0]) {
+                linter.report(
+                    path,
+                    format!(
+                        "cells with different code share type hash {:x}: {}",
+                        code_hash,
+                        matches.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    let (inputs, witnesses) = (tx.inputs().len(), tx.witnesses().len());
+    if witnesses < inputs {
+        linter.report(
+            String::from("tx.witnesses"),
+            format!(
+                "{} witnesses for {} inputs, lock scripts expect a witness for each input",
+                witnesses, inputs
+            ),
+        );
+    }
+    let (outputs, outputs_data) = (tx.outputs().len(), tx.outputs_data().len());
+    if outputs != outputs_data {
+        linter.report(
+            String::from("tx.outputs_data"),
+            format!("{} outputs data for {} outputs", outputs_data, outputs),
+        );
+    }
+
+    linter.problems
+}
@@ -0,0 +1,156 @@
+use crate::MockResourceLoader;
+use ckb_jsonrpc_types as json_types;
+use ckb_types::{
+    bytes::Bytes,
+    core::HeaderView,
+    packed::{Byte32, CellOutput, OutPoint, Transaction},
+    prelude::*,
+    H256,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Loads the cells and headers missing from a mock transaction from a CKB node through its JSON-RPC interface.
+///
+/// Cells are looked up with `get_transaction`, which also knows about cells that have been spent since, and with
+/// `get_live_cell` when the node can't find the transaction. Committed transactions and headers never change, so
+/// when a cache directory is set, they are kept there and later runs work without the node.
+#[derive(Clone)]
+pub struct RpcResourceLoader {
+    url: String,
+    cache_dir: Option<PathBuf>,
+}
+
+impl RpcResourceLoader {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            cache_dir: None,
+        }
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let mut response: Value = ureq::post(&self.url)
+            .send_json(request)
+            .map_err(|e| format!("RPC {} to {} failed: {}", method, self.url, e))?
+            .into_json()
+            .map_err(|e| format!("RPC {} to {} returned invalid JSON: {}", method, self.url, e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("RPC {} to {} failed: {}", method, self.url, error));
+        }
+        Ok(response["result"].take())
+    }
+
+    // Results for which `cacheable` returns true are stored in the cache directory under `name`.
+    fn cached_call(
+        &self,
+        name: &str,
+        method: &str,
+        params: Value,
+        cacheable: impl Fn(&Value) -> bool,
+    ) -> Result<Value, String> {
+        let path = self.cache_dir.as_ref().map(|dir| dir.join(format!("{}.json", name)));
+        if let Some(path) = &path {
+            if let Ok(content) = std::fs::read_to_string(path) {
+                return serde_json::from_str(&content)
+                    .map_err(|e| format!("Invalid cache file {}: {}", path.display(), e));
+            }
+        }
+        let result = self.call(method, params)?;
+        if let Some(path) = &path {
+            if cacheable(&result) {
+                std::fs::create_dir_all(path.parent().unwrap())
+                    .and_then(|_| std::fs::write(path, result.to_string()))
+                    .map_err(|e| format!("Failed to write cache file {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn transaction_with_status(&self, tx_hash: &H256) -> Result<Value, String> {
+        self.cached_call(
+            &format!("transaction-{:x}", tx_hash),
+            "get_transaction",
+            json!([tx_hash]),
+            |result| result["tx_status"]["status"] == "committed",
+        )
+    }
+
+    /// Fetches a transaction known to the node, whether it is committed or still pending.
+    pub fn get_transaction(&self, tx_hash: &H256) -> Result<Option<Transaction>, String> {
+        let mut result = self.transaction_with_status(tx_hash)?;
+        if result.is_null() || result["transaction"].is_null() {
+            return Ok(None);
+        }
+        let transaction: json_types::TransactionView = serde_json::from_value(result["transaction"].take())
+            .map_err(|e| format!("Invalid transaction {:x}: {}", tx_hash, e))?;
+        Ok(Some(transaction.inner.into()))
+    }
+
+    fn get_transaction_output(
+        &self,
+        tx_hash: &H256,
+        index: usize,
+    ) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        let result = self.transaction_with_status(tx_hash)?;
+        if result.is_null() || result["transaction"].is_null() {
+            return Ok(None);
+        }
+        let transaction = &result["transaction"];
+        let output = match transaction["outputs"].get(index) {
+            Some(output) => output.clone(),
+            None => return Ok(None),
+        };
+        let output: json_types::CellOutput =
+            serde_json::from_value(output).map_err(|e| format!("Invalid cell output in {:x}: {}", tx_hash, e))?;
+        let data: json_types::JsonBytes = serde_json::from_value(transaction["outputs_data"][index].clone())
+            .map_err(|e| format!("Invalid output data in {:x}: {}", tx_hash, e))?;
+        let block_hash: Option<H256> = serde_json::from_value(result["tx_status"]["block_hash"].clone())
+            .map_err(|e| format!("Invalid block hash of {:x}: {}", tx_hash, e))?;
+        Ok(Some((output.into(), data.into_bytes(), block_hash.map(|h| h.pack()))))
+    }
+}
+
+impl MockResourceLoader for RpcResourceLoader {
+    fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
+        let result = self.cached_call(&format!("header-{:x}", hash), "get_header", json!([hash]), |result| {
+            !result.is_null()
+        })?;
+        if result.is_null() {
+            return Ok(None);
+        }
+        let header: json_types::HeaderView =
+            serde_json::from_value(result).map_err(|e| format!("Invalid header {:x}: {}", hash, e))?;
+        Ok(Some(header.into()))
+    }
+
+    fn get_live_cell(&mut self, out_point: OutPoint) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        let tx_hash: H256 = out_point.tx_hash().unpack();
+        let index: u32 = out_point.index().unpack();
+        if let Some(cell) = self.get_transaction_output(&tx_hash, index as usize)? {
+            return Ok(Some(cell));
+        }
+        let out_point_json: json_types::OutPoint = out_point.into();
+        let result = self.call("get_live_cell", json!([out_point_json, true]))?;
+        let cell: json_types::CellWithStatus =
+            serde_json::from_value(result).map_err(|e| format!("Invalid live cell {:x}#{}: {}", tx_hash, index, e))?;
+        match cell.cell {
+            Some(json_types::CellInfo {
+                output,
+                data: Some(data),
+            }) if cell.status == "live" => Ok(Some((output.into(), data.content.into_bytes(), None))),
+            _ => Ok(None),
+        }
+    }
+}
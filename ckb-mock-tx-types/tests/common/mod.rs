@@ -0,0 +1,42 @@
+// Fixtures shared by the integration tests, each test only uses some of them.
+#![allow(dead_code)]
+
+use ckb_mock_tx_types::{MockTransaction, ReprMockTransaction};
+use ckb_types::packed::{Byte32, OutPoint};
+
+pub fn out_point(tx: u8, index: u32) -> OutPoint {
+    OutPoint::new(Byte32::new([tx; 32]), index)
+}
+
+// The JSON of a mock transaction with no deps and no outputs. `mock_inputs` and `tx_inputs` are the items of the input
+// arrays of `mock_info` and `tx`, `mock_info` is appended to the fields of `mock_info`.
+pub fn mock_tx(mock_inputs: &str, tx_inputs: &str, mock_info: &str) -> String {
+    format!(
+        r#"{{
+  "mock_info": {{
+    "inputs": [{}],
+    "cell_deps": [],
+    "header_deps": []{}
+  }},
+  "tx": {{
+    "version": "0x0",
+    "cell_deps": [],
+    "header_deps": [],
+    "inputs": [{}],
+    "outputs": [],
+    "outputs_data": [],
+    "witnesses": []
+  }}
+}}"#,
+        mock_inputs, mock_info, tx_inputs
+    )
+}
+
+pub fn parse(json: &str) -> MockTransaction {
+    let repr_mock_tx: ReprMockTransaction = serde_json::from_str(json).unwrap();
+    repr_mock_tx.into()
+}
+
+pub fn to_json(mock_tx: MockTransaction) -> String {
+    serde_json::to_string(&ReprMockTransaction::from(mock_tx)).unwrap()
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use ckb_mock_tx_types::Resource;
+use ckb_types::{
+    core::{
+        cell::{CellProvider, CellStatus},
+        EpochNumberWithFraction, TransactionInfo,
+    },
+    packed::Byte32,
+};
+use common::{mock_tx, parse, to_json};
+
+const TX_HASH: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+
+// A mock transaction spending one cell, `context` is appended to the fields of its mock input.
+fn input_mock_tx(context: &str) -> String {
+    let previous_output = format!(
+        r#"{{ "previous_output": {{ "tx_hash": "{}", "index": "0x0" }}, "since": "0x0" }}"#,
+        TX_HASH
+    );
+    let mock_input = format!(
+        r#"{{
+        "input": {},
+        "output": {{
+          "capacity": "0x174876e800",
+          "lock": {{ "code_hash": "0x{}", "hash_type": "data1", "args": "0x" }},
+          "type": null
+        }},
+        "data": "0x",
+        "header": null{}
+      }}"#,
+        previous_output,
+        "0".repeat(64),
+        context
+    );
+    mock_tx(&mock_input, &previous_output, "")
+}
+
+fn input_transaction_info(json: &str) -> TransactionInfo {
+    let mock_tx = parse(json);
+    let resource = Resource::from_mock_tx(&mock_tx).unwrap();
+    let out_point = mock_tx.mock_info.inputs[0].input.previous_output();
+    match resource.cell(&out_point, false) {
+        CellStatus::Live(meta) => meta.transaction_info.unwrap(),
+        _ => panic!("cell {} is not live", out_point),
+    }
+}
+
+#[test]
+pub fn test_block_context_defaults() {
+    let info = input_transaction_info(&input_mock_tx(""));
+    assert_eq!(info.block_number, 0);
+    assert_eq!(info.block_epoch, EpochNumberWithFraction::new(0, 0, 1800));
+    assert_eq!(info.index, 0);
+    assert_eq!(info.block_hash, Byte32::default());
+}
+
+#[test]
+pub fn test_block_context() {
+    let epoch = EpochNumberWithFraction::new(300, 15, 1000);
+    let context = format!(
+        r#", "block_number": "0x2710", "epoch": "0x{:x}", "tx_index": "0x3""#,
+        epoch.full_value()
+    );
+    let json = input_mock_tx(&context);
+    let info = input_transaction_info(&json);
+    assert_eq!(info.block_number, 10000);
+    assert_eq!(info.block_epoch, epoch);
+    assert_eq!(info.index, 3);
+
+    // The fields survive a round trip, and are left out again when they are not set.
+    let round_trip = |json: &str| to_json(parse(json));
+    let output = round_trip(&json);
+    assert!(output.contains(r#""block_number":"0x2710""#));
+    assert!(output.contains(r#""tx_index":"0x3""#));
+    assert!(!round_trip(&input_mock_tx("")).contains("block_number"));
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use ckb_mock_tx_types::{verify_consensus, ChainTip, MockCellDep, MockInfo, MockInput, MockTransaction, Resource};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::resolve_transaction, Capacity, EpochNumberWithFraction, HeaderBuilder, HeaderView, TransactionBuilder,
+    },
+    packed::{self, CellDep, CellInput, CellOutput},
+    prelude::*,
+};
+use common::out_point;
+use std::collections::HashSet;
+
+fn cell(capacity: u64) -> CellOutput {
+    CellOutput::new_builder().capacity(Capacity::bytes(capacity as usize).unwrap().pack()).build()
+}
+
+// A transaction spending a 1000 CKB cell created in block 100 of epoch 10 into a 900 CKB one.
+fn mock_tx(since: u64) -> MockTransaction {
+    let input = CellInput::new(out_point(1, 0), since);
+    let tx = TransactionBuilder::default()
+        .input(input.clone())
+        .cell_dep(CellDep::new_builder().out_point(out_point(2, 0)).build())
+        .output(cell(900))
+        .output_data(packed::Bytes::default())
+        .build();
+    MockTransaction {
+        mock_info: MockInfo {
+            inputs: vec![MockInput {
+                input,
+                output: cell(1000),
+                block_number: Some(100),
+                epoch: Some(EpochNumberWithFraction::new(10, 0, 1000)),
+                // The first transaction of a block is its cellbase.
+                tx_index: Some(1),
+                ..Default::default()
+            }],
+            cell_deps: vec![MockCellDep {
+                cell_dep: CellDep::new_builder().out_point(out_point(2, 0)).build(),
+                output: cell(1000),
+                data: Bytes::from("code"),
+                ..Default::default()
+            }],
+            header_deps: vec![],
+            files: Default::default(),
+        },
+        tx: tx.data(),
+    }
+}
+
+// Headers of the blocks 0 to 3, a second apart.
+fn chain() -> Vec<HeaderView> {
+    let mut headers: Vec<HeaderView> = vec![];
+    for number in 0..4u64 {
+        let mut builder = HeaderBuilder::default().number(number.pack()).timestamp((1000 * (number + 1)).pack());
+        if let Some(parent) = headers.last() {
+            builder = builder.parent_hash(parent.hash());
+        }
+        headers.push(builder.build());
+    }
+    headers
+}
+
+// Puts the cell spent by `mock_tx` in the last block of `headers`, which become the header deps.
+fn with_headers(mut mock_tx: MockTransaction, headers: Vec<HeaderView>) -> MockTransaction {
+    mock_tx.mock_info.inputs[0].header = headers.last().map(|header| header.hash());
+    let tx = mock_tx
+        .core_transaction()
+        .as_advanced_builder()
+        .header_deps(headers.iter().map(|header| header.hash()))
+        .build();
+    mock_tx.tx = tx.data();
+    mock_tx.mock_info.header_deps = headers;
+    mock_tx
+}
+
+fn violations(mock_tx: &MockTransaction, tip: &ChainTip) -> Vec<(&'static str, String)> {
+    let resource = Resource::from_mock_tx(mock_tx).unwrap();
+    let rtx = resolve_transaction(mock_tx.core_transaction(), &mut HashSet::new(), &resource, &resource).unwrap();
+    verify_consensus(&rtx, &resource, tip).into_iter().map(|v| (v.rule, v.message)).collect()
+}
+
+fn rules(mock_tx: &MockTransaction, tip: &ChainTip) -> Vec<&'static str> {
+    violations(mock_tx, tip).into_iter().map(|(rule, _)| rule).collect()
+}
+
+#[test]
+pub fn test_consensus_valid() {
+    assert_eq!(violations(&mock_tx(0), &ChainTip::default()), vec![]);
+}
+
+#[test]
+pub fn test_consensus_capacity() {
+    let mut mock_tx = mock_tx(0);
+    let tx = mock_tx.core_transaction().as_advanced_builder().set_outputs(vec![cell(1100)]).build();
+    mock_tx.tx = tx.data();
+    assert_eq!(rules(&mock_tx, &ChainTip::default()), vec!["capacity"]);
+
+    // An output too small to hold its own lock script.
+    let small = CellOutput::new_builder().capacity(Capacity::bytes(8).unwrap().pack()).build();
+    let tx = mock_tx.core_transaction().as_advanced_builder().set_outputs(vec![small]).build();
+    mock_tx.tx = tx.data();
+    assert_eq!(rules(&mock_tx, &ChainTip::default()), vec!["capacity"]);
+}
+
+#[test]
+pub fn test_consensus_structure() {
+    let mut extra_data = mock_tx(0);
+    let tx = extra_data.core_transaction().as_advanced_builder().output_data(packed::Bytes::default()).build();
+    extra_data.tx = tx.data();
+    assert_eq!(rules(&extra_data, &ChainTip::default()), vec!["transaction"]);
+
+    let mut mock_tx = mock_tx(0);
+    let tx = mock_tx
+        .core_transaction()
+        .as_advanced_builder()
+        .cell_dep(CellDep::new_builder().out_point(out_point(2, 0)).build())
+        .output_data(packed::Bytes::default())
+        .build();
+    mock_tx.tx = tx.data();
+    // The verifier stops at the first rule broken, the duplicate cell dep.
+    assert_eq!(rules(&mock_tx, &ChainTip::default()), vec!["transaction"]);
+}
+
+#[test]
+pub fn test_consensus_cellbase_maturity() {
+    let mut mock_tx = mock_tx(0);
+    mock_tx.mock_info.inputs[0].tx_index = Some(0);
+    let (rule, message) = violations(&mock_tx, &ChainTip::default()).remove(0);
+    assert_eq!(rule, "since");
+    assert!(message.contains("without a tip epoch"));
+    let at_epoch = |number, index, length| ChainTip {
+        epoch: Some(EpochNumberWithFraction::new(number, index, length)),
+        ..Default::default()
+    };
+    // Cellbases mature 4 epochs after the block they are in.
+    assert_eq!(rules(&mock_tx, &at_epoch(13, 999, 1000)), vec!["since"]);
+    assert_eq!(rules(&mock_tx, &at_epoch(14, 0, 1000)), Vec::<&str>::new());
+}
+
+#[test]
+pub fn test_consensus_since() {
+    // Absolute block number 200.
+    let absolute = mock_tx(200);
+    let at = |block_number| ChainTip {
+        block_number: Some(block_number),
+        ..Default::default()
+    };
+    assert_eq!(rules(&absolute, &at(199)), vec!["since"]);
+    assert_eq!(rules(&absolute, &at(200)), Vec::<&str>::new());
+
+    // 50 blocks after the block of the cell, which is 100.
+    let relative = mock_tx((1 << 63) | 50);
+    assert_eq!(rules(&relative, &at(149)), vec!["since"]);
+    assert_eq!(rules(&relative, &at(150)), Vec::<&str>::new());
+
+    // Half an epoch after epoch 10.
+    let relative_epoch = mock_tx((1 << 63) | (1 << 61) | EpochNumberWithFraction::new(0, 1, 2).full_value());
+    let at_epoch = |number, index, length| ChainTip {
+        epoch: Some(EpochNumberWithFraction::new(number, index, length)),
+        ..Default::default()
+    };
+    assert_eq!(rules(&relative_epoch, &at_epoch(10, 499, 1000)), vec!["since"]);
+    assert_eq!(rules(&relative_epoch, &at_epoch(10, 250, 500)), Vec::<&str>::new());
+
+    // A timestamp can't be checked without the median time of the tip.
+    let timestamp = mock_tx((2 << 61) | 1_600_000_000);
+    let (rule, message) = violations(&timestamp, &ChainTip::default()).remove(0);
+    assert_eq!(rule, "since");
+    assert!(message.contains("without a tip median time"));
+    let at_time = |median_time| ChainTip {
+        median_time: Some(median_time),
+        ..Default::default()
+    };
+    assert_eq!(rules(&timestamp, &at_time(1_600_000_000_000)), Vec::<&str>::new());
+
+    // Reserved bits.
+    assert_eq!(rules(&mock_tx(1 << 56), &at(1000)), vec!["since"]);
+}
+
+#[test]
+pub fn test_consensus_relative_timestamp() {
+    // 10 seconds after the block of the cell.
+    let since = (1 << 63) | (2 << 61) | 10;
+    let at_time = |median_time| ChainTip {
+        median_time: Some(median_time),
+        ..Default::default()
+    };
+    let headers = chain();
+    let relative = with_headers(mock_tx(since), headers.clone());
+    assert_eq!(rules(&relative, &at_time(5_000)), vec!["since"]);
+    assert_eq!(rules(&relative, &at_time(100_000)), Vec::<&str>::new());
+
+    // The median time the since counts from needs the blocks before the block of the cell.
+    let truncated = with_headers(mock_tx(since), headers[2..].to_vec());
+    let (rule, message) = violations(&truncated, &at_time(100_000)).remove(0);
+    assert_eq!(rule, "since");
+    assert!(message.contains(&format!("{:x}", headers[1].hash())));
+}
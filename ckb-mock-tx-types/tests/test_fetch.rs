@@ -0,0 +1,110 @@
+mod common;
+
+use ckb_mock_tx_types::{MockResourceLoader, MockTransaction, ReprMockTransaction, Resource};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::{CellProvider, CellStatus},
+        Capacity, DepType, HeaderBuilder, HeaderView, TransactionBuilder,
+    },
+    packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, OutPointVec},
+    prelude::*,
+    H256,
+};
+use common::{out_point, parse, to_json};
+use std::collections::HashMap;
+
+// Serves the cells and headers it was set up with, the way a node would.
+#[derive(Default)]
+struct FixtureLoader {
+    cells: HashMap<OutPoint, (CellOutput, Bytes, Option<Byte32>)>,
+    headers: HashMap<H256, HeaderView>,
+}
+
+impl FixtureLoader {
+    fn add_cell(&mut self, out_point: &OutPoint, data: Bytes, header: Option<Byte32>) {
+        let output = CellOutput::new_builder().capacity(Capacity::bytes(data.len() + 100).unwrap().pack()).build();
+        self.cells.insert(out_point.clone(), (output, data, header));
+    }
+}
+
+impl MockResourceLoader for FixtureLoader {
+    fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
+        Ok(self.headers.get(&hash).cloned())
+    }
+
+    fn get_live_cell(&mut self, out_point: OutPoint) -> Result<Option<(CellOutput, Bytes, Option<Byte32>)>, String> {
+        Ok(self.cells.get(&out_point).cloned())
+    }
+}
+
+#[test]
+pub fn test_from_loader() {
+    let header = HeaderBuilder::default().number(7u64.pack()).build();
+    let mut loader = FixtureLoader::default();
+    loader.headers.insert(header.hash().unpack(), header.clone());
+    loader.add_cell(&out_point(1, 0), Bytes::from("input"), Some(header.hash()));
+    loader.add_cell(&out_point(2, 0), Bytes::from("code"), None);
+    loader.add_cell(&out_point(3, 0), Bytes::from("lib a"), None);
+    loader.add_cell(&out_point(3, 1), Bytes::from("lib b"), None);
+    let group = OutPointVec::new_builder().push(out_point(3, 0)).push(out_point(3, 1)).build();
+    loader.add_cell(&out_point(4, 0), group.as_bytes(), None);
+
+    let tx = TransactionBuilder::default()
+        .input(CellInput::new(out_point(1, 0), 0))
+        .cell_dep(CellDep::new_builder().out_point(out_point(2, 0)).build())
+        .cell_dep(CellDep::new_builder().out_point(out_point(4, 0)).dep_type(DepType::DepGroup.into()).build())
+        .header_dep(header.hash())
+        .build();
+    let mock_tx = MockTransaction::from_loader(tx.data(), loader).unwrap();
+
+    assert_eq!(mock_tx.mock_info.inputs.len(), 1);
+    assert_eq!(mock_tx.mock_info.inputs[0].data, Bytes::from("input"));
+    assert_eq!(mock_tx.mock_info.inputs[0].header, Some(header.hash()));
+    assert_eq!(mock_tx.mock_info.inputs[0].block_number, Some(7));
+    // The code cell, the dep group and both cells in the group.
+    assert_eq!(mock_tx.mock_info.cell_deps.len(), 4);
+    assert_eq!(mock_tx.mock_info.header_deps.len(), 1);
+
+    // Everything is in the mock transaction now, so it resolves without a loader, also after a round trip through
+    // the JSON format.
+    let resource = Resource::from_mock_tx(&parse(&to_json(mock_tx))).unwrap();
+    for (cell, data) in [(out_point(3, 1), "lib b"), (out_point(2, 0), "code")] {
+        match resource.cell(&cell, true) {
+            CellStatus::Live(meta) => assert_eq!(meta.mem_cell_data, Some(Bytes::from(data))),
+            _ => panic!("cell {} is not live", cell),
+        }
+    }
+}
+
+#[test]
+pub fn test_from_loader_missing_cell() {
+    let tx = TransactionBuilder::default().input(CellInput::new(out_point(1, 0), 0)).build();
+    assert!(MockTransaction::from_loader(tx.data(), FixtureLoader::default()).is_err());
+}
+
+#[test]
+pub fn test_from_loader_shared_deps() {
+    let mut loader = FixtureLoader::default();
+    loader.add_cell(&out_point(3, 0), Bytes::from("lib a"), None);
+    loader.add_cell(&out_point(3, 1), Bytes::from("lib b"), None);
+    let group = OutPointVec::new_builder().push(out_point(3, 0)).push(out_point(3, 1)).build();
+    loader.add_cell(&out_point(4, 0), group.as_bytes(), None);
+    let group = OutPointVec::new_builder().push(out_point(3, 1)).build();
+    loader.add_cell(&out_point(5, 0), group.as_bytes(), None);
+
+    let dep_group = |out_point| CellDep::new_builder().out_point(out_point).dep_type(DepType::DepGroup.into()).build();
+    let tx = TransactionBuilder::default()
+        .cell_dep(CellDep::new_builder().out_point(out_point(3, 0)).build())
+        .cell_dep(dep_group(out_point(4, 0)))
+        .cell_dep(dep_group(out_point(5, 0)))
+        .build();
+    let mock_tx = MockTransaction::from_loader(tx.data(), loader).unwrap();
+    let out_points: Vec<OutPoint> =
+        mock_tx.mock_info.cell_deps.iter().map(|mock_cell| mock_cell.cell_dep.out_point()).collect();
+    assert_eq!(
+        out_points,
+        vec![out_point(3, 0), out_point(3, 1), out_point(4, 0), out_point(5, 0)]
+    );
+    assert!(ckb_mock_tx_types::lint(&ReprMockTransaction::from(mock_tx)).is_empty());
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use ckb_types::bytes::Bytes;
+use common::{mock_tx, parse, to_json};
+
+fn files_mock_tx(files: &str) -> String {
+    mock_tx("", "", files)
+}
+
+#[test]
+pub fn test_files_default_to_empty() {
+    let mock_tx = parse(&files_mock_tx(""));
+    assert!(mock_tx.mock_info.files.is_empty());
+    let json = to_json(mock_tx);
+    assert!(!json.contains("files"));
+}
+
+#[test]
+pub fn test_files() {
+    let mock_tx = parse(&files_mock_tx(
+        r#", "files": { "vectors/a.bin": "0x0102", "empty": "0x" }"#,
+    ));
+    let files = &mock_tx.mock_info.files;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files["vectors/a.bin"], Bytes::from(vec![1, 2]));
+    assert_eq!(files["empty"], Bytes::new());
+
+    let json = to_json(mock_tx.clone());
+    assert_eq!(parse(&json).mock_info.files, mock_tx.mock_info.files);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use ckb_mock_tx_types::{lint, MockCellDep, MockInfo, MockInput, MockTransaction, ReprMockTransaction};
+use ckb_types::{
+    bytes::Bytes,
+    core::{DepType, HeaderBuilder, ScriptHashType, TransactionBuilder},
+    packed::{self, CellDep, CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+};
+use common::out_point;
+
+fn cell_dep(out_point: OutPoint, dep_type: DepType, data: Bytes) -> MockCellDep {
+    MockCellDep {
+        cell_dep: CellDep::new_builder().out_point(out_point).dep_type(dep_type.into()).build(),
+        data,
+        ..Default::default()
+    }
+}
+
+fn lock(code: &Bytes) -> Script {
+    Script::new_builder().code_hash(CellOutput::calc_data_hash(code)).hash_type(ScriptHashType::Data1.into()).build()
+}
+
+fn problems(mock_tx: MockTransaction) -> Vec<(String, String)> {
+    lint(&ReprMockTransaction::from(mock_tx)).into_iter().map(|p| (p.path, p.message)).collect()
+}
+
+fn valid_mock_tx() -> MockTransaction {
+    let code = Bytes::from("lock code");
+    let input = CellInput::new(out_point(1, 0), 0);
+    let tx = TransactionBuilder::default()
+        .input(input.clone())
+        .cell_dep(CellDep::new_builder().out_point(out_point(2, 0)).build())
+        .output(CellOutput::new_builder().lock(lock(&code)).build())
+        .output_data(packed::Bytes::default())
+        .witness(packed::Bytes::default())
+        .build();
+    MockTransaction {
+        mock_info: MockInfo {
+            inputs: vec![MockInput {
+                input,
+                output: CellOutput::new_builder().lock(lock(&code)).build(),
+                ..Default::default()
+            }],
+            cell_deps: vec![cell_dep(out_point(2, 0), DepType::Code, code)],
+            header_deps: vec![],
+            files: Default::default(),
+        },
+        tx: tx.data(),
+    }
+}
+
+#[test]
+pub fn test_lint_valid() {
+    assert_eq!(problems(valid_mock_tx()), vec![]);
+}
+
+#[test]
+pub fn test_lint_missing_cells() {
+    let mut mock_tx = valid_mock_tx();
+    let header = HeaderBuilder::default().build();
+    let tx = mock_tx
+        .core_transaction()
+        .as_advanced_builder()
+        .input(CellInput::new(out_point(3, 0), 0))
+        .cell_dep(CellDep::new_builder().out_point(out_point(4, 0)).build())
+        .cell_dep(CellDep::new_builder().out_point(out_point(5, 0)).dep_type(DepType::DepGroup.into()).build())
+        .header_dep(header.hash())
+        .build();
+    mock_tx.tx = tx.data();
+    mock_tx.mock_info.cell_deps.push(cell_dep(out_point(5, 0), DepType::DepGroup, Bytes::from("not a group")));
+
+    let problems = problems(mock_tx);
+    let paths: Vec<&str> = problems.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "tx.inputs[1]",
+            "tx.cell_deps[1].out_point",
+            "mock_info.cell_deps[1].data",
+            "tx.header_deps[0]",
+            "tx.witnesses"
+        ]
+    );
+    assert!(problems[0].1.contains("missing from mock_info.inputs"));
+}
+
+#[test]
+pub fn test_lint_code_hash() {
+    let mut mock_tx = valid_mock_tx();
+    mock_tx.mock_info.cell_deps[0].data = Bytes::from("other code");
+    assert_eq!(
+        problems(mock_tx),
+        vec![(
+            String::from("mock_info.inputs[0].output.lock.code_hash"),
+            format!(
+                "no cell dep matches code hash {:x}",
+                CellOutput::calc_data_hash(b"lock code")
+            )
+        )]
+    );
+}
+
+#[test]
+pub fn test_lint_duplicates() {
+    let mut mock_tx = valid_mock_tx();
+    let dep = mock_tx.mock_info.cell_deps[0].clone();
+    let tx = mock_tx.core_transaction().as_advanced_builder().cell_dep(dep.cell_dep.clone()).build();
+    mock_tx.tx = tx.data();
+    mock_tx.mock_info.cell_deps.push(dep);
+    let paths: Vec<String> = problems(mock_tx).into_iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, vec!["tx.cell_deps[1]", "mock_info.cell_deps[1]"]);
+}
@@ -0,0 +1,156 @@
+use ckb_jsonrpc_types as json_types;
+use ckb_mock_tx_types::{MockInfo, MockTransaction, Resource, RpcResourceLoader};
+use ckb_traits::HeaderProvider;
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::{CellProvider, CellStatus},
+        Capacity, HeaderBuilder, HeaderView, TransactionBuilder, TransactionView,
+    },
+    packed::{CellDep, CellInput, CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// A node that knows about a single committed transaction and the header of the block it is in.
+fn start_stub_node(tx: TransactionView, header: HeaderView) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((key, value)) = line.split_once(':') {
+                    if key.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            let tx_hash: H256 = tx.hash().unpack();
+            let header_hash: H256 = header.hash().unpack();
+            let result = match request["method"].as_str().unwrap() {
+                "get_transaction" if request["params"][0] == json!(tx_hash) => json!({
+                    "transaction": json_types::TransactionView::from(tx.clone()),
+                    "cycles": null,
+                    "tx_status": { "status": "committed", "block_hash": header_hash, "reason": null },
+                }),
+                "get_header" if request["params"][0] == json!(header_hash) => {
+                    json!(json_types::HeaderView::from(header.clone()))
+                }
+                "get_live_cell" => json!({ "cell": null, "status": "unknown" }),
+                _ => Value::Null,
+            };
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    });
+    (url, requests)
+}
+
+fn setup() -> (TransactionView, HeaderView, MockTransaction) {
+    let cell = |data: &str| {
+        let output = CellOutput::new_builder().capacity(Capacity::bytes(100).unwrap().pack()).build();
+        (output, Bytes::from(data.to_string()).pack())
+    };
+    let (code_output, code_data) = cell("code");
+    let (input_output, input_data) = cell("abc");
+    let deployed = TransactionBuilder::default()
+        .output(code_output)
+        .output_data(code_data)
+        .output(input_output)
+        .output_data(input_data)
+        .build();
+    let header = HeaderBuilder::default().number(42u64.pack()).build();
+    let tx = TransactionBuilder::default()
+        .cell_dep(CellDep::new_builder().out_point(OutPoint::new(deployed.hash(), 0)).build())
+        .input(CellInput::new(OutPoint::new(deployed.hash(), 1), 0))
+        .header_dep(header.hash())
+        .build();
+    let mock_tx = MockTransaction {
+        mock_info: MockInfo::default(),
+        tx: tx.data(),
+    };
+    (deployed, header, mock_tx)
+}
+
+fn live_cell(resource: &Resource, out_point: &OutPoint) -> (Bytes, Option<H256>) {
+    match resource.cell(out_point, true) {
+        CellStatus::Live(meta) => (
+            meta.mem_cell_data.unwrap(),
+            meta.transaction_info.map(|info| info.block_hash.unpack()),
+        ),
+        _ => panic!("cell {} is not live", out_point),
+    }
+}
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ckb-mock-tx-types-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+pub fn test_rpc_loader() {
+    let (deployed, header, mock_tx) = setup();
+    let (url, _) = start_stub_node(deployed.clone(), header.clone());
+    let resource = Resource::from_both(&mock_tx, RpcResourceLoader::new(&url)).unwrap();
+
+    let header_hash: H256 = header.hash().unpack();
+    let (data, block_hash) = live_cell(&resource, &OutPoint::new(deployed.hash(), 0));
+    assert_eq!(data, Bytes::from("code"));
+    assert_eq!(block_hash, Some(header_hash));
+    let (data, _) = live_cell(&resource, &OutPoint::new(deployed.hash(), 1));
+    assert_eq!(data, Bytes::from("abc"));
+    assert_eq!(resource.get_header(&header.hash()).unwrap().number(), 42);
+}
+
+#[test]
+pub fn test_rpc_loader_cache() {
+    let (deployed, header, mock_tx) = setup();
+    let (url, requests) = start_stub_node(deployed, header);
+    let dir = cache_dir("cache");
+    let loader = RpcResourceLoader::new(&url).with_cache_dir(&dir);
+    Resource::from_both(&mock_tx, loader.clone()).unwrap();
+    let count = requests.load(Ordering::SeqCst);
+    assert!(count > 0);
+    Resource::from_both(&mock_tx, loader).unwrap();
+    assert_eq!(requests.load(Ordering::SeqCst), count);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+pub fn test_rpc_loader_missing_cell() {
+    let (deployed, header, _) = setup();
+    let (url, _) = start_stub_node(deployed, header);
+    let tx = TransactionBuilder::default().input(CellInput::new(OutPoint::new(Default::default(), 0), 0)).build();
+    let mock_tx = MockTransaction {
+        mock_info: MockInfo::default(),
+        tx: tx.data(),
+    };
+    assert!(Resource::from_both(&mock_tx, RpcResourceLoader::new(&url)).is_err());
+}
@@ -0,0 +1,79 @@
+use ckb_vm::instructions::{extract_opcode, instruction_opcode_name, Instruction, Itype, R4type, Rtype, Stype, Utype};
+use ckb_vm::registers::REGISTER_ABI_NAMES;
+
+fn reg(index: usize) -> &'static str {
+    REGISTER_ABI_NAMES[index]
+}
+
+/// Formats a decoded instruction the way objdump would, e.g. `addi sp, sp, -32` or `sd ra, 24(sp)`. Compressed
+/// instructions are shown as their expanded form, macro-op fused instructions with the name of the fused operation.
+pub fn disassemble(inst: Instruction) -> String {
+    let name = instruction_opcode_name(extract_opcode(inst));
+    let mut mnemonic = name.to_lowercase();
+    if name.starts_with("AMO") || name.starts_with("LR_") || name.starts_with("SC_") {
+        mnemonic = mnemonic.replace('_', ".");
+    }
+    let operands = match name {
+        "ECALL" | "EBREAK" | "FENCE" | "FENCEI" | "UNLOADED" | "CUSTOM_TRACE_END" => String::new(),
+        "LUI"
+        | "AUIPC"
+        | "JAL"
+        | "FAR_JUMP_REL"
+        | "FAR_JUMP_ABS"
+        | "LD_SIGN_EXTENDED_32_CONSTANT"
+        | "CUSTOM_LOAD_IMM" => {
+            let i = Utype(inst);
+            format!("{}, {}", reg(i.rd()), i.immediate_s())
+        }
+        "CUSTOM_LOAD_UIMM" => {
+            let i = Utype(inst);
+            format!("{}, 0x{:x}", reg(i.rd()), i.immediate_u())
+        }
+        "LB" | "LH" | "LW" | "LD" | "LBU" | "LHU" | "LWU" | "JALR" => {
+            let i = Itype(inst);
+            format!("{}, {}({})", reg(i.rd()), i.immediate_s(), reg(i.rs1()))
+        }
+        "SB" | "SH" | "SW" | "SD" => {
+            let i = Stype(inst);
+            format!("{}, {}({})", reg(i.rs2()), i.immediate_s(), reg(i.rs1()))
+        }
+        "BEQ" | "BNE" | "BLT" | "BGE" | "BLTU" | "BGEU" => {
+            let i = Stype(inst);
+            format!("{}, {}, {}", reg(i.rs1()), reg(i.rs2()), i.immediate_s())
+        }
+        "ADDI" | "ADDIW" | "ANDI" | "ORI" | "XORI" | "SLTI" | "SLTIU" => {
+            let i = Itype(inst);
+            format!("{}, {}, {}", reg(i.rd()), reg(i.rs1()), i.immediate_s())
+        }
+        "SLLI" | "SRLI" | "SRAI" | "SLLIW" | "SRLIW" | "SRAIW" | "SLLIUW" | "RORI" | "RORIW" | "BCLRI" | "BEXTI"
+        | "BINVI" | "BSETI" => {
+            let i = Itype(inst);
+            format!("{}, {}, {}", reg(i.rd()), reg(i.rs1()), i.immediate_u())
+        }
+        "CLZ" | "CLZW" | "CTZ" | "CTZW" | "CPOP" | "CPOPW" | "ORCB" | "REV8" | "SEXTB" | "SEXTH" | "ZEXTH" => {
+            let i = Rtype(inst);
+            format!("{}, {}", reg(i.rd()), reg(i.rs1()))
+        }
+        "LR_W" | "LR_D" => {
+            let i = Rtype(inst);
+            format!("{}, ({})", reg(i.rd()), reg(i.rs1()))
+        }
+        _ if name.starts_with("SC_") || name.starts_with("AMO") => {
+            let i = Rtype(inst);
+            format!("{}, {}, ({})", reg(i.rd()), reg(i.rs2()), reg(i.rs1()))
+        }
+        "WIDE_MUL" | "WIDE_MULU" | "WIDE_MULSU" | "WIDE_DIV" | "WIDE_DIVU" | "ADCS" | "SBBS" => {
+            let i = R4type(inst);
+            format!("{}, {}, {}, {}", reg(i.rd()), reg(i.rs1()), reg(i.rs2()), reg(i.rs3()))
+        }
+        _ => {
+            let i = Rtype(inst);
+            format!("{}, {}, {}", reg(i.rd()), reg(i.rs1()), reg(i.rs2()))
+        }
+    };
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{} {}", mnemonic, operands)
+    }
+}
@@ -1,6 +1,8 @@
 use byteorder::{ByteOrder, LittleEndian};
 use ckb_vm::{
-    decoder::build_decoder, CoreMachine, DefaultCoreMachine, DefaultMachine, Error as CkbError, Memory, SupportMachine,
+    decoder::{build_decoder, Decoder},
+    snapshot::{make_snapshot, resume, Snapshot},
+    CoreMachine, DefaultCoreMachine, DefaultMachine, Error as CkbError, Memory, SupportMachine,
     RISCV_GENERAL_REGISTER_NUMBER,
 };
 
@@ -66,10 +68,27 @@ impl WatchPointStatus {
     }
 }
 
+// Number of instructions executed between two snapshots at the start of a session. The interval doubles each time
+// the number of snapshots exceeds MAX_SNAPSHOTS, so memory usage stays bounded for long running scripts.
+const SNAPSHOT_INTERVAL: u64 = 100_000;
+const MAX_SNAPSHOTS: usize = 64;
+
+pub type MachineFactory<'a, M> = Box<dyn Fn() -> DefaultMachine<'a, DefaultCoreMachine<u64, M>> + 'a>;
+
+struct History {
+    // Instructions executed since the program was loaded.
+    steps: u64,
+    interval: u64,
+    // (steps, cycles, snapshot), ordered by steps.
+    snapshots: Vec<(u64, u64, Snapshot)>,
+}
+
 pub struct GdbHandler<'a, M: Memory<REG = u64> + Default> {
     machine: RefCell<DefaultMachine<'a, DefaultCoreMachine<u64, M>>>,
     breakpoints: RefCell<Vec<Breakpoint>>,
     watchpoints: RefCell<Vec<WatchPointStatus>>,
+    factory: Option<MachineFactory<'a, M>>,
+    history: RefCell<History>,
 }
 
 impl<'a, M: Memory<REG = u64> + Default> GdbHandler<'a, M> {
@@ -92,8 +111,175 @@ impl<'a, M: Memory<REG = u64> + Default> GdbHandler<'a, M> {
             machine: RefCell::new(machine),
             breakpoints: RefCell::new(vec![]),
             watchpoints: RefCell::new(vec![]),
+            factory: None,
+            history: RefCell::new(History {
+                steps: 0,
+                interval: SNAPSHOT_INTERVAL,
+                snapshots: vec![],
+            }),
+        }
+    }
+
+    /// Creates a handler that supports reverse execution. `factory` must build a machine with the same syscalls as
+    /// `machine` but without a program loaded, the loaded state is restored from snapshots taken while the program
+    /// runs forward. Going back restores the closest snapshot and re-executes the instructions after it, which only
+    /// yields the original state when the syscalls are deterministic. Registers and memory changed from gdb are lost
+    /// when going back.
+    pub fn new_reversible(
+        machine: DefaultMachine<'a, DefaultCoreMachine<u64, M>>,
+        factory: MachineFactory<'a, M>,
+    ) -> Self {
+        let mut handler = Self::new(machine);
+        handler.factory = Some(factory);
+        handler
+    }
+
+    fn step(&self, decoder: &mut Decoder) -> Result<(), CkbError> {
+        if self.factory.is_some() {
+            self.take_snapshot()?;
+        }
+        self.machine.borrow_mut().step(decoder)?;
+        self.history.borrow_mut().steps += 1;
+        Ok(())
+    }
+
+    fn take_snapshot(&self) -> Result<(), CkbError> {
+        let mut history = self.history.borrow_mut();
+        let due = match history.snapshots.last() {
+            Some((steps, _, _)) => history.steps >= steps + history.interval,
+            None => true,
+        };
+        if !due {
+            return Ok(());
+        }
+        let mut machine = self.machine.borrow_mut();
+        let snapshot = make_snapshot(&mut *machine)?;
+        let (steps, cycles) = (history.steps, machine.cycles());
+        history.snapshots.push((steps, cycles, snapshot));
+        if history.snapshots.len() > MAX_SNAPSHOTS {
+            let mut index = 0;
+            history.snapshots.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            history.interval *= 2;
+        }
+        Ok(())
+    }
+
+    // Builds a machine from the snapshot at `index` and executes it until `steps` instructions have run in total.
+    // Also returns the steps of the last state before `steps` which is either at one of `breakpoints`, or about to
+    // execute an instruction writing to one of the memory `regions`.
+    fn replay(
+        &self,
+        index: usize,
+        steps: u64,
+        breakpoints: &[u64],
+        regions: &[(u64, u64)],
+    ) -> Result<(DefaultMachine<'a, DefaultCoreMachine<u64, M>>, Option<u64>), CkbError> {
+        let history = self.history.borrow();
+        let (start, cycles, snapshot) = &history.snapshots[index];
+        let factory = self.factory.as_ref().expect("reverse execution is not enabled");
+        let mut machine = factory();
+        resume(&mut machine, snapshot)?;
+        machine.set_cycles(*cycles);
+        machine.set_running(true);
+        let mut decoder = build_decoder::<u64>(machine.isa(), machine.version());
+        let mut hit = None;
+        for current in *start..steps {
+            if breakpoints.contains(machine.pc()) {
+                hit = Some(current);
+            }
+            if regions.is_empty() {
+                machine.step(&mut decoder)?;
+                continue;
+            }
+            let before: Vec<Vec<u8>> = regions.iter().map(|r| read_region(&mut machine, *r)).collect();
+            machine.step(&mut decoder)?;
+            if regions.iter().zip(&before).any(|(r, b)| &read_region(&mut machine, *r) != b) {
+                hit = Some(current);
+            }
+        }
+        Ok((machine, hit))
+    }
+
+    fn go_to(&self, steps: u64) -> Result<(), Error> {
+        let index = self.history.borrow().snapshots.iter().rposition(|(s, _, _)| *s <= steps).unwrap_or(0);
+        let (machine, _) = self.replay(index, steps, &[], &[]).map_err(replay_error)?;
+        *self.machine.borrow_mut() = machine;
+        {
+            let mut history = self.history.borrow_mut();
+            history.steps = steps;
+            // Snapshots past this point belong to the execution being left, running forward takes them again.
+            history.snapshots.retain(|(s, _, _)| *s <= steps);
+        }
+        // Resync the watched values with the restored memory.
+        self.at_watchpoint()?;
+        Ok(())
+    }
+
+    fn stop_reason(&self) -> StopReason {
+        if self.machine.borrow().running() {
+            // SIGTRAP
+            StopReason::Signal(5)
+        } else {
+            StopReason::Exited(0, self.machine.borrow().exit_code() as u8)
         }
     }
+
+    /// Goes back by one instruction. Returns None when already at the start of the program.
+    pub fn reverse_step(&self) -> Result<Option<StopReason>, Error> {
+        if self.factory.is_none() {
+            return Err(Error::Error(6));
+        }
+        let steps = self.history.borrow().steps;
+        if steps == 0 {
+            return Ok(None);
+        }
+        self.go_to(steps - 1)?;
+        Ok(Some(self.stop_reason()))
+    }
+
+    /// Goes back to the last breakpoint hit, or to the last instruction that changed a watched memory region. Returns
+    /// None after going back to the start of the program when neither is found.
+    pub fn reverse_continue(&self) -> Result<Option<StopReason>, Error> {
+        if self.factory.is_none() {
+            return Err(Error::Error(6));
+        }
+        let breakpoints: Vec<u64> = self.breakpoints.borrow().iter().map(|b| b.addr).collect();
+        let regions: Vec<(u64, u64)> =
+            self.watchpoints.borrow().iter().map(|wp| (wp.watchpoint.addr, wp.watchpoint.n_bytes)).collect();
+        let mut end = self.history.borrow().steps;
+        // go_to borrows the history mutably, it must not stay borrowed over the loop.
+        let len = self.history.borrow().snapshots.len();
+        // Scan the history one snapshot interval at a time, starting with the most recent one.
+        for index in (0..len).rev() {
+            let start = self.history.borrow().snapshots[index].0;
+            if start >= end {
+                continue;
+            }
+            let (_, hit) = self.replay(index, end, &breakpoints, &regions).map_err(replay_error)?;
+            if let Some(steps) = hit {
+                self.go_to(steps)?;
+                return Ok(Some(self.stop_reason()));
+            }
+            end = start;
+        }
+        self.go_to(0)?;
+        Ok(None)
+    }
+}
+
+fn read_region<M: Memory<REG = u64> + Default>(
+    machine: &mut DefaultMachine<DefaultCoreMachine<u64, M>>,
+    (address, length): (u64, u64),
+) -> Vec<u8> {
+    (address..address + length).map(|a| machine.memory_mut().load8(&a).map(|v| v as u8).unwrap_or(0)).collect()
+}
+
+fn replay_error(e: CkbError) -> Error {
+    error!("Error re-executing the script: {:?}", e);
+    Error::Error(6)
 }
 
 impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
@@ -186,7 +372,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
         let (vcont, _thread_id) = &request[0];
         match vcont {
             VCont::Continue => {
-                let res = self.machine.borrow_mut().step(&mut decoder);
+                let res = self.step(&mut decoder);
                 if res.is_err() {
                     show_warning(&res.err().unwrap());
                     return Ok(StopReason::Signal(5));
@@ -197,7 +383,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
                     if self.at_watchpoint()? {
                         break;
                     }
-                    let res = self.machine.borrow_mut().step(&mut decoder);
+                    let res = self.step(&mut decoder);
                     if res.is_err() {
                         show_warning(&res.err().unwrap());
                         return Ok(StopReason::Signal(5));
@@ -206,7 +392,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
             }
             VCont::Step => {
                 if self.machine.borrow().running() {
-                    let res = self.machine.borrow_mut().step(&mut decoder);
+                    let res = self.step(&mut decoder);
                     if res.is_err() {
                         show_warning(&res.err().unwrap());
                         return Ok(StopReason::Signal(5));
@@ -214,7 +400,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
                 }
             }
             VCont::RangeStep(range) => {
-                let res = self.machine.borrow_mut().step(&mut decoder);
+                let res = self.step(&mut decoder);
                 if res.is_err() {
                     show_warning(&res.err().unwrap());
                     return Ok(StopReason::Signal(5));
@@ -227,7 +413,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
                     if self.at_watchpoint()? {
                         break;
                     }
-                    let res = self.machine.borrow_mut().step(&mut decoder);
+                    let res = self.step(&mut decoder);
                     if res.is_err() {
                         show_warning(&res.err().unwrap());
                         return Ok(StopReason::Signal(5));
@@ -239,12 +425,7 @@ impl<'a, M: Memory<REG = u64> + Default> Handler for GdbHandler<'a, M> {
                 return Err(Error::Error(5));
             }
         }
-        if self.machine.borrow().running() {
-            // SIGTRAP
-            Ok(StopReason::Signal(5))
-        } else {
-            Ok(StopReason::Exited(0, self.machine.borrow().exit_code() as u8))
-        }
+        Ok(self.stop_reason())
     }
 
     fn insert_software_breakpoint(&self, breakpoint: Breakpoint) -> Result<(), Error> {
@@ -1,12 +1,18 @@
 #[macro_use]
 extern crate log;
 
+mod disasm;
 mod elf_dumper;
 mod gdbserver;
+mod reverse;
 #[cfg(feature = "stdio")]
 mod stdio;
+mod trace;
 
+pub use disasm::disassemble;
 pub use elf_dumper::ElfDumper;
-pub use gdbserver::GdbHandler;
+pub use gdbserver::{GdbHandler, MachineFactory};
+pub use reverse::process_packets_with_reverse;
 #[cfg(feature = "stdio")]
 pub use stdio::Stdio;
+pub use trace::{TraceReader, TraceRecord, TraceRecorder, TraceWriter, TRACE_MAGIC, TRACE_VERSION};
@@ -0,0 +1,232 @@
+//! The RSP parser used by the gdb server doesn't know about the reverse execution packets `bs` and `bc`. This module
+//! sits between the connection and the parser: it answers those packets with the reverse execution support of
+//! [`GdbHandler`] and advertises them in the reply to `qSupported`, everything else goes to the parser untouched.
+use crate::gdbserver::GdbHandler;
+use ckb_vm::Memory;
+use gdb_remote_protocol::{
+    process_packets_from, Breakpoint, Error, Handler, MemoryRegion, ProcessType, StopReason, ThreadId, VCont,
+    VContFeature, Watchpoint,
+};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::io::{Read, Result as IoResult, Write};
+use std::rc::Rc;
+
+enum Chunk {
+    // Acknowledgements and interrupts.
+    Byte(u8),
+    Packet { payload: Vec<u8>, raw: Vec<u8> },
+}
+
+// Removes the first complete chunk from `buffer`.
+fn next_chunk(buffer: &mut Vec<u8>) -> Option<Chunk> {
+    match buffer.first() {
+        None => None,
+        Some(b'$') => {
+            // '#' is always escaped inside a packet, the first one marks the start of the checksum.
+            let end = buffer.iter().position(|b| *b == b'#')?;
+            if buffer.len() < end + 3 {
+                return None;
+            }
+            let raw: Vec<u8> = buffer.drain(..end + 3).collect();
+            let payload = raw[1..end].to_vec();
+            Some(Chunk::Packet { payload, raw })
+        }
+        Some(_) => Some(Chunk::Byte(buffer.remove(0))),
+    }
+}
+
+fn make_packet(payload: &[u8]) -> Vec<u8> {
+    let checksum = payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    let mut packet = Vec::with_capacity(payload.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(payload);
+    packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+    packet
+}
+
+fn format_stop(result: Result<Option<StopReason>, Error>) -> String {
+    match result {
+        Ok(Some(StopReason::Exited(_, code))) => format!("W{:02x}", code),
+        Ok(Some(StopReason::Signal(signal))) => format!("S{:02x}", signal),
+        Ok(Some(_)) => String::from("S05"),
+        // Tells gdb that there is no more history to go back to.
+        Ok(None) => String::from("T05replaylog:begin;"),
+        Err(Error::Error(code)) => format!("E{:02x}", code),
+        Err(_) => String::from("E01"),
+    }
+}
+
+struct ReverseReader<'h, 'a, R: Read, W: Write, M: Memory<REG = u64> + Default> {
+    reader: R,
+    writer: Rc<RefCell<W>>,
+    handler: &'h GdbHandler<'a, M>,
+    supported_requested: Rc<Cell<bool>>,
+    no_ack: bool,
+    pending: Vec<u8>,
+    ready: Vec<u8>,
+}
+
+impl<'h, 'a, R: Read, W: Write, M: Memory<REG = u64> + Default> ReverseReader<'h, 'a, R, W, M> {
+    fn reply(&mut self, payload: &[u8]) -> IoResult<()> {
+        let result = if payload == b"bs" {
+            self.handler.reverse_step()
+        } else {
+            self.handler.reverse_continue()
+        };
+        let mut writer = self.writer.borrow_mut();
+        if !self.no_ack {
+            writer.write_all(b"+")?;
+        }
+        writer.write_all(&make_packet(format_stop(result).as_bytes()))?;
+        writer.flush()
+    }
+}
+
+impl<'h, 'a, R: Read, W: Write, M: Memory<REG = u64> + Default> Read for ReverseReader<'h, 'a, R, W, M> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        while self.ready.is_empty() {
+            match next_chunk(&mut self.pending) {
+                Some(Chunk::Packet { payload, .. }) if payload == b"bs" || payload == b"bc" => self.reply(&payload)?,
+                Some(Chunk::Packet { payload, raw }) => {
+                    if payload.starts_with(b"qSupported") {
+                        self.supported_requested.set(true);
+                    }
+                    if payload == b"QStartNoAckMode" {
+                        self.no_ack = true;
+                    }
+                    self.ready.extend_from_slice(&raw);
+                }
+                Some(Chunk::Byte(b)) => self.ready.push(b),
+                None => {
+                    let mut data = [0u8; 4096];
+                    let n = self.reader.read(&mut data)?;
+                    if n == 0 {
+                        // Hand over whatever is left and let the parser deal with the closed connection.
+                        self.ready.append(&mut self.pending);
+                        if self.ready.is_empty() {
+                            return Ok(0);
+                        }
+                    }
+                    self.pending.extend_from_slice(&data[..n]);
+                }
+            }
+        }
+        let n = buf.len().min(self.ready.len());
+        buf[..n].copy_from_slice(&self.ready[..n]);
+        self.ready.drain(..n);
+        Ok(n)
+    }
+}
+
+struct ReverseWriter<W: Write> {
+    writer: Rc<RefCell<W>>,
+    supported_requested: Rc<Cell<bool>>,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Write for ReverseWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(chunk) = next_chunk(&mut self.pending) {
+            let mut writer = self.writer.borrow_mut();
+            match chunk {
+                Chunk::Byte(b) => writer.write_all(&[b])?,
+                Chunk::Packet { mut payload, .. } if self.supported_requested.get() => {
+                    self.supported_requested.set(false);
+                    payload.extend_from_slice(b";ReverseStep+;ReverseContinue+");
+                    writer.write_all(&make_packet(&payload))?;
+                }
+                Chunk::Packet { raw, .. } => writer.write_all(&raw)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+// The handler is shared with ReverseReader, while the parser wants to own one.
+struct SharedHandler<'h, 'a, M: Memory<REG = u64> + Default>(&'h GdbHandler<'a, M>);
+
+impl<'h, 'a, M: Memory<REG = u64> + Default> Handler for SharedHandler<'h, 'a, M> {
+    fn attached(&self, pid: Option<u64>) -> Result<ProcessType, Error> {
+        self.0.attached(pid)
+    }
+
+    fn halt_reason(&self) -> Result<StopReason, Error> {
+        self.0.halt_reason()
+    }
+
+    fn read_general_registers(&self) -> Result<Vec<u8>, Error> {
+        self.0.read_general_registers()
+    }
+
+    fn read_register(&self, register: u64) -> Result<Vec<u8>, Error> {
+        self.0.read_register(register)
+    }
+
+    fn write_register(&self, register: u64, contents: &[u8]) -> Result<(), Error> {
+        self.0.write_register(register, contents)
+    }
+
+    fn read_memory(&self, region: MemoryRegion) -> Result<Vec<u8>, Error> {
+        self.0.read_memory(region)
+    }
+
+    fn write_memory(&self, address: u64, bytes: &[u8]) -> Result<(), Error> {
+        self.0.write_memory(address, bytes)
+    }
+
+    fn query_supported_vcont(&self) -> Result<Cow<'static, [VContFeature]>, Error> {
+        self.0.query_supported_vcont()
+    }
+
+    fn vcont(&self, request: Vec<(VCont, Option<ThreadId>)>) -> Result<StopReason, Error> {
+        self.0.vcont(request)
+    }
+
+    fn insert_software_breakpoint(&self, breakpoint: Breakpoint) -> Result<(), Error> {
+        self.0.insert_software_breakpoint(breakpoint)
+    }
+
+    fn remove_software_breakpoint(&self, breakpoint: Breakpoint) -> Result<(), Error> {
+        self.0.remove_software_breakpoint(breakpoint)
+    }
+
+    fn insert_write_watchpoint(&self, watchpoint: Watchpoint) -> Result<(), Error> {
+        self.0.insert_write_watchpoint(watchpoint)
+    }
+
+    fn remove_write_watchpoint(&self, watchpoint: Watchpoint) -> Result<(), Error> {
+        self.0.remove_write_watchpoint(watchpoint)
+    }
+}
+
+/// Same as `gdb_remote_protocol::process_packets_from`, with the `bs` and `bc` packets served as well. The handler
+/// should be created with [`GdbHandler::new_reversible`].
+pub fn process_packets_with_reverse<R: Read, W: Write, M: Memory<REG = u64> + Default>(
+    reader: R,
+    writer: W,
+    handler: GdbHandler<M>,
+) {
+    let writer = Rc::new(RefCell::new(writer));
+    let supported_requested = Rc::new(Cell::new(false));
+    let reader = ReverseReader {
+        reader,
+        writer: writer.clone(),
+        handler: &handler,
+        supported_requested: supported_requested.clone(),
+        no_ack: false,
+        pending: vec![],
+        ready: vec![],
+    };
+    let writer = ReverseWriter {
+        writer,
+        supported_requested,
+        pending: vec![],
+    };
+    process_packets_from(reader, writer, SharedHandler(&handler));
+}
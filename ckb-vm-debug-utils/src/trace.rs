@@ -0,0 +1,234 @@
+//! A compact binary record of every executed instruction.
+//!
+//! A trace file starts with the 8 byte magic `CKBTRACE` followed by a little endian u32 format version. Each record
+//! then holds, in little endian:
+//!
+//! * pc: u64
+//! * decoded ckb-vm instruction: u64
+//! * cycles consumed once the instruction retired: u64
+//! * number of changed registers: u8, followed by (index: u8, new value: u64) pairs
+//! * number of memory writes: u8, followed by (address: u64, length: u8, bytes) entries
+//!
+//! Only writes performed by store instructions are recorded, memory changed by syscalls is not.
+use crate::disasm::disassemble;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ckb_vm::{
+    decoder::Decoder,
+    instructions::{extract_opcode, instruction_opcode_name, insts, Instruction, Stype},
+    registers::REGISTER_ABI_NAMES,
+    Error, Memory, Register, SupportMachine, RISCV_GENERAL_REGISTER_NUMBER,
+};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub const TRACE_MAGIC: &[u8; 8] = b"CKBTRACE";
+pub const TRACE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u64,
+    pub instruction: Instruction,
+    pub cycles: u64,
+    pub registers: Vec<(u8, u64)>,
+    pub memory_writes: Vec<(u64, Vec<u8>)>,
+}
+
+impl TraceRecord {
+    pub fn opcode_name(&self) -> &'static str {
+        instruction_opcode_name(extract_opcode(self.instruction))
+    }
+
+    pub fn writes_memory(&self, addr: u64) -> bool {
+        self.memory_writes.iter().any(|(start, data)| addr >= *start && addr < start + data.len() as u64)
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:08x} {:<28} cycles={}",
+            self.pc,
+            disassemble(self.instruction),
+            self.cycles
+        )?;
+        for (index, value) in &self.registers {
+            write!(f, " {}=0x{:x}", REGISTER_ABI_NAMES[*index as usize], value)?;
+        }
+        for (addr, data) in &self.memory_writes {
+            write!(f, " [0x{:x}]=", addr)?;
+            for b in data {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(TRACE_MAGIC)?;
+        writer.write_u32::<LittleEndian>(TRACE_VERSION)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        self.writer.write_u64::<LittleEndian>(record.pc)?;
+        self.writer.write_u64::<LittleEndian>(record.instruction)?;
+        self.writer.write_u64::<LittleEndian>(record.cycles)?;
+        self.writer.write_u8(record.registers.len() as u8)?;
+        for (index, value) in &record.registers {
+            self.writer.write_u8(*index)?;
+            self.writer.write_u64::<LittleEndian>(*value)?;
+        }
+        self.writer.write_u8(record.memory_writes.len() as u8)?;
+        for (addr, data) in &record.memory_writes {
+            self.writer.write_u64::<LittleEndian>(*addr)?;
+            self.writer.write_u8(data.len() as u8)?;
+            self.writer.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Iterates over the records of a trace file.
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ckb-vm trace file"));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != TRACE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported trace version {}", version),
+            ));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self, pc: u64) -> io::Result<TraceRecord> {
+        let instruction = self.reader.read_u64::<LittleEndian>()?;
+        let cycles = self.reader.read_u64::<LittleEndian>()?;
+        let register_count = self.reader.read_u8()?;
+        let mut registers = Vec::with_capacity(register_count as usize);
+        for _ in 0..register_count {
+            let index = self.reader.read_u8()?;
+            let value = self.reader.read_u64::<LittleEndian>()?;
+            registers.push((index, value));
+        }
+        let write_count = self.reader.read_u8()?;
+        let mut memory_writes = Vec::with_capacity(write_count as usize);
+        for _ in 0..write_count {
+            let addr = self.reader.read_u64::<LittleEndian>()?;
+            let mut data = vec![0u8; self.reader.read_u8()? as usize];
+            self.reader.read_exact(&mut data)?;
+            memory_writes.push((addr, data));
+        }
+        Ok(TraceRecord {
+            pc,
+            instruction,
+            cycles,
+            registers,
+            memory_writes,
+        })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A clean end of file can only happen on a record boundary.
+        let pc = match self.reader.read_u64::<LittleEndian>() {
+            Ok(pc) => pc,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(self.read_record(pc))
+    }
+}
+
+/// Captures the machine state around each step and writes the difference as a trace record.
+pub struct TraceRecorder<W: Write> {
+    writer: TraceWriter<W>,
+    pending: Option<(TraceRecord, [u64; RISCV_GENERAL_REGISTER_NUMBER], Option<(u64, u64)>)>,
+}
+
+impl<W: Write> TraceRecorder<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        Ok(Self {
+            writer: TraceWriter::new(writer)?,
+            pending: None,
+        })
+    }
+
+    /// Must be called right before the machine executes the instruction at the current pc.
+    pub fn before_step<Mac: SupportMachine>(&mut self, machine: &mut Mac, decoder: &mut Decoder) -> Result<(), Error> {
+        let pc = machine.pc().to_u64();
+        let instruction = decoder.decode(machine.memory_mut(), pc)?;
+        let mut registers = [0u64; RISCV_GENERAL_REGISTER_NUMBER];
+        for (i, value) in machine.registers().iter().enumerate() {
+            registers[i] = value.to_u64();
+        }
+        let store_size = match extract_opcode(instruction) {
+            insts::OP_SB => Some(1),
+            insts::OP_SH => Some(2),
+            insts::OP_SW => Some(4),
+            insts::OP_SD => Some(8),
+            _ => None,
+        };
+        let store = store_size.map(|size| {
+            let i = Stype(instruction);
+            (registers[i.rs1()].wrapping_add(i.immediate_s() as i64 as u64), size)
+        });
+        let record = TraceRecord {
+            pc,
+            instruction,
+            ..Default::default()
+        };
+        self.pending = Some((record, registers, store));
+        Ok(())
+    }
+
+    /// Must be called once the instruction prepared by `before_step` has been executed.
+    pub fn after_step<Mac: SupportMachine>(&mut self, machine: &mut Mac) -> Result<(), Error> {
+        let (mut record, registers, store) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        record.cycles = machine.cycles();
+        for (i, value) in machine.registers().iter().enumerate() {
+            if value.to_u64() != registers[i] {
+                record.registers.push((i as u8, value.to_u64()));
+            }
+        }
+        if let Some((addr, size)) = store {
+            let mut data = Vec::with_capacity(size as usize);
+            for a in addr..addr + size {
+                data.push(machine.memory_mut().load8(&Mac::REG::from_u64(a))?.to_u8());
+            }
+            record.memory_writes.push((addr, data));
+        }
+        self.writer.write(&record)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,104 @@
+use byteorder::{ByteOrder, LittleEndian};
+use ckb_vm::{
+    CoreMachine, DefaultCoreMachine, DefaultMachine, DefaultMachineBuilder, Memory, SparseMemory, SupportMachine,
+    ISA_IMC,
+};
+use ckb_vm_debug_utils::GdbHandler;
+use gdb_remote_protocol::{Breakpoint, Handler, StopReason, VCont};
+
+type Machine<'a> = DefaultMachine<'a, DefaultCoreMachine<u64, SparseMemory<u64>>>;
+
+// loop:
+//     addi a0, a0, 1
+//     j loop
+const PROGRAM: [u8; 8] = [0x13, 0x05, 0x15, 0x00, 0x6f, 0xf0, 0xdf, 0xff];
+const A0: u64 = 10;
+const PC: u64 = 32;
+
+fn new_machine<'a>() -> Machine<'a> {
+    let machine_core = DefaultCoreMachine::<u64, SparseMemory<u64>>::new(ISA_IMC, 1, u64::max_value());
+    let mut machine = DefaultMachineBuilder::new(machine_core).build();
+    machine.memory_mut().store_bytes(0, &PROGRAM).unwrap();
+    machine
+}
+
+fn new_handler<'a>() -> GdbHandler<'a, SparseMemory<u64>> {
+    let mut machine = new_machine();
+    machine.set_running(true);
+    GdbHandler::new_reversible(machine, Box::new(new_machine))
+}
+
+fn register(handler: &GdbHandler<SparseMemory<u64>>, register: u64) -> u64 {
+    LittleEndian::read_u64(&handler.read_register(register).unwrap())
+}
+
+fn step(handler: &GdbHandler<SparseMemory<u64>>, vcont: VCont) {
+    assert!(matches!(
+        handler.vcont(vec![(vcont, None)]).unwrap(),
+        StopReason::Signal(5)
+    ));
+}
+
+#[test]
+pub fn test_reverse_step() {
+    let handler = new_handler();
+    assert!(handler.reverse_step().unwrap().is_none());
+    for _ in 0..5 {
+        step(&handler, VCont::Step);
+    }
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (3, 4));
+
+    assert!(matches!(handler.reverse_step().unwrap(), Some(StopReason::Signal(5))));
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (2, 0));
+    assert!(matches!(handler.reverse_step().unwrap(), Some(StopReason::Signal(5))));
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (2, 4));
+
+    // Running forward again after going back reaches the same states.
+    step(&handler, VCont::Step);
+    step(&handler, VCont::Step);
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (3, 4));
+}
+
+#[test]
+pub fn test_reverse_continue() {
+    let handler = new_handler();
+    handler
+        .insert_software_breakpoint(Breakpoint {
+            addr: 4,
+            kind: 4,
+            conditions: None,
+            commands: None,
+        })
+        .unwrap();
+    step(&handler, VCont::Continue);
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (1, 4));
+    step(&handler, VCont::Continue);
+    step(&handler, VCont::Continue);
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (3, 4));
+
+    assert!(matches!(
+        handler.reverse_continue().unwrap(),
+        Some(StopReason::Signal(5))
+    ));
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (2, 4));
+    assert!(matches!(
+        handler.reverse_continue().unwrap(),
+        Some(StopReason::Signal(5))
+    ));
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (1, 4));
+    // No breakpoint is hit before the first one, it stops at the start of the program.
+    assert!(handler.reverse_continue().unwrap().is_none());
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (0, 0));
+
+    step(&handler, VCont::Continue);
+    assert_eq!((register(&handler, A0), register(&handler, PC)), (1, 4));
+}
+
+#[test]
+pub fn test_reverse_without_factory() {
+    let mut machine = new_machine();
+    machine.set_running(true);
+    let handler = GdbHandler::new(machine);
+    assert!(handler.reverse_step().is_err());
+    assert!(handler.reverse_continue().is_err());
+}
@@ -0,0 +1,92 @@
+use ckb_vm::{
+    decoder::build_decoder,
+    instructions::{extract_opcode, insts, Instruction},
+    CoreMachine, DefaultCoreMachine, DefaultMachineBuilder, Memory, SparseMemory, ISA_IMC,
+};
+use ckb_vm_debug_utils::{TraceReader, TraceRecord, TraceRecorder, TraceWriter, TRACE_MAGIC};
+
+// addi a0, zero, 5
+// sd a0, 0x100(zero)
+const PROGRAM: [u8; 8] = [0x13, 0x05, 0x50, 0x00, 0x23, 0x30, 0xa0, 0x10];
+
+fn one_cycle(_: Instruction) -> u64 {
+    1
+}
+
+fn read_all(data: &[u8]) -> Vec<TraceRecord> {
+    TraceReader::new(data).unwrap().collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+#[test]
+pub fn test_write_read() {
+    let records = vec![
+        TraceRecord {
+            pc: 0x1000,
+            instruction: 0x1234_5678_9abc_def0,
+            cycles: 1,
+            registers: vec![(10, u64::max_value()), (31, 0)],
+            memory_writes: vec![],
+        },
+        TraceRecord {
+            pc: 0x1004,
+            instruction: 42,
+            cycles: 2,
+            registers: vec![],
+            memory_writes: vec![(0x2000, vec![1, 2, 3, 4, 5, 6, 7, 8]), (0x3000, vec![9])],
+        },
+        TraceRecord::default(),
+    ];
+    let mut data = vec![];
+    let mut writer = TraceWriter::new(&mut data).unwrap();
+    for record in &records {
+        writer.write(record).unwrap();
+    }
+    writer.flush().unwrap();
+    assert_eq!(&data[..8], TRACE_MAGIC);
+    assert_eq!(read_all(&data), records);
+
+    // A file ending in the middle of a record is an error, not a shorter trace.
+    let mut reader = TraceReader::new(&data[..data.len() - 1]).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_err());
+
+    assert!(TraceReader::new(&b"CKBTRAC"[..]).is_err());
+    assert!(TraceReader::new(&b"NOTTRACE\x01\x00\x00\x00"[..]).is_err());
+    assert!(TraceReader::new(&b"CKBTRACE\x02\x00\x00\x00"[..]).is_err());
+    assert!(read_all(b"CKBTRACE\x01\x00\x00\x00").is_empty());
+}
+
+#[test]
+pub fn test_recorder() {
+    let machine_core = DefaultCoreMachine::<u64, SparseMemory<u64>>::new(ISA_IMC, 1, u64::max_value());
+    let mut machine = DefaultMachineBuilder::new(machine_core).instruction_cycle_func(&one_cycle).build();
+    machine.memory_mut().store_bytes(0, &PROGRAM).unwrap();
+    let mut decoder = build_decoder::<u64>(ISA_IMC, 1);
+
+    let mut data = vec![];
+    let mut recorder = TraceRecorder::new(&mut data).unwrap();
+    for _ in 0..2 {
+        recorder.before_step(&mut machine, &mut decoder).unwrap();
+        machine.step(&mut decoder).unwrap();
+        recorder.after_step(&mut machine).unwrap();
+    }
+    recorder.flush().unwrap();
+    drop(recorder);
+
+    let records = read_all(&data);
+    assert_eq!(records.len(), 2);
+    assert_eq!((records[0].pc, records[0].cycles), (0, 1));
+    assert_eq!(extract_opcode(records[0].instruction), insts::OP_ADDI);
+    assert_eq!(records[0].registers, vec![(10, 5)]);
+    assert!(records[0].memory_writes.is_empty());
+    assert!(records[0].to_string().contains(" a0=0x5"));
+
+    assert_eq!((records[1].pc, records[1].cycles), (4, 2));
+    assert_eq!(extract_opcode(records[1].instruction), insts::OP_SD);
+    assert!(records[1].registers.is_empty());
+    assert_eq!(records[1].memory_writes, vec![(0x100, vec![5, 0, 0, 0, 0, 0, 0, 0])]);
+    assert!(records[1].writes_memory(0x107));
+    assert!(!records[1].writes_memory(0x108));
+    assert!(records[1].to_string().ends_with(" [0x100]=0500000000000000"));
+}
@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+
+use ckb_vm::Bytes;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct FileCoverage {
+    // line -> hits
+    lines: BTreeMap<u32, u64>,
+    // function name -> (line, hits)
+    functions: BTreeMap<String, (u32, u64)>,
+}
+
+/// Line and function coverage of one or more runs, keyed by source file, in the shape of an lcov tracefile.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    files: BTreeMap<String, FileCoverage>,
+}
+
+// Start address of every instruction in the executable sections of the ELF.
+fn instruction_addrs(elf: &goblin::elf::Elf, program: &[u8]) -> Vec<u64> {
+    let mut addrs = vec![];
+    for section in &elf.section_headers {
+        if section.sh_flags & goblin::elf::section_header::SHF_EXECINSTR as u64 == 0
+            || section.sh_type == goblin::elf::section_header::SHT_NOBITS
+        {
+            continue;
+        }
+        let data = match program.get(section.sh_offset as usize..(section.sh_offset + section.sh_size) as usize) {
+            Some(data) => data,
+            None => continue,
+        };
+        let mut offset = 0;
+        while offset + 2 <= data.len() {
+            addrs.push(section.sh_addr + offset as u64);
+            // The two lowest bits are 0b11 for 32 bit instructions, anything else is a compressed one.
+            offset += if data[offset] & 0b11 == 0b11 { 4 } else { 2 };
+        }
+    }
+    addrs
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the pcs executed by one run of `program`, as returned by `Profile::pc_counts`. Every line the program has
+    /// code for is recorded, so lines that were never executed show up with zero hits. The hits of a line are those of
+    /// its most executed instruction.
+    pub fn add_run(
+        &mut self,
+        program: &Bytes,
+        pc_counts: &HashMap<u64, u64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let object = object::File::parse(&program)?;
+        let ctx = addr2line::Context::new(&object)?;
+        let elf = goblin::elf::Elf::parse(&program)?;
+        let mut run = Coverage::new();
+        for addr in instruction_addrs(&elf, program) {
+            if let Some(loc) = ctx.find_location(addr)? {
+                if let (Some(file), Some(line)) = (loc.file, loc.line) {
+                    let hits = pc_counts.get(&addr).cloned().unwrap_or(0);
+                    let file = run.files.entry(file.to_string()).or_default();
+                    let entry = file.lines.entry(line).or_insert(0);
+                    *entry = (*entry).max(hits);
+                }
+            }
+        }
+        for sym in &elf.syms {
+            if !sym.is_function() || sym.st_value == 0 {
+                continue;
+            }
+            let name = match elf.strtab.get(sym.st_name) {
+                Some(Ok(name)) => addr2line::demangle_auto(Cow::from(name), None).to_string(),
+                _ => continue,
+            };
+            if let Some(loc) = ctx.find_location(sym.st_value)? {
+                if let (Some(file), Some(line)) = (loc.file, loc.line) {
+                    let hits = pc_counts.get(&sym.st_value).cloned().unwrap_or(0);
+                    run.files.entry(file.to_string()).or_default().functions.insert(name, (line, hits));
+                }
+            }
+        }
+        self.merge(&run);
+        Ok(())
+    }
+
+    /// Adds the hits of `other` to this coverage.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (path, other_file) in &other.files {
+            let file = self.files.entry(path.clone()).or_default();
+            for (line, hits) in &other_file.lines {
+                *file.lines.entry(*line).or_insert(0) += hits;
+            }
+            for (name, (line, hits)) in &other_file.functions {
+                file.functions.entry(name.clone()).or_insert((*line, 0)).1 += hits;
+            }
+        }
+    }
+
+    /// Parses an lcov tracefile. Records other than line and function coverage are ignored.
+    pub fn read_lcov(reader: impl BufRead) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut coverage = Coverage::new();
+        let mut current: Option<String> = None;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let malformed = || format!("malformed lcov record at line {}: {}", index + 1, line);
+            let (key, value) = match line.trim().split_once(':') {
+                Some(kv) => kv,
+                None => {
+                    if line.trim() == "end_of_record" {
+                        current = None;
+                    }
+                    continue;
+                }
+            };
+            if key == "SF" {
+                coverage.files.entry(value.to_string()).or_default();
+                current = Some(value.to_string());
+                continue;
+            }
+            let file = match (&current, key) {
+                (Some(path), "DA" | "FN" | "FNDA") => coverage.files.get_mut(path).unwrap(),
+                (None, "DA" | "FN" | "FNDA") => return Err(malformed().into()),
+                _ => continue,
+            };
+            let (first, second) = value.split_once(',').ok_or_else(malformed)?;
+            match key {
+                "DA" => {
+                    let line: u32 = first.parse().map_err(|_| malformed())?;
+                    // A third field holds a checksum of the line, which isn't needed here.
+                    let hits: u64 = second.split(',').next().unwrap().parse().map_err(|_| malformed())?;
+                    *file.lines.entry(line).or_insert(0) += hits;
+                }
+                "FN" => {
+                    let line: u32 = first.parse().map_err(|_| malformed())?;
+                    file.functions.entry(second.to_string()).or_insert((line, 0)).0 = line;
+                }
+                _ => {
+                    let hits: u64 = first.parse().map_err(|_| malformed())?;
+                    file.functions.entry(second.to_string()).or_insert((0, 0)).1 += hits;
+                }
+            }
+        }
+        Ok(coverage)
+    }
+
+    pub fn write_lcov(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        for (path, file) in &self.files {
+            writeln!(writer, "TN:")?;
+            writeln!(writer, "SF:{}", path)?;
+            for (name, (line, _)) in &file.functions {
+                writeln!(writer, "FN:{},{}", line, name)?;
+            }
+            for (name, (_, hits)) in &file.functions {
+                writeln!(writer, "FNDA:{},{}", hits, name)?;
+            }
+            writeln!(writer, "FNF:{}", file.functions.len())?;
+            writeln!(
+                writer,
+                "FNH:{}",
+                file.functions.values().filter(|(_, hits)| *hits > 0).count()
+            )?;
+            for (line, hits) in &file.lines {
+                writeln!(writer, "DA:{},{}", line, hits)?;
+            }
+            writeln!(writer, "LF:{}", file.lines.len())?;
+            writeln!(writer, "LH:{}", file.lines.values().filter(|hits| **hits > 0).count())?;
+            writeln!(writer, "end_of_record")?;
+        }
+        writer.flush()
+    }
+}
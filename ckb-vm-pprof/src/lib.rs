@@ -14,7 +14,9 @@ use ckb_vm::{
 };
 
 mod cost_model;
+mod coverage;
 pub use cost_model::instruction_cycles;
+pub use coverage::Coverage;
 
 type Addr2LineEndianReader = addr2line::gimli::EndianReader<addr2line::gimli::RunTimeEndian, Rc<[u8]>>;
 type Addr2LineContext = addr2line::Context<Addr2LineEndianReader>;
@@ -131,6 +133,7 @@ pub struct Profile {
     cache_fun: HashMap<u64, String>,
     sbrk_addr: u64,
     sbrk_heap: u64,
+    pc_counts: Option<HashMap<u64, u64>>,
 }
 
 impl Profile {
@@ -148,9 +151,54 @@ impl Profile {
             cache_fun: goblin_fun(&elf),
             sbrk_addr: goblin_get_sym(&elf, "_sbrk"),
             sbrk_heap: goblin_get_sym(&elf, "_end"),
+            pc_counts: None,
         })
     }
 
+    /// Starts counting how many times each pc is executed.
+    pub fn enable_coverage(&mut self) {
+        self.pc_counts.get_or_insert_with(HashMap::new);
+    }
+
+    /// Execution count of each pc, available once `enable_coverage` has been called.
+    pub fn pc_counts(&self) -> Option<&HashMap<u64, u64>> {
+        self.pc_counts.as_ref()
+    }
+
+    /// Cycles spent in each function itself, callees not included, summed over every stack it was called from. Keys
+    /// are named like the frames of the flamegraph, `file:function`.
+    pub fn function_cycles(&mut self) -> HashMap<String, u64> {
+        let mut cycles = HashMap::new();
+        let mut nodes = vec![self.trie_root.clone()];
+        while let Some(node) = nodes.pop() {
+            let name = self.get_tag(node.borrow().addr).simple();
+            *cycles.entry(name).or_insert(0) += node.borrow().cycles;
+            nodes.extend(node.borrow().childs.iter().cloned());
+        }
+        cycles
+    }
+
+    /// Returns the entry address of the function named `name`, if the ELF has such a symbol.
+    pub fn get_func_addr(&self, name: &str) -> Option<u64> {
+        self.cache_fun.iter().find(|(_, v)| v.as_str() == name).map(|(k, _)| *k)
+    }
+
+    /// Number of frames between the current function and the program entry.
+    pub fn stack_depth(&self) -> usize {
+        let mut depth = 0;
+        let mut frame = self.trie_node.clone();
+        loop {
+            let parent = frame.borrow().parent.clone();
+            if let Some(p) = parent {
+                depth += 1;
+                frame = p;
+            } else {
+                break;
+            }
+        }
+        depth
+    }
+
     pub fn get_tag(&mut self, addr: u64) -> Tags {
         if let Some(data) = self.cache_tag.get(&addr) {
             return data.clone();
@@ -214,6 +262,9 @@ impl Profile {
                 sp, self.sbrk_heap
             )));
         }
+        if let Some(pc_counts) = &mut self.pc_counts {
+            *pc_counts.entry(pc).or_insert(0) += 1;
+        }
         let inst = decoder.decode(machine.memory_mut(), pc)?;
         let opcode = ckb_vm::instructions::extract_opcode(inst);
         let cycles = machine.instruction_cycle_func()(inst);
@@ -388,6 +439,19 @@ impl<'a, R: Register, M: Memory<REG = R>, Inner: SupportMachine<REG = R, MEM = M
         self.machine.load_program(program, args)
     }
 
+    /// Executes a single instruction while keeping the call trie up to date.
+    pub fn step(&mut self, decoder: &mut Decoder) -> Result<(), Error> {
+        if self.machine.reset_signal() {
+            decoder.reset_instructions_cache();
+            let mut profile = Profile::new(&self.machine.code()).map_err(|e| Error::External(e.to_string()))?;
+            // Coverage counts keep accumulating across exec, so the pcs run before it are not lost.
+            profile.pc_counts = self.profile.pc_counts.take();
+            self.profile = profile;
+        }
+        self.profile.step(&mut self.machine, decoder)?;
+        self.machine.step(decoder)
+    }
+
     pub fn run(&mut self) -> Result<i8, Error> {
         if self.isa() & ISA_MOP != 0 && self.version() == VERSION0 {
             return Err(Error::InvalidVersion);
@@ -395,12 +459,7 @@ impl<'a, R: Register, M: Memory<REG = R>, Inner: SupportMachine<REG = R, MEM = M
         let mut decoder = build_decoder::<Inner::REG>(self.isa(), self.version());
         self.machine.set_running(true);
         while self.machine.running() {
-            if self.machine.reset_signal() {
-                decoder.reset_instructions_cache();
-                self.profile = Profile::new(&self.machine.code()).unwrap();
-            }
-            self.profile.step(&mut self.machine, &mut decoder)?;
-            self.machine.step(&mut decoder)?;
+            self.step(&mut decoder)?;
         }
         Ok(self.machine.exit_code())
     }
@@ -0,0 +1,108 @@
+use ckb_vm::{Bytes, DefaultCoreMachine, DefaultMachineBuilder, SparseMemory, WXorXMemory, ISA_B, ISA_IMC, ISA_MOP};
+use ckb_vm_pprof::{instruction_cycles, Coverage, PProfMachine, Profile};
+use std::collections::HashMap;
+
+fn fib() -> Bytes {
+    Bytes::from(std::fs::read("res/fib").unwrap())
+}
+
+fn run_fib() -> HashMap<u64, u64> {
+    let program = fib();
+    let core = DefaultCoreMachine::<u64, WXorXMemory<SparseMemory<u64>>>::new(
+        ISA_IMC | ISA_B | ISA_MOP,
+        ckb_vm::machine::VERSION1,
+        1 << 32,
+    );
+    let machine = DefaultMachineBuilder::new(core).instruction_cycle_func(&instruction_cycles).build();
+    let mut profile = Profile::new(&program).unwrap();
+    profile.enable_coverage();
+    let mut machine = PProfMachine::new(machine, profile);
+    machine.load_program(&program, &[Bytes::from("fib")]).unwrap();
+    assert_eq!(machine.run().unwrap(), 0);
+    machine.profile.pc_counts().unwrap().clone()
+}
+
+fn lcov(coverage: &Coverage) -> String {
+    let mut data = vec![];
+    coverage.write_lcov(&mut data).unwrap();
+    String::from_utf8(data).unwrap()
+}
+
+// Hits recorded in the lcov text for the line holding `fib(5) != 5` and for the function `fib`.
+fn fib_hits(lcov: &str) -> (u64, u64) {
+    let main_line = lcov
+        .lines()
+        .filter_map(|l| l.strip_prefix("DA:"))
+        .find(|l| l.starts_with("10,"))
+        .map(|l| l.split(',').nth(1).unwrap().parse().unwrap())
+        .unwrap();
+    let fib_calls = lcov
+        .lines()
+        .filter_map(|l| l.strip_prefix("FNDA:"))
+        .find(|l| l.ends_with(",fib"))
+        .map(|l| l.split(',').next().unwrap().parse().unwrap())
+        .unwrap();
+    (main_line, fib_calls)
+}
+
+#[test]
+pub fn test_add_run() {
+    let mut coverage = Coverage::new();
+    coverage.add_run(&fib(), &run_fib()).unwrap();
+    let text = lcov(&coverage);
+    assert!(text.lines().any(|l| l.starts_with("SF:") && l.ends_with("fib.c")));
+    // fib(5) calls fib 15 times in all.
+    assert_eq!(fib_hits(&text), (1, 15));
+
+    // A program that never ran still lists its lines, with no hits.
+    let mut empty = Coverage::new();
+    empty.add_run(&fib(), &HashMap::new()).unwrap();
+    assert_eq!(fib_hits(&lcov(&empty)), (0, 0));
+}
+
+#[test]
+pub fn test_merge() {
+    let mut run = Coverage::new();
+    run.add_run(&fib(), &run_fib()).unwrap();
+    let mut coverage = run.clone();
+    coverage.merge(&run);
+    assert_eq!(fib_hits(&lcov(&coverage)), (2, 30));
+
+    let mut twice = Coverage::new();
+    twice.add_run(&fib(), &run_fib()).unwrap();
+    twice.add_run(&fib(), &run_fib()).unwrap();
+    assert_eq!(twice, coverage);
+
+    let mut unchanged = run.clone();
+    unchanged.merge(&Coverage::new());
+    assert_eq!(unchanged, run);
+}
+
+#[test]
+pub fn test_lcov_round_trip() {
+    let mut coverage = Coverage::new();
+    coverage.add_run(&fib(), &run_fib()).unwrap();
+    let text = lcov(&coverage);
+    let read = Coverage::read_lcov(text.as_bytes()).unwrap();
+    assert_eq!(read, coverage);
+    assert_eq!(lcov(&read), text);
+}
+
+#[test]
+pub fn test_read_lcov() {
+    let text = "TN:\nSF:a.c\nFN:1,main\nFNDA:2,main\nDA:1,2\nDA:2,0,checksum\nBRDA:2,0,0,1\nend_of_record\n";
+    let coverage = Coverage::read_lcov(text.as_bytes()).unwrap();
+    assert_eq!(
+        lcov(&coverage),
+        "TN:\nSF:a.c\nFN:1,main\nFNDA:2,main\nFNF:1\nFNH:1\nDA:1,2\nDA:2,0\nLF:2\nLH:1\nend_of_record\n"
+    );
+
+    // A file listed twice is merged.
+    let twice = format!("{}{}", text, text);
+    let coverage = Coverage::read_lcov(twice.as_bytes()).unwrap();
+    assert!(lcov(&coverage).contains("FNDA:4,main\n"));
+    assert!(lcov(&coverage).contains("DA:1,4\n"));
+
+    assert!(Coverage::read_lcov("DA:1,2\n".as_bytes()).is_err());
+    assert!(Coverage::read_lcov("SF:a.c\nDA:x,2\n".as_bytes()).is_err());
+}